@@ -1,15 +1,32 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
-use winit::window::Window;
+use common::{params::Params, shader::{self, ErrorOverlay, ShaderError, ShaderFile}, target::{OffscreenTarget, RenderTarget}, time::Time};
+use gpu_layout::UniformLayout;
+use winit::{dpi::PhysicalSize, event::{ElementState, KeyEvent, WindowEvent}, keyboard::ModifiersState, window::Window};
 use crate::uniform;
 
+// The shader and everything it includes, the fallback when the files on disk are broken
+const SHADERS: &[(&str, &str)] = &[
+    (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader.wgsl"), include_str!("shader.wgsl")),
+    (concat!(env!("CARGO_MANIFEST_DIR"), "/../shaders/noise.wgsl"), include_str!("../../shaders/noise.wgsl"))
+];
+
+// Bind group of the shader's `Params` struct
+const PARAMS_GROUP: u32 = 1;
+
 pub struct AppState {
-    pub window: Arc<Window>,
-    surface: wgpu::Surface<'static>,
+    target: RenderTarget,
+    size: PhysicalSize<u32>,
     device: wgpu::Device,
     queue: wgpu::Queue,
+    shader: ShaderFile,
+    shader_error: Option<ShaderError>,
+    error_overlay: ErrorOverlay,
+    render_pipeline_layout: wgpu::PipelineLayout,
     render_pipeline: wgpu::RenderPipeline,
-    uniform: uniform::Uniform
+    uniform: uniform::Uniform,
+    params: Params,
+    modifiers: ModifiersState
 }
 
 impl AppState {
@@ -26,8 +43,8 @@ impl AppState {
 
         let surface_caps = surface.get_capabilities(&adapter);
         let config = wgpu::SurfaceConfiguration {
-            width: size.width,
-            height: size.height,
+            width: size.width.max(1),
+            height: size.height.max(1),
             format: surface_caps.formats[0],
             alpha_mode: surface_caps.alpha_modes[0],
             present_mode: surface_caps.present_modes[0],
@@ -37,74 +54,172 @@ impl AppState {
         };
         surface.configure(&device, &config);
 
-        let uniform = uniform::Uniform::new(&device);
+        let title = window.title();
+        let pixel_ratio = window.scale_factor() as f32;
+        let target = RenderTarget::Window { window, title, surface, config };
+        let mut app_state = Self::with_target(device, queue, target, size);
+        app_state.uniform.set_pixel_ratio(pixel_ratio);
+        app_state
+    }
 
-        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into())
-        });
+    pub async fn new_headless(width: u32, height: u32, software: bool) -> Self {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = match instance.request_adapter(&wgpu::RequestAdapterOptions{
+            force_fallback_adapter: software,
+            ..Default::default()
+        }).await {
+            Some(adapter) => adapter,
+            None => instance.request_adapter(&wgpu::RequestAdapterOptions{
+                force_fallback_adapter: true,
+                ..Default::default()
+            }).await.unwrap()
+        };
+
+        let (device, queue) = adapter.request_device(&wgpu::DeviceDescriptor::default(), None).await.unwrap();
+
+        let target = RenderTarget::Offscreen(OffscreenTarget::new(&device, width, height));
+        Self::with_target(device, queue, target, PhysicalSize::new(width, height))
+    }
+
+    fn with_target(device: wgpu::Device, queue: wgpu::Queue, target: RenderTarget, size: PhysicalSize<u32>) -> Self {
+        device.on_uncaptured_error(Box::new(|error| eprintln!("Uncaptured wgpu error:\n{error}")));
+
+        let uniform = uniform::Uniform::new(&device, size.width, size.height);
+
+        let mut shader = ShaderFile::new(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader.wgsl")), SHADERS)
+            .include_dir(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../shaders")))
+            .define("ANIMATED_NOISE");
+
+        let mut params = Params::new(&device);
+        params.set_file(shader.path.with_extension("params"));
 
         let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             bind_group_layouts: &[
-                &uniform.bind_group_layout
+                &uniform.bind_group_layout,
+                &params.bind_group_layout
             ],
             ..Default::default()
         });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader_module,
-                entry_point: "vs_main",
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                buffers: &[]
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader_module,
-                entry_point: "fs_main",
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                targets: &[
-                    Some(wgpu::ColorTargetState {
-                        format: config.format,
-                        blend: Some(wgpu::BlendState::REPLACE),
-                        write_mask: wgpu::ColorWrites::all()
-                    })
-                ]
-            }),
-            primitive: wgpu::PrimitiveState { 
-                topology: wgpu::PrimitiveTopology::TriangleList, 
-                strip_index_format: None, 
-                front_face: wgpu::FrontFace::Ccw, 
-                cull_mode: None, 
-                unclipped_depth: false, 
-                polygon_mode: wgpu::PolygonMode::Fill, 
-                conservative: false 
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None
-        });
-
-
+        let create_pipeline = |shader_module: &wgpu::ShaderModule| {
+            create_render_pipeline(&device, &render_pipeline_layout, shader_module, target.format())
+        };
+        let ((render_pipeline, params_layout), shader_error) = match shader.load().and_then(|source| shader::compile(&device, &source, &uniform::UniformRaw::layout(), PARAMS_GROUP, create_pipeline)) {
+            Ok(compiled) => (compiled, None),
+            Err(error) => {
+                eprintln!("{error}\nUsing the embedded shader until {} is fixed", shader.path.display());
+                let compiled = shader.load_embedded()
+                    .and_then(|source| shader::compile(&device, &source, &uniform::UniformRaw::layout(), PARAMS_GROUP, create_pipeline))
+                    .unwrap_or_else(|error| panic!("the embedded shader is broken:\n{error}"));
+                (compiled, Some(error))
+            }
+        };
+        params.set_layout(&device, params_layout);
+        target.show_error(shader_error.as_ref().map(|error| error.summary.as_str()));
+        let error_overlay = ErrorOverlay::new(&device, target.format());
 
         AppState {
-            window,
-            surface,
+            target,
+            size,
             device,
             queue,
+            shader,
+            shader_error,
+            error_overlay,
+            render_pipeline_layout,
             render_pipeline,
-            uniform
+            uniform,
+            params,
+            modifiers: ModifiersState::default()
+        }
+    }
+
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        self.size = new_size;
+        if new_size.width == 0 || new_size.height == 0 {
+            return;
+        }
+
+        self.target.resize(&self.device, new_size.width, new_size.height);
+        self.uniform.resize(new_size.width, new_size.height);
+    }
+
+    pub fn reconfigure(&mut self) {
+        self.resize(self.size);
+    }
+
+    // Where the tweaked params are saved, next to the shader by default
+    pub fn set_params_file(&mut self, path: PathBuf) {
+        self.params.set_file(path);
+    }
+
+    pub fn set_time(&mut self, time: Time) {
+        self.uniform.set_time(time);
+    }
+
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                event: KeyEvent { state: ElementState::Pressed, logical_key, .. },
+                ..
+            } => self.uniform.time_mut().handle_key(logical_key) || self.params.handle_key(logical_key, self.modifiers.shift_key()),
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+                false
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                self.uniform.set_mouse(position.x as f32, position.y as f32);
+                false
+            },
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.uniform.set_pixel_ratio(*scale_factor as f32);
+                false
+            },
+            _ => false
         }
     }
 
     pub fn update(&mut self) {
+        self.reload_shader();
         self.uniform.update(&self.queue);
+        self.params.update(&self.queue);
+    }
+
+    fn reload_shader(&mut self) {
+        if !self.shader.changed() {
+            return;
+        }
+
+        let format = self.target.format();
+        let result = self.shader.load().and_then(|source| shader::compile(&self.device, &source, &uniform::UniformRaw::layout(), PARAMS_GROUP, |shader_module| {
+            create_render_pipeline(&self.device, &self.render_pipeline_layout, shader_module, format)
+        }));
+
+        match result {
+            Ok((render_pipeline, params_layout)) => {
+                self.render_pipeline = render_pipeline;
+                self.params.set_layout(&self.device, params_layout);
+                self.set_shader_error(None);
+                println!("Reloaded {}", self.shader.path.display());
+            },
+            Err(error) => {
+                eprintln!("{error}\nKeeping the previous pipeline until {} is fixed", self.shader.path.display());
+                self.set_shader_error(Some(error));
+            }
+        }
+    }
+
+    fn set_shader_error(&mut self, error: Option<ShaderError>) {
+        self.target.show_error(error.as_ref().map(|error| error.summary.as_str()));
+        self.shader_error = error;
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let texture = self.surface.get_current_texture()?;
-        let view = texture.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        if self.size.width == 0 || self.size.height == 0 {
+            return Ok(());
+        }
+
+        let frame = self.target.acquire()?;
 
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
         {
@@ -113,7 +228,7 @@ impl AppState {
                 color_attachments: &[
                     Some(
                         wgpu::RenderPassColorAttachment {
-                            view: &view,
+                            view: &frame.view,
                             resolve_target: None,
                             ops: wgpu::Operations{
                                 load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
@@ -128,11 +243,59 @@ impl AppState {
             });
             render_pass.set_pipeline(&self.render_pipeline);
             render_pass.set_bind_group(0, &self.uniform.bind_group, &[]);
+            render_pass.set_bind_group(PARAMS_GROUP, &self.params.bind_group, &[]);
             render_pass.draw(0..6, 0..1);
         }
+        if self.shader_error.is_some() {
+            self.error_overlay.draw(&mut encoder, &frame.view);
+        }
         self.queue.submit(std::iter::once(encoder.finish()));
-        texture.present();
+        frame.present();
 
         Ok(())
     }
+
+    pub fn capture(&self) -> Option<image::RgbaImage> {
+        match &self.target {
+            RenderTarget::Offscreen(target) => Some(target.read_image(&self.device, &self.queue)),
+            RenderTarget::Window { .. } => None
+        }
+    }
+}
+
+fn create_render_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, shader_module: &wgpu::ShaderModule, format: wgpu::TextureFormat) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader_module,
+            entry_point: "vs_main",
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            buffers: &[]
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader_module,
+            entry_point: "fs_main",
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::all()
+                })
+            ]
+        }),
+        primitive: wgpu::PrimitiveState { 
+            topology: wgpu::PrimitiveTopology::TriangleList, 
+            strip_index_format: None, 
+            front_face: wgpu::FrontFace::Ccw, 
+            cull_mode: None, 
+            unclipped_depth: false, 
+            polygon_mode: wgpu::PolygonMode::Fill, 
+            conservative: false 
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None
+    })
 }
\ No newline at end of file
@@ -1,34 +1,34 @@
+use common::time::{Time, TimeMode};
+use gpu_layout::UniformLayout;
 
-pub struct Time {
-    start: std::time::Instant
-}
-
-impl Time {
-    pub fn new() -> Self {
-        Time {
-            start: std::time::Instant::now()
-        }
-    }
 
-    pub fn elapsed(&self) -> f32 {
-        self.start.elapsed().as_secs_f32()
-    }
+#[derive(UniformLayout)]
+pub struct UniformRaw {
+    resolution: [f32; 2],
+    mouse: [f32; 2],
+    time: f32,
+    pixel_ratio: f32,
+    frame: u32
 }
 
 pub struct Uniform {
     time: Time,
-    time_buffer: wgpu::Buffer,
+    resolution: [f32; 2],
+    // Cursor position in physical pixels from the top left corner
+    mouse: [f32; 2],
+    pixel_ratio: f32,
+    buffer: wgpu::Buffer,
     pub bind_group_layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup
 }
 
 impl Uniform {
-    pub fn new(device: &wgpu::Device) -> Self {
-        let time = Time::new();
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let time = Time::new(TimeMode::RealTime);
 
-        let time_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
-            size: std::mem::size_of::<f32>() as u64,
+            size: UniformRaw::SIZE as u64,
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false
         });
@@ -55,7 +55,7 @@ impl Uniform {
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: time_buffer.as_entire_binding()
+                    resource: buffer.as_entire_binding()
                 }
             ]
         });
@@ -63,13 +63,69 @@ impl Uniform {
 
         Uniform {
             time,
-            time_buffer,
+            resolution: [width as f32, height as f32],
+            mouse: [0.0; 2],
+            pixel_ratio: 1.0,
+            buffer,
             bind_group,
             bind_group_layout
         }
     }
 
-    pub fn update(&self, queue: &wgpu::Queue) {
-        queue.write_buffer(&self.time_buffer, 0, bytemuck::cast_slice(&[self.time.elapsed()]));
+    pub fn set_time(&mut self, time: Time) {
+        self.time = time;
+    }
+
+    pub fn time_mut(&mut self) -> &mut Time {
+        &mut self.time
     }
-}
\ No newline at end of file
+
+    pub fn set_mouse(&mut self, x: f32, y: f32) {
+        self.mouse = [x, y];
+    }
+
+    pub fn set_pixel_ratio(&mut self, pixel_ratio: f32) {
+        self.pixel_ratio = pixel_ratio;
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.resolution = [width as f32, height as f32];
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue) {
+        self.time.update();
+        queue.write_buffer(&self.buffer, 0, &self.as_raw().to_bytes());
+    }
+
+    pub fn as_raw(&self) -> UniformRaw {
+        UniformRaw {
+            resolution: self.resolution,
+            mouse: self.mouse,
+            time: self.time.elapsed(),
+            pixel_ratio: self.pixel_ratio,
+            frame: self.time.frame()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use common::{layout, preprocessor::preprocess, shader};
+    use gpu_layout::UniformLayout;
+    use super::UniformRaw;
+
+    #[test]
+    fn uniform_raw_matches_the_shader() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let source = preprocess(&dir.join("src/shader.wgsl"), &[dir.join("../shaders")], &["ANIMATED_NOISE".to_string()], |path| std::fs::read_to_string(path))
+            .unwrap_or_else(|error| panic!("{error}"));
+        let module = shader::validate(&source).unwrap_or_else(|error| panic!("{error}"));
+
+        if let Err(error) = layout::check(&source, &module, "UniformParameters", &UniformRaw::layout()) {
+            panic!("{error}");
+        }
+    }
+
+}
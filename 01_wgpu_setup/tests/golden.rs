@@ -0,0 +1,22 @@
+//! Golden-image tests, see `common::golden`.
+//!
+//! Run `UPDATE_GOLDEN=1 cargo test` to regenerate the references after an intended change.
+
+use common::golden::Golden;
+
+
+fn golden() -> Golden {
+    Golden::new(env!("CARGO_BIN_EXE_step_01"), env!("CARGO_MANIFEST_DIR"), env!("CARGO_TARGET_TMPDIR"))
+        // Values tweaked while running the app are saved next to the shader, the tests use the defaults
+        .saved_to("--params", "test.params")
+}
+
+#[test]
+fn perlin_at_one_second() {
+    golden().check("perlin_1s", 1.0, &[]);
+}
+
+#[test]
+fn perlin_at_two_and_a_half_seconds() {
+    golden().check("perlin_2_5s", 2.5, &[]);
+}
@@ -1,15 +1,48 @@
-use std::sync::Arc;
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
-use winit::window::Window;
-use crate::uniform;
+use common::{constants::Constants, palette::Palettes, params::Params, shader::{self, ErrorOverlay, ShaderError, ShaderFile}, target::{OffscreenTarget, RenderTarget}, time::Time};
+use gpu_layout::UniformLayout;
+use winit::{dpi::PhysicalSize, event::{ElementState, KeyEvent, WindowEvent}, keyboard::ModifiersState, window::Window};
+use crate::{canvas::ComputeCanvas, multipass::{BufferConfig, Multipass}, shadertoy::Shadertoy, uniform};
+
+// The shader and everything it includes, the fallback when the files on disk are broken. Baking
+// reads the noise library from here when the binary runs without the sources.
+pub const SHADERS: &[(&str, &str)] = &[
+    (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader.wgsl"), include_str!("shader.wgsl")),
+    (concat!(env!("CARGO_MANIFEST_DIR"), "/../shaders/noise.wgsl"), include_str!("../../shaders/noise.wgsl")),
+    (concat!(env!("CARGO_MANIFEST_DIR"), "/../shaders/palette.wgsl"), include_str!("../../shaders/palette.wgsl"))
+];
+
+// Bind group of the shader's `Params` struct
+const PARAMS_GROUP: u32 = 1;
+// Bind group of the palette lookup table
+const PALETTE_GROUP: u32 = 2;
 
 pub struct AppState {
-    pub window: Arc<Window>,
-    surface: wgpu::Surface<'static>,
+    target: RenderTarget,
+    size: PhysicalSize<u32>,
     device: wgpu::Device,
     queue: wgpu::Queue,
+    shader: ShaderFile,
+    shader_error: Option<ShaderError>,
+    error_overlay: ErrorOverlay,
+    render_pipeline_layout: wgpu::PipelineLayout,
     render_pipeline: wgpu::RenderPipeline,
-    uniform: uniform::Uniform
+    uniform: uniform::Uniform,
+    params: Params,
+    constants: Constants,
+    palettes: Palettes,
+    modifiers: ModifiersState,
+    // Replaces the noise shader when set
+    scene: Option<Scene>
+}
+
+enum Scene {
+    Shadertoy(Shadertoy),
+    // Feedback buffers and their composite pass
+    Multipass(Multipass),
+    // A compute shader run before the render pass
+    Compute(ComputeCanvas)
 }
 
 impl AppState {
@@ -26,8 +59,8 @@ impl AppState {
 
         let surface_caps = surface.get_capabilities(&adapter);
         let config = wgpu::SurfaceConfiguration {
-            width: size.width,
-            height: size.height,
+            width: size.width.max(1),
+            height: size.height.max(1),
             format: surface_caps.formats[0],
             alpha_mode: surface_caps.alpha_modes[0],
             present_mode: surface_caps.present_modes[0],
@@ -37,83 +70,295 @@ impl AppState {
         };
         surface.configure(&device, &config);
 
-        let uniform = uniform::Uniform::new(&device);
+        let title = window.title();
+        let pixel_ratio = window.scale_factor() as f32;
+        let target = RenderTarget::Window { window, title, surface, config };
+        let mut app_state = Self::with_target(device, queue, target, size);
+        app_state.uniform.set_pixel_ratio(pixel_ratio);
+        app_state
+    }
 
-        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into())
-        });
+    pub async fn new_headless(width: u32, height: u32, software: bool) -> Self {
+        let (device, queue) = request_headless_device(software).await;
+
+        let target = RenderTarget::Offscreen(OffscreenTarget::new(&device, width, height));
+        Self::with_target(device, queue, target, PhysicalSize::new(width, height))
+    }
+
+    fn with_target(device: wgpu::Device, queue: wgpu::Queue, target: RenderTarget, size: PhysicalSize<u32>) -> Self {
+        device.on_uncaptured_error(Box::new(|error| eprintln!("Uncaptured wgpu error:\n{error}")));
+
+        let uniform = uniform::Uniform::new(&device, size.width, size.height);
+
+        let mut shader = ShaderFile::new(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader.wgsl")), SHADERS)
+            .include_dir(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../shaders")))
+            .define("ANIMATED_NOISE");
+
+        let mut params = Params::new(&device);
+        params.set_file(shader.path.with_extension("params"));
+        let palettes = Palettes::new(&device, "water");
 
         let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             bind_group_layouts: &[
-                &uniform.bind_group_layout
+                &uniform.bind_group_layout,
+                &params.bind_group_layout,
+                &palettes.bind_group_layout
             ],
             ..Default::default()
         });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader_module,
-                entry_point: "vs_main",
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                buffers: &[]
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader_module,
-                entry_point: "fs_main",
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                targets: &[
-                    Some(wgpu::ColorTargetState {
-                        format: config.format,
-                        blend: Some(wgpu::BlendState::REPLACE),
-                        write_mask: wgpu::ColorWrites::all()
-                    })
-                ]
-            }),
-            primitive: wgpu::PrimitiveState { 
-                topology: wgpu::PrimitiveTopology::TriangleList, 
-                strip_index_format: None, 
-                front_face: wgpu::FrontFace::Ccw, 
-                cull_mode: None, 
-                unclipped_depth: false, 
-                polygon_mode: wgpu::PolygonMode::Fill, 
-                conservative: false 
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None
-        });
-
-
+        // The pipeline constants are applied by `set_constants`, the shader starts with its defaults
+        let create_pipeline = |shader_module: &wgpu::ShaderModule| {
+            create_render_pipeline(&device, &render_pipeline_layout, shader_module, target.format(), &HashMap::new())
+        };
+        let ((render_pipeline, params_layout), shader_error) = match shader.load().and_then(|source| shader::compile(&device, &source, &uniform::UniformRaw::layout(), PARAMS_GROUP, create_pipeline)) {
+            Ok(compiled) => (compiled, None),
+            Err(error) => {
+                eprintln!("{error}\nUsing the embedded shader until {} is fixed", shader.path.display());
+                let compiled = shader.load_embedded()
+                    .and_then(|source| shader::compile(&device, &source, &uniform::UniformRaw::layout(), PARAMS_GROUP, create_pipeline))
+                    .unwrap_or_else(|error| panic!("the embedded shader is broken:\n{error}"));
+                (compiled, Some(error))
+            }
+        };
+        params.set_layout(&device, params_layout);
+        target.show_error(shader_error.as_ref().map(|error| error.summary.as_str()));
+        let error_overlay = ErrorOverlay::new(&device, target.format());
 
         AppState {
-            window,
-            surface,
+            target,
+            size,
             device,
             queue,
+            shader,
+            shader_error,
+            error_overlay,
+            render_pipeline_layout,
             render_pipeline,
-            uniform
+            uniform,
+            params,
+            constants: Constants::new(),
+            palettes,
+            modifiers: ModifiersState::default(),
+            scene: None
+        }
+    }
+
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        self.size = new_size;
+        if new_size.width == 0 || new_size.height == 0 {
+            return;
+        }
+
+        self.target.resize(&self.device, new_size.width, new_size.height);
+        self.uniform.resize(new_size.width, new_size.height);
+        match &mut self.scene {
+            Some(Scene::Multipass(multipass)) => multipass.resize(&self.device, new_size),
+            Some(Scene::Compute(canvas)) => {
+                if let Err(error) = canvas.resize(&self.device, &self.uniform.bind_group_layout, new_size) {
+                    eprintln!("{error}\nNothing is computed until {} is reloaded", canvas.path().display());
+                    self.set_shader_error(Some(error));
+                }
+            },
+            _ => {}
+        }
+    }
+
+    pub fn reconfigure(&mut self) {
+        self.resize(self.size);
+    }
+
+    // Where the tweaked params are saved, next to the shader by default
+    pub fn set_params_file(&mut self, path: PathBuf) {
+        self.params.set_file(path);
+    }
+
+    // Pipeline constants from `path`, next to the shader by default, and from the command line.
+    // The pipeline is rebuilt whenever the file changes.
+    pub fn set_constants(&mut self, path: Option<PathBuf>, values: &[(String, f64)]) {
+        self.constants.set_file(path.unwrap_or(self.shader.path.with_extension("constants")));
+        for (name, value) in values {
+            self.constants.set(name, *value);
+        }
+
+        let error = self.rebuild_pipeline().err();
+        if let Some(error) = &error {
+            eprintln!("{error}\nKeeping the default pipeline constants until this is fixed");
+        }
+        self.set_shader_error(error);
+    }
+
+    pub fn set_time(&mut self, time: Time) {
+        self.uniform.set_time(time);
+    }
+
+    // A preset name or a gradient file, see palette.rs
+    pub fn set_palette(&mut self, name_or_path: &str) -> Result<(), String> {
+        self.palettes.select(name_or_path)
+    }
+
+    // Moves on to the next palette every `seconds` of shader time
+    pub fn set_palette_cycle(&mut self, seconds: Option<f32>) {
+        self.palettes.set_cycle(seconds);
+    }
+
+    pub fn set_shadertoy(&mut self, path: PathBuf) {
+        let mut shadertoy = Shadertoy::new(&self.device, path, self.target.format());
+        let error = shadertoy.reload(&self.device).err();
+        if let Some(error) = &error {
+            eprintln!("{error}\nUsing the default Shadertoy shader until {} is fixed", shadertoy.path().display());
+        }
+
+        self.scene = Some(Scene::Shadertoy(shadertoy));
+        self.set_shader_error(error);
+    }
+
+    pub fn set_multipass(&mut self, buffers: &[BufferConfig], composite: Option<PathBuf>) {
+        let mut multipass = Multipass::new(&self.device, &self.uniform.bind_group_layout, buffers, composite, self.target.format(), self.size);
+        let error = multipass.load(&self.device).err();
+        if let Some(error) = &error {
+            eprintln!("{error}\nSkipping the broken passes until they are fixed");
+        }
+
+        self.scene = Some(Scene::Multipass(multipass));
+        self.set_shader_error(error);
+    }
+
+    pub fn set_compute(&mut self, path: PathBuf) {
+        let mut canvas = ComputeCanvas::new(&self.device, path, self.target.format(), self.size);
+        let error = canvas.reload(&self.device, &self.uniform.bind_group_layout).err();
+        if let Some(error) = &error {
+            eprintln!("{error}\nNothing is computed until {} is fixed", canvas.path().display());
+        }
+
+        self.scene = Some(Scene::Compute(canvas));
+        self.set_shader_error(error);
+    }
+
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        if let Some(Scene::Shadertoy(shadertoy)) = &mut self.scene {
+            if shadertoy.input(event) {
+                return true;
+            }
+        }
+
+        match event {
+            WindowEvent::KeyboardInput {
+                event: KeyEvent { state: ElementState::Pressed, logical_key, .. },
+                ..
+            } => {
+                self.uniform.time_mut().handle_key(logical_key)
+                    || self.params.handle_key(logical_key, self.modifiers.shift_key())
+                    || self.palettes.handle_key(logical_key, self.modifiers.shift_key())
+            },
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+                false
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                self.uniform.set_mouse(position.x as f32, position.y as f32);
+                false
+            },
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.uniform.set_pixel_ratio(*scale_factor as f32);
+                false
+            },
+            _ => false
         }
     }
 
     pub fn update(&mut self) {
+        self.reload_shader();
         self.uniform.update(&self.queue);
+        self.params.update(&self.queue);
+        self.palettes.update(&self.queue, self.uniform.time().elapsed());
+        match &mut self.scene {
+            Some(Scene::Shadertoy(shadertoy)) => shadertoy.update(&self.queue, self.uniform.time(), self.size),
+            Some(Scene::Multipass(multipass)) => multipass.advance(),
+            _ => {}
+        }
+    }
+
+    fn reload_shader(&mut self) {
+        let (path, result) = match &mut self.scene {
+            Some(Scene::Shadertoy(shadertoy)) => {
+                if !shadertoy.changed() {
+                    return;
+                }
+                (shadertoy.path().to_path_buf(), shadertoy.reload(&self.device))
+            },
+            Some(Scene::Multipass(multipass)) => match multipass.reload_changed(&self.device) {
+                Some(reloaded) => reloaded,
+                None => return
+            },
+            Some(Scene::Compute(canvas)) => {
+                if !canvas.changed() {
+                    return;
+                }
+                (canvas.path().to_path_buf(), canvas.reload(&self.device, &self.uniform.bind_group_layout))
+            },
+            None => {
+                // Both are polled, so a change of either is not reported twice
+                if !(self.shader.changed() | self.constants.changed()) {
+                    return;
+                }
+                (self.shader.path.clone(), self.rebuild_pipeline())
+            }
+        };
+
+        match result {
+            Ok(()) => {
+                self.set_shader_error(None);
+                println!("Reloaded {}", path.display());
+            },
+            Err(error) => {
+                eprintln!("{error}\nKeeping the previous pipeline until {} is fixed", path.display());
+                self.set_shader_error(Some(error));
+            }
+        }
+    }
+
+    fn rebuild_pipeline(&mut self) -> Result<(), ShaderError> {
+        self.constants.load()?;
+        let source = self.shader.load()?;
+        self.constants.check(&source)?;
+
+        let format = self.target.format();
+        let constants = self.constants.values();
+        let (render_pipeline, params_layout) = shader::compile(&self.device, &source, &uniform::UniformRaw::layout(), PARAMS_GROUP, |shader_module| {
+            create_render_pipeline(&self.device, &self.render_pipeline_layout, shader_module, format, &constants)
+        })?;
+        self.render_pipeline = render_pipeline;
+        self.params.set_layout(&self.device, params_layout);
+
+        Ok(())
+    }
+
+    fn set_shader_error(&mut self, error: Option<ShaderError>) {
+        self.target.show_error(error.as_ref().map(|error| error.summary.as_str()));
+        self.shader_error = error;
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let texture = self.surface.get_current_texture()?;
-        let view = texture.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        if self.size.width == 0 || self.size.height == 0 {
+            return Ok(());
+        }
+
+        let frame = self.target.acquire()?;
 
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        match &self.scene {
+            Some(Scene::Multipass(multipass)) => multipass.render_buffers(&mut encoder, &self.uniform.bind_group),
+            Some(Scene::Compute(canvas)) => canvas.compute(&mut encoder, &self.uniform.bind_group),
+            _ => {}
+        }
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: None,
                 color_attachments: &[
                     Some(
                         wgpu::RenderPassColorAttachment {
-                            view: &view,
+                            view: &frame.view,
                             resolve_target: None,
                             ops: wgpu::Operations{
                                 load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
@@ -126,13 +371,92 @@ impl AppState {
                 timestamp_writes: None,
                 occlusion_query_set: None
             });
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.uniform.bind_group, &[]);
-            render_pass.draw(0..6, 0..1);
+            match &self.scene {
+                Some(Scene::Shadertoy(shadertoy)) => shadertoy.draw(&mut render_pass),
+                Some(Scene::Multipass(multipass)) => multipass.draw(&mut render_pass, &self.uniform.bind_group),
+                Some(Scene::Compute(canvas)) => canvas.draw(&mut render_pass),
+                None => {
+                    render_pass.set_pipeline(&self.render_pipeline);
+                    render_pass.set_bind_group(0, &self.uniform.bind_group, &[]);
+                    render_pass.set_bind_group(PARAMS_GROUP, &self.params.bind_group, &[]);
+                    render_pass.set_bind_group(PALETTE_GROUP, &self.palettes.bind_group, &[]);
+                    render_pass.draw(0..6, 0..1);
+                }
+            }
+        }
+        if self.shader_error.is_some() {
+            self.error_overlay.draw(&mut encoder, &frame.view);
         }
         self.queue.submit(std::iter::once(encoder.finish()));
-        texture.present();
+        frame.present();
 
         Ok(())
     }
+
+    pub fn capture(&self) -> Option<image::RgbaImage> {
+        match &self.target {
+            RenderTarget::Offscreen(target) => Some(target.read_image(&self.device, &self.queue)),
+            RenderTarget::Window { .. } => None
+        }
+    }
+}
+
+// A device without a surface, on the software adapter when `software` is set or there is no GPU
+pub async fn request_headless_device(software: bool) -> (wgpu::Device, wgpu::Queue) {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    let adapter = match instance.request_adapter(&wgpu::RequestAdapterOptions{
+        force_fallback_adapter: software,
+        ..Default::default()
+    }).await {
+        Some(adapter) => adapter,
+        None => instance.request_adapter(&wgpu::RequestAdapterOptions{
+            force_fallback_adapter: true,
+            ..Default::default()
+        }).await.unwrap()
+    };
+
+    adapter.request_device(&wgpu::DeviceDescriptor::default(), None).await.unwrap()
+}
+
+fn create_render_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, shader_module: &wgpu::ShaderModule, format: wgpu::TextureFormat, constants: &HashMap<String, f64>) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader_module,
+            entry_point: "vs_main",
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants,
+                ..Default::default()
+            },
+            buffers: &[]
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader_module,
+            entry_point: "fs_main",
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants,
+                ..Default::default()
+            },
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::all()
+                })
+            ]
+        }),
+        primitive: wgpu::PrimitiveState { 
+            topology: wgpu::PrimitiveTopology::TriangleList, 
+            strip_index_format: None, 
+            front_face: wgpu::FrontFace::Ccw, 
+            cull_mode: None, 
+            unclipped_depth: false, 
+            polygon_mode: wgpu::PolygonMode::Fill, 
+            conservative: false 
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None
+    })
 }
\ No newline at end of file
@@ -0,0 +1,260 @@
+use std::path::{Path, PathBuf};
+
+use common::{layout, preprocessor::{preprocess, Preprocessed}, shader::{self, ShaderError}};
+use gpu_layout::UniformLayout;
+use image::{DynamicImage, ImageBuffer, Pixel};
+use wgpu::{naga, util::DeviceExt};
+use crate::{app_state::SHADERS, compute::ComputeBuilder};
+
+
+const BAKE_SHADER: &str = include_str!("bake.wgsl");
+
+// Evaluates a WGSL expression for every texel of a texture in a compute shader, for baking noise textures
+// without a window. The expression can call anything in the noise library and gets the texel's
+// position in noise units as `pos`, a vec2f or a vec3f for volumes. It has to be an f32,
+// which is baked as grey, or a vector of two to four f32.
+pub struct Bake {
+    pub expression: String,
+    pub width: u32,
+    pub height: u32,
+    // Bakes a 3D texture with this many slices
+    pub depth: Option<u32>,
+    // Blends four shifted copies of the noise so the texture wraps around, which lowers the contrast
+    pub tileable: bool,
+    // Noise units across the texture
+    pub scale: f32,
+    pub time: f32
+}
+
+#[derive(UniformLayout)]
+struct BakeParametersRaw {
+    time: f32,
+    scale: f32,
+    size: [u32; 3]
+}
+
+// The texels read back from the GPU, RGBA rows with the slices of a volume one after another
+pub struct Baked {
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    // The components of the expression, 1 to 4
+    pub components: usize,
+    pub texels: Vec<f32>
+}
+
+impl Bake {
+    pub fn run(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Baked, ShaderError> {
+        // The expression is compiled once on its own to find out what it evaluates to
+        let source = self.preprocess("vec4f(0.0)")?;
+        let module = shader::validate(&source)?;
+        let components = expression_components(&source, &module)?;
+
+        let conversion = match components {
+            1 => "vec4f(vec3f(value), 1.0)",
+            2 => "vec4f(value, 0.0, 1.0)",
+            3 => "vec4f(value, 1.0)",
+            _ => "value"
+        };
+        let source = self.preprocess(conversion)?;
+        let module = shader::validate(&source)?;
+        layout::check(&source, &module, "BakeParameters", &BakeParametersRaw::layout())?;
+
+        let depth = self.depth.unwrap_or(1);
+        let size = self.width as u64 * self.height as u64 * depth as u64 * 16;
+        let limit = device.limits().max_storage_buffer_binding_size as u64;
+        if size > limit {
+            let message = format!("{}x{}x{depth} texels of 16 bytes exceed the device's limit of {limit} bytes", self.width, self.height);
+            return Err(ShaderError::new(&source.files, &message, Vec::new(), Vec::new()));
+        }
+        let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: &BakeParametersRaw {
+                time: self.time,
+                scale: self.scale,
+                size: [self.width, self.height, depth]
+            }.to_bytes(),
+            usage: wgpu::BufferUsages::UNIFORM
+        });
+
+        let bake_pass = ComputeBuilder::new(&source, "main")
+            .uniform_buffer(0, &uniform_buffer)
+            .storage_buffer(1, &output_buffer, false)
+            .build(device)?;
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            bake_pass.dispatch(&mut compute_pass, &[], [self.width, self.height, depth]);
+        }
+        encoder.copy_buffer_to_buffer(&output_buffer, 0, &readback_buffer, 0, size);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = readback_buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| sender.send(result).unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        if let Err(error) = receiver.recv().unwrap_or(Err(wgpu::BufferAsyncError)) {
+            return Err(ShaderError::new(&source.files, &format!("cannot read back the baked texels: {error}"), Vec::new(), Vec::new()));
+        }
+        let texels = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+
+        Ok(Baked {
+            width: self.width,
+            height: self.height,
+            depth,
+            components,
+            texels
+        })
+    }
+
+    fn preprocess(&self, conversion: &str) -> Result<Preprocessed, ShaderError> {
+        let path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/src/bake.wgsl"));
+        let include_dirs = [Path::new(env!("CARGO_MANIFEST_DIR")).join("../shaders")];
+        let mut defines = Vec::new();
+        if self.depth.is_some() {
+            defines.push("VOLUME".to_string());
+        }
+        if self.tileable {
+            defines.push("TILEABLE".to_string());
+        }
+
+        let code = BAKE_SHADER
+            .replace("{{expression}}", &self.expression)
+            .replace("{{conversion}}", conversion);
+        // The includes are read from disk, or from the binary when it runs without the sources
+        preprocess(&path, &include_dirs, &defines, |file| match file == path {
+            true => Ok(code.clone()),
+            false => std::fs::read_to_string(file).or_else(|_| shader::read_embedded(SHADERS, file))
+        })
+    }
+}
+
+impl Baked {
+    // The format follows the extension, `bits` is 8 or 16 for integer formats like PNG and 32 for
+    // float formats like EXR. Integer formats clamp to 0..1. The slices of a volume are stacked vertically.
+    pub fn save(&self, path: &Path, bits: u32) -> Result<(), String> {
+        let width = self.width;
+        let height = self.height * self.depth;
+        let to_u8 = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let to_u16 = |value: f32| (value.clamp(0.0, 1.0) * 65535.0).round() as u16;
+
+        let image = match (bits, self.components) {
+            (8, 1) => DynamicImage::ImageLuma8(self.image(width, height, to_u8)),
+            (8, 2 | 3) => DynamicImage::ImageRgb8(self.image(width, height, to_u8)),
+            (8, _) => DynamicImage::ImageRgba8(self.image(width, height, to_u8)),
+            (16, 1) => DynamicImage::ImageLuma16(self.image(width, height, to_u16)),
+            (16, 2 | 3) => DynamicImage::ImageRgb16(self.image(width, height, to_u16)),
+            (16, _) => DynamicImage::ImageRgba16(self.image(width, height, to_u16)),
+            // There is no grey float image, grey expressions are baked with equal components
+            (32, 1..=3) => DynamicImage::ImageRgb32F(self.image(width, height, |value| value)),
+            (32, _) => DynamicImage::ImageRgba32F(self.image(width, height, |value| value)),
+            _ => return Err(format!("cannot save {bits} bit images, expected 8, 16 or 32"))
+        };
+
+        image.save(path).map_err(|error| format!("cannot save {}: {error}", path.display()))
+    }
+
+    fn image<P: Pixel>(&self, width: u32, height: u32, convert: impl Fn(f32) -> P::Subpixel) -> ImageBuffer<P, Vec<P::Subpixel>> {
+        let channels = P::CHANNEL_COUNT as usize;
+        let data = self.texels.chunks(4).flat_map(|texel| texel[..channels].iter().map(|value| convert(*value))).collect();
+        ImageBuffer::from_raw(width, height, data).expect("the texels cover the image")
+    }
+}
+
+// The number of components of `let value` in `bakeValue`
+fn expression_components(source: &Preprocessed, module: &naga::Module) -> Result<usize, ShaderError> {
+    let mut validator = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all());
+    let info = validator.validate(module).expect("the module was validated already");
+
+    let (handle, function) = module.functions.iter()
+        .find(|(_, function)| function.name.as_deref() == Some("bakeValue"))
+        .expect("bake.wgsl declares bakeValue");
+    let value = function.named_expressions.iter()
+        .find(|(_, name)| name.as_str() == "value")
+        .map(|(expression, _)| *expression)
+        .expect("bakeValue declares value");
+
+    match info[handle][value].ty.inner_with(&module.types) {
+        naga::TypeInner::Scalar(naga::Scalar::F32) => Ok(1),
+        naga::TypeInner::Vector { size, scalar: naga::Scalar::F32 } => Ok(*size as usize),
+        inner => {
+            let span = function.expressions.get_span(value);
+            let labels = shader::map_labels(source, std::iter::once((span, "")));
+            let message = format!("the baked expression is a {}, expected an f32 or a vector of f32", inner.to_wgsl(&module.to_ctx()));
+            Err(ShaderError::new(&source.files, &message, labels, Vec::new()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::noise::{perlin_noise2, perlin_noise3};
+    use glam::{Vec2, Vec3};
+    use crate::app_state::request_headless_device;
+    use super::{Bake, Baked};
+
+    fn bake(expression: &str, width: u32, height: u32, depth: Option<u32>, tileable: bool) -> Baked {
+        let (device, queue) = pollster::block_on(request_headless_device(true));
+        let bake = Bake { expression: expression.to_string(), width, height, depth, tileable, scale: 4.0, time: 0.0 };
+        bake.run(&device, &queue).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    #[test]
+    fn bakes_like_the_cpu_reference() {
+        let baked = bake("perlinNoise2(pos, 3u)", 16, 8, None, false);
+        assert_eq!(baked.components, 1);
+        for (i, texel) in baked.texels.chunks(4).enumerate() {
+            let uv = (Vec2::new((i % 16) as f32, (i / 16) as f32) + 0.5) / Vec2::new(16.0, 8.0);
+            let expected = perlin_noise2(uv * 4.0, 3);
+            assert!((texel[0] - expected).abs() < 1e-5, "texel {i} is {texel:?}, expected {expected}");
+        }
+
+        let baked = bake("vec3f(perlinNoise3(pos, 3u), 0.25, 0.5)", 8, 8, Some(4), false);
+        assert_eq!(baked.components, 3);
+        for (i, texel) in baked.texels.chunks(4).enumerate() {
+            let uvw = (Vec3::new((i % 8) as f32, (i / 8 % 8) as f32, (i / 64) as f32) + 0.5) / Vec3::new(8.0, 8.0, 4.0);
+            let expected = perlin_noise3(uvw * 4.0, 3);
+            assert!((texel[0] - expected).abs() < 1e-5, "texel {i} is {texel:?}, expected {expected}");
+            assert_eq!(&texel[1..], [0.25, 0.5, 1.0]);
+        }
+    }
+
+    // The step from the last column to the first is no larger than between neighbouring columns
+    #[test]
+    fn tileable_bakes_wrap_around() {
+        let size = 32;
+        let seam = |baked: &Baked| {
+            let value = |x: usize, y: usize| baked.texels[(y * size + x) * 4];
+            let largest = |x0: usize, x1: usize| (0..size).map(|y| (value(x0, y) - value(x1, y)).abs()).fold(0.0, f32::max);
+            let inner = (0..size - 1).map(|x| largest(x, x + 1)).fold(0.0, f32::max);
+            (largest(size - 1, 0), inner)
+        };
+
+        let (wrapped, inner) = seam(&bake("fractalFbm2(pos, defaultFractal(1u))", size as u32, size as u32, None, true));
+        assert!(wrapped <= inner, "the tileable bake jumps by {wrapped} at the seam, by up to {inner} inside");
+        let (wrapped, inner) = seam(&bake("fractalFbm2(pos, defaultFractal(1u))", size as u32, size as u32, None, false));
+        assert!(wrapped > inner, "the plain bake should not wrap around");
+    }
+
+    #[test]
+    fn rejects_non_float_expressions() {
+        let (device, queue) = pollster::block_on(request_headless_device(true));
+        let bake = Bake { expression: "vec2i(1)".to_string(), width: 4, height: 4, depth: None, tileable: false, scale: 1.0, time: 0.0 };
+        let error = bake.run(&device, &queue).err().expect("vec2i cannot be baked");
+        assert!(error.summary.contains("bake.wgsl:"), "{}", error.summary);
+        assert!(error.summary.ends_with("the baked expression is a vec2<i32>, expected an f32 or a vector of f32"), "{}", error.summary);
+    }
+}
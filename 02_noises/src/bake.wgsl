@@ -0,0 +1,68 @@
+// Compute shader of the noise baker, bake.rs fills in the expression and its conversion to a color
+//
+// Defines:
+//   VOLUME    bakes a 3D texture, `pos` is a vec3f
+//   TILEABLE  blends shifted copies of the noise so the texture wraps around
+
+struct BakeParameters {
+    time: f32,
+    // Noise units across the texture
+    scale: f32,
+    // Width, height and depth of the texture
+    size: vec3u
+}
+
+// The expression can use `uniforms.time` like the steps' shaders
+@group(0) @binding(0) var<uniform> uniforms: BakeParameters;
+
+// The texels row by row and slice by slice, like a texture copied into a buffer
+@group(0) @binding(1) var<storage, read_write> output: array<vec4f>;
+
+#ifdef VOLUME
+alias Pos = vec3f;
+#else
+alias Pos = vec2f;
+#endif
+
+#include "noise.wgsl"
+
+fn bakeValue(pos: Pos) -> vec4f {
+    let value = {{expression}};
+    return {{conversion}};
+}
+
+#ifdef VOLUME
+@compute @workgroup_size(4, 4, 4)
+fn main(@builtin(global_invocation_id) id: vec3u) {
+    let size = uniforms.size;
+    if any(id >= size) {
+        return;
+    }
+
+    let uvw = (vec3f(id) + 0.5) / vec3f(size);
+    output[(id.z * size.y + id.y) * size.x + id.x] = bakeValue(uvw * uniforms.scale);
+}
+#else
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3u) {
+    let size = uniforms.size.xy;
+    if any(id.xy >= size) {
+        return;
+    }
+
+    let uv = (vec2f(id.xy) + 0.5) / vec2f(size);
+    let pos = uv * uniforms.scale;
+    let index = id.y * size.x + id.x;
+#ifdef TILEABLE
+    // Each copy is shifted by a whole period, they meet at the opposite edges
+    let period = uniforms.scale;
+    let a = bakeValue(pos);
+    let b = bakeValue(pos - vec2f(period, 0.0));
+    let c = bakeValue(pos - vec2f(0.0, period));
+    let d = bakeValue(pos - vec2f(period, period));
+    output[index] = mix(mix(a, b, uv.x), mix(c, d, uv.x), uv.y);
+#else
+    output[index] = bakeValue(pos);
+#endif
+}
+#endif
@@ -0,0 +1,194 @@
+use std::path::{Path, PathBuf};
+
+use common::{preprocessor::Preprocessed, shader::{self, ShaderError, ShaderFile}};
+use winit::dpi::PhysicalSize;
+use crate::compute::{ComputeBuilder, ComputePass};
+
+
+// Draws the window with a compute shader given with `--compute FILE`. Its `main` is dispatched once
+// per pixel every frame before the render pass, which shows the storage texture it wrote.
+pub struct ComputeCanvas {
+    shader: ShaderFile,
+    // The last source that compiled, rebuilt against the new resources on resize
+    source: Option<Preprocessed>,
+    pass: Option<ComputePass>,
+    size: PhysicalSize<u32>,
+    output: wgpu::Texture,
+    state: wgpu::Buffer,
+    blit_pipeline: wgpu::RenderPipeline,
+    blit_bind_group: wgpu::BindGroup
+}
+
+impl ComputeCanvas {
+    // `texture_storage_2d<rgba16float, write>` in canvas.wgsl
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+    const BLIT_SHADER: &'static str = "
+        @group(0) @binding(0) var canvas: texture_2d<f32>;
+
+        @fragment
+        fn fs_main(@builtin(position) pos: vec4f) -> @location(0) vec4f {
+            return vec4f(textureLoad(canvas, vec2i(pos.xy), 0).rgb, 1.0);
+        }
+    ";
+
+    pub fn new(device: &wgpu::Device, path: PathBuf, format: wgpu::TextureFormat, size: PhysicalSize<u32>) -> Self {
+        let shader = ShaderFile::new(path, &[])
+            .include_dir(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/src/canvas")))
+            .include_dir(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../shaders")))
+            .define("ANIMATED_NOISE");
+
+        let vertex_module = shader::create_shader_module(device, include_str!("fullscreen.wgsl"));
+        let fragment_module = shader::create_shader_module(device, Self::BLIT_SHADER);
+        let blit_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &vertex_module,
+                entry_point: "vs_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[]
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &fragment_module,
+                entry_point: "fs_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::all()
+                    })
+                ]
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None
+        });
+
+        let (output, state, blit_bind_group) = create_resources(device, &blit_pipeline, size);
+
+        ComputeCanvas {
+            shader,
+            source: None,
+            pass: None,
+            size,
+            output,
+            state,
+            blit_pipeline,
+            blit_bind_group
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.shader.path
+    }
+
+    pub fn changed(&mut self) -> bool {
+        self.shader.changed()
+    }
+
+    // Rebuilds the compute pipeline from the file on disk, the previous one is kept on failure
+    pub fn reload(&mut self, device: &wgpu::Device, uniform_layout: &wgpu::BindGroupLayout) -> Result<(), ShaderError> {
+        let source = self.shader.load()?;
+        self.pass = Some(self.build(device, uniform_layout, &source)?);
+        self.source = Some(source);
+        Ok(())
+    }
+
+    // The output and the state start over at the new size. Nothing is computed until the next
+    // reload when the pipeline cannot be rebuilt for the new resources.
+    pub fn resize(&mut self, device: &wgpu::Device, uniform_layout: &wgpu::BindGroupLayout, size: PhysicalSize<u32>) -> Result<(), ShaderError> {
+        self.size = size;
+        (self.output, self.state, self.blit_bind_group) = create_resources(device, &self.blit_pipeline, size);
+
+        self.pass = None;
+        if let Some(source) = &self.source {
+            self.pass = Some(self.build(device, uniform_layout, source)?);
+        }
+        Ok(())
+    }
+
+    // Runs before the render pass of the frame
+    pub fn compute(&self, encoder: &mut wgpu::CommandEncoder, uniform_bind_group: &wgpu::BindGroup) {
+        if let Some(pass) = &self.pass {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            pass.dispatch(&mut compute_pass, &[uniform_bind_group], [self.size.width, self.size.height, 1]);
+        }
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.blit_pipeline);
+        render_pass.set_bind_group(0, &self.blit_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn build(&self, device: &wgpu::Device, uniform_layout: &wgpu::BindGroupLayout, source: &Preprocessed) -> Result<ComputePass, ShaderError> {
+        let output_view = self.output.create_view(&wgpu::TextureViewDescriptor::default());
+
+        ComputeBuilder::new(source, "main")
+            .shared_group(uniform_layout)
+            .storage_texture(0, &output_view, Self::FORMAT, wgpu::StorageTextureAccess::WriteOnly)
+            .storage_buffer(1, &self.state, false)
+            .build(device)
+    }
+}
+
+fn create_resources(device: &wgpu::Device, blit_pipeline: &wgpu::RenderPipeline, size: PhysicalSize<u32>) -> (wgpu::Texture, wgpu::Buffer, wgpu::BindGroup) {
+    let output = device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: 1
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        view_formats: &[],
+        dimension: wgpu::TextureDimension::D2,
+        format: ComputeCanvas::FORMAT,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING
+    });
+
+    // Past the device's storage binding size the bind group fails, which is reported like a shader error
+    let state = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: size.width as u64 * size.height as u64 * 16,
+        usage: wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false
+    });
+
+    let blit_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &blit_pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&output.create_view(&wgpu::TextureViewDescriptor::default()))
+            }
+        ]
+    });
+
+    (output, state, blit_bind_group)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use common::{layout, preprocessor::preprocess, shader};
+    use gpu_layout::UniformLayout;
+    use crate::uniform::UniformRaw;
+
+    #[test]
+    fn uniform_raw_matches_the_canvas_shaders() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let source = preprocess(&dir.join("src/canvas/smear.wgsl"), &[dir.join("../shaders")], &["ANIMATED_NOISE".to_string()], |path| std::fs::read_to_string(path))
+            .unwrap_or_else(|error| panic!("{error}"));
+        let module = shader::validate(&source).unwrap_or_else(|error| panic!("{error}"));
+
+        if let Err(error) = layout::check(&source, &module, "UniformParameters", &UniformRaw::layout()) {
+            panic!("{error}");
+        }
+    }
+}
@@ -0,0 +1,27 @@
+// Bindings of the --compute shaders, included with `#include "canvas.wgsl"`
+//
+// The entry point `main` runs once per pixel of the window, rounded up to whole workgroups,
+// so it has to return early outside of `canvasSize()`. What it stores to `output` is shown.
+
+struct UniformParameters {
+    resolution: vec2f,
+    mouse: vec2f,
+    time: f32,
+    pixel_ratio: f32,
+    frame: u32
+}
+
+@group(0) @binding(0) var<uniform> uniforms: UniformParameters;
+
+@group(1) @binding(0) var output: texture_storage_2d<rgba16float, write>;
+// One vec4f per pixel, row by row. Kept from frame to frame and zeroed when the window is resized.
+@group(1) @binding(1) var<storage, read_write> state: array<vec4f>;
+
+fn canvasSize() -> vec2u {
+    return vec2u(uniforms.resolution);
+}
+
+// The index of a pixel in `state`
+fn stateIndex(pixel: vec2u) -> u32 {
+    return pixel.y * canvasSize().x + pixel.x;
+}
@@ -0,0 +1,21 @@
+// Example compute canvas: a running average of the animated domain warp, which smears its motion,
+// run with `--compute 02_noises/src/canvas/smear.wgsl`
+#include "canvas.wgsl"
+#include "noise.wgsl"
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3u) {
+    if any(id.xy >= canvasSize()) {
+        return;
+    }
+
+    let uv = (vec2f(id.xy) - uniforms.resolution / 2.0) * 9.0 / uniforms.resolution.y;
+    let noise = domainWarp(uv, 2);
+
+    // The first frame starts from the noise itself instead of black
+    let index = stateIndex(id.xy);
+    let average = select(mix(state[index].x, noise, 0.05), noise, uniforms.frame == 0u);
+    state[index] = vec4f(average, noise, 0.0, 0.0);
+
+    textureStore(output, id.xy, vec4f(vec3f(0.1, 0.4, 0.8) * average + abs(noise - average), 1.0));
+}
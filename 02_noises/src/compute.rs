@@ -0,0 +1,200 @@
+use common::{layout, preprocessor::Preprocessed, shader::{self, ShaderError}};
+use gpu_layout::UniformLayout;
+use crate::uniform::UniformRaw;
+
+
+// Builds a compute pipeline with the resources of one bind group. The bind group comes after the
+// groups added with `shared_group`, which are bound by whoever dispatches, like the uniforms.
+pub struct ComputeBuilder<'a> {
+    source: &'a Preprocessed,
+    entry_point: &'a str,
+    shared_groups: Vec<&'a wgpu::BindGroupLayout>,
+    resources: Vec<(u32, Resource<'a>)>
+}
+
+enum Resource<'a> {
+    Uniform(&'a wgpu::Buffer),
+    Storage {
+        buffer: &'a wgpu::Buffer,
+        read_only: bool
+    },
+    StorageTexture {
+        view: &'a wgpu::TextureView,
+        format: wgpu::TextureFormat,
+        access: wgpu::StorageTextureAccess
+    }
+}
+
+// A compute pipeline with its bind group, dispatched over a grid of invocations
+pub struct ComputePass {
+    pipeline: wgpu::ComputePipeline,
+    bind_group: wgpu::BindGroup,
+    group: u32,
+    // `@workgroup_size` of the entry point
+    workgroup_size: [u32; 3]
+}
+
+impl<'a> ComputeBuilder<'a> {
+    pub fn new(source: &'a Preprocessed, entry_point: &'a str) -> Self {
+        ComputeBuilder {
+            source,
+            entry_point,
+            shared_groups: Vec::new(),
+            resources: Vec::new()
+        }
+    }
+
+    pub fn shared_group(mut self, layout: &'a wgpu::BindGroupLayout) -> Self {
+        self.shared_groups.push(layout);
+        self
+    }
+
+    pub fn uniform_buffer(mut self, binding: u32, buffer: &'a wgpu::Buffer) -> Self {
+        self.resources.push((binding, Resource::Uniform(buffer)));
+        self
+    }
+
+    pub fn storage_buffer(mut self, binding: u32, buffer: &'a wgpu::Buffer, read_only: bool) -> Self {
+        self.resources.push((binding, Resource::Storage { buffer, read_only }));
+        self
+    }
+
+    pub fn storage_texture(mut self, binding: u32, view: &'a wgpu::TextureView, format: wgpu::TextureFormat, access: wgpu::StorageTextureAccess) -> Self {
+        self.resources.push((binding, Resource::StorageTexture { view, format, access }));
+        self
+    }
+
+    // Validates the shader and reflects the workgroup size of the entry point before creating the pipeline
+    pub fn build(self, device: &wgpu::Device) -> Result<ComputePass, ShaderError> {
+        let module = shader::validate(self.source)?;
+        layout::check(self.source, &module, "UniformParameters", &UniformRaw::layout())?;
+        let workgroup_size = module.entry_points.iter()
+            .find(|entry_point| entry_point.name == self.entry_point && entry_point.stage == wgpu::naga::ShaderStage::Compute)
+            .map(|entry_point| entry_point.workgroup_size)
+            .ok_or_else(|| {
+                let message = format!("the shader has no compute entry point named {}", self.entry_point);
+                ShaderError::new(&self.source.files, &message, Vec::new(), Vec::new())
+            })?;
+
+        let layout_entries: Vec<wgpu::BindGroupLayoutEntry> = self.resources.iter().map(|(binding, resource)| wgpu::BindGroupLayoutEntry {
+            binding: *binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: match resource {
+                Resource::Uniform(_) => wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None
+                },
+                Resource::Storage { read_only, .. } => wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: *read_only },
+                    has_dynamic_offset: false,
+                    min_binding_size: None
+                },
+                Resource::StorageTexture { format, access, .. } => wgpu::BindingType::StorageTexture {
+                    access: *access,
+                    format: *format,
+                    view_dimension: wgpu::TextureViewDimension::D2
+                }
+            },
+            count: None
+        }).collect();
+        let entries: Vec<wgpu::BindGroupEntry> = self.resources.iter().map(|(binding, resource)| wgpu::BindGroupEntry {
+            binding: *binding,
+            resource: match resource {
+                Resource::Uniform(buffer) | Resource::Storage { buffer, .. } => buffer.as_entire_binding(),
+                Resource::StorageTexture { view, .. } => wgpu::BindingResource::TextureView(view)
+            }
+        }).collect();
+
+        let (pipeline, bind_group) = shader::capture_errors(device, || {
+            let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &layout_entries
+            });
+            let bind_group_layouts: Vec<&wgpu::BindGroupLayout> = self.shared_groups.iter().copied().chain([&bind_group_layout]).collect();
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                bind_group_layouts: &bind_group_layouts,
+                ..Default::default()
+            });
+
+            let shader_module = shader::create_shader_module(device, &self.source.code);
+            let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                module: &shader_module,
+                entry_point: self.entry_point,
+                compilation_options: wgpu::PipelineCompilationOptions::default()
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &bind_group_layout,
+                entries: &entries
+            });
+            (pipeline, bind_group)
+        }).map_err(|error| ShaderError::device(self.source.path(), &error))?;
+
+        Ok(ComputePass {
+            pipeline,
+            bind_group,
+            group: self.shared_groups.len() as u32,
+            workgroup_size
+        })
+    }
+}
+
+impl ComputePass {
+    // Runs one invocation per cell of a `size` grid, rounded up to whole workgroups. The shader
+    // has to skip the invocations outside of the grid. `shared` are the bind groups of `shared_group`.
+    pub fn dispatch<'a>(&'a self, compute_pass: &mut wgpu::ComputePass<'a>, shared: &[&'a wgpu::BindGroup], size: [u32; 3]) {
+        let [x, y, z] = self.workgroups(size);
+
+        compute_pass.set_pipeline(&self.pipeline);
+        for (group, bind_group) in shared.iter().enumerate() {
+            compute_pass.set_bind_group(group as u32, bind_group, &[]);
+        }
+        compute_pass.set_bind_group(self.group, &self.bind_group, &[]);
+        compute_pass.dispatch_workgroups(x, y, z);
+    }
+
+    fn workgroups(&self, size: [u32; 3]) -> [u32; 3] {
+        std::array::from_fn(|i| size[i].div_ceil(self.workgroup_size[i]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use common::preprocessor::preprocess;
+
+    use crate::app_state::request_headless_device;
+    use super::ComputeBuilder;
+
+    const SHADER: &str = "
+        @group(0) @binding(0) var<storage, read_write> output: array<u32>;
+
+        @compute @workgroup_size(4, 2)
+        fn main(@builtin(global_invocation_id) id: vec3u) {
+            output[id.x] = id.y;
+        }
+    ";
+
+    #[test]
+    fn reflects_the_workgroup_size() {
+        let (device, _) = pollster::block_on(request_headless_device(true));
+        let source = preprocess(Path::new("test.wgsl"), &[], &[], |_| Ok(SHADER.to_string())).unwrap_or_else(|error| panic!("{error}"));
+        let output = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false
+        });
+
+        let pass = ComputeBuilder::new(&source, "main").storage_buffer(0, &output, false).build(&device).unwrap_or_else(|error| panic!("{error}"));
+        assert_eq!(pass.workgroup_size, [4, 2, 1]);
+        assert_eq!(pass.workgroups([9, 2, 1]), [3, 1, 1]);
+
+        let error = ComputeBuilder::new(&source, "simulate").storage_buffer(0, &output, false).build(&device).err().expect("the entry point is missing");
+        assert!(error.to_string().contains("no compute entry point named simulate"), "{error}");
+    }
+}
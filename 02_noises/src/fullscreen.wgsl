@@ -0,0 +1,8 @@
+// One triangle covering the screen, the pattern of `sky_vs_main` in step 04. Used by the
+// Shadertoy and multipass shaders, which only provide the fragment stage.
+@vertex
+fn vs_main(@builtin(vertex_index) id: u32) -> @builtin(position) vec4f {
+    let x = i32(id) & 2;
+    let y = i32(id) & 1;
+    return vec4f(f32(x) * 4.0 - 1.0, 1.0 - f32(y) * 4.0, 0.0, 1.0);
+}
@@ -1,21 +1,128 @@
 use std::sync::Arc;
 
+use bake::Bake;
+use common::time::{Time, TimeMode};
+use options::{BakeOptions, HeadlessOptions, Options};
 use winit::{event::{Event, WindowEvent}, event_loop::EventLoop, window::WindowBuilder};
 
 mod app_state;
 mod uniform;
+mod options;
+mod shadertoy;
+mod bake;
+mod multipass;
+mod compute;
+mod canvas;
+
 fn main() {
-   pollster::block_on(run());
+    let options = Options::from_args().unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(2);
+    });
+
+    match (&options.headless, &options.bake) {
+        (_, Some(bake)) => pollster::block_on(run_bake(&options, bake)),
+        (Some(headless), None) => pollster::block_on(run_headless(&options, headless)),
+        (None, None) => pollster::block_on(run(&options))
+    }
 }
 
-async fn run() {
+fn create_time(options: &Options, default_mode: TimeMode) -> Time {
+    let mode = options.fixed_step.map_or(default_mode, TimeMode::FixedStep);
+    let mut time = Time::new(mode);
+    time.set_elapsed(options.time.unwrap_or(0.0));
+    time.set_paused(options.paused);
+    time
+}
+
+fn set_palette(app_state: &mut app_state::AppState, options: &Options) {
+    if let Some(palette) = &options.palette {
+        if let Err(error) = app_state.set_palette(palette) {
+            eprintln!("{error}");
+            std::process::exit(2);
+        }
+    }
+    app_state.set_palette_cycle(options.palette_cycle);
+}
+
+async fn run_headless(options: &Options, headless: &HeadlessOptions) {
+    let mut app_state = app_state::AppState::new_headless(options.width, options.height, headless.software).await;
+    app_state.set_time(create_time(options, TimeMode::FixedStep(1.0 / 60.0)));
+    if let Some(path) = &options.params {
+        app_state.set_params_file(path.clone());
+    }
+    app_state.set_constants(options.constants_file.clone(), &options.constants);
+    if let Some(path) = &options.shadertoy {
+        app_state.set_shadertoy(path.clone());
+    }
+    if !options.buffers.is_empty() || options.composite.is_some() {
+        app_state.set_multipass(&options.buffers, options.composite.clone());
+    }
+    if let Some(path) = &options.compute {
+        app_state.set_compute(path.clone());
+    }
+    set_palette(&mut app_state, options);
+    std::fs::create_dir_all(&headless.out).unwrap();
+
+    for frame in 0..headless.frames {
+        app_state.update();
+        app_state.render().unwrap();
+
+        let path = headless.out.join(format!("frame_{frame:04}.png"));
+        app_state.capture().unwrap().save(&path).unwrap();
+        println!("Saved {}", path.display());
+    }
+}
+
+async fn run_bake(options: &Options, bake_options: &BakeOptions) {
+    let (device, queue) = app_state::request_headless_device(bake_options.software).await;
+    let bake = Bake {
+        expression: bake_options.expression.clone(),
+        width: options.width,
+        height: options.height,
+        depth: bake_options.depth,
+        tileable: bake_options.tileable,
+        scale: bake_options.scale,
+        time: options.time.unwrap_or(0.0)
+    };
+
+    let baked = bake.run(&device, &queue).unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(1);
+    });
+    if let Some(dir) = bake_options.out.parent() {
+        std::fs::create_dir_all(dir).unwrap();
+    }
+    if let Err(error) = baked.save(&bake_options.out, bake_options.bits) {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+    println!("Saved {}", bake_options.out.display());
+}
+
+async fn run(options: &Options) {
     let event_loop = EventLoop::new().unwrap();
     let window = WindowBuilder::new()
-    .with_inner_size(winit::dpi::PhysicalSize::new(1600, 900))
+    .with_inner_size(winit::dpi::PhysicalSize::new(options.width, options.height))
     .build(&event_loop).unwrap();
 
     let window = Arc::new(window);
     let mut app_state = app_state::AppState::new(window.clone()).await;
+    app_state.set_time(create_time(options, TimeMode::RealTime));
+    if let Some(path) = &options.params {
+        app_state.set_params_file(path.clone());
+    }
+    app_state.set_constants(options.constants_file.clone(), &options.constants);
+    if let Some(path) = &options.shadertoy {
+        app_state.set_shadertoy(path.clone());
+    }
+    if !options.buffers.is_empty() || options.composite.is_some() {
+        app_state.set_multipass(&options.buffers, options.composite.clone());
+    }
+    if let Some(path) = &options.compute {
+        app_state.set_compute(path.clone());
+    }
+    set_palette(&mut app_state, options);
 
     event_loop.run(move |event, elwt| {
         match event {
@@ -23,15 +130,28 @@ async fn run() {
                 window_id, 
                 event 
             } if window.id() == window_id => {
+                if app_state.input(&event) {
+                    return;
+                }
+
                 match event {
                     WindowEvent::CloseRequested => {
                         elwt.exit();
                     },
+                    WindowEvent::Resized(new_size) => {
+                        app_state.resize(new_size);
+                    },
                     WindowEvent::RedrawRequested => {
                         app_state.update();
                         match app_state.render() {
+                            Ok(_) => {},
+                            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                                app_state.reconfigure();
+                            },
                             Err(wgpu::SurfaceError::OutOfMemory) => { elwt.exit() },
-                            _ => {}
+                            Err(wgpu::SurfaceError::Timeout) => {
+                                eprintln!("Surface timeout, retrying on the next frame");
+                            }
                         }
                     },
                     _ => {}
@@ -0,0 +1,466 @@
+use std::path::PathBuf;
+
+use common::{preprocessor::Preprocessed, shader::{self, ShaderError, ShaderFile}};
+use gpu_layout::UniformLayout;
+use winit::dpi::PhysicalSize;
+use crate::uniform::UniformRaw;
+
+
+// The default composite shader and the bindings header, the fallback when the files on disk are broken
+const SHADERS: &[(&str, &str)] = &[
+    (concat!(env!("CARGO_MANIFEST_DIR"), "/src/multipass/composite.wgsl"), include_str!("multipass/composite.wgsl")),
+    (concat!(env!("CARGO_MANIFEST_DIR"), "/src/multipass/buffers.wgsl"), include_str!("multipass/buffers.wgsl"))
+];
+
+// Bind group of the buffers, after the uniforms
+const BUFFERS_GROUP: u32 = 1;
+// The passes get no `Params`, a shader declaring them fails to build its pipeline
+const PARAMS_GROUP: u32 = 2;
+
+// A feedback buffer given with `--buffer NAME=FILE[,FORMAT][,FILTER]`
+#[derive(Clone, Debug, PartialEq)]
+pub struct BufferConfig {
+    // 0 to 3 for buffers A to D
+    pub slot: usize,
+    pub path: PathBuf,
+    pub format: wgpu::TextureFormat,
+    pub filter: wgpu::FilterMode
+}
+
+impl BufferConfig {
+    pub const NAMES: [&'static str; 4] = ["A", "B", "C", "D"];
+
+    // Buffers are `rgba16float` and filtered linearly unless the options say otherwise
+    pub fn parse(arg: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid --buffer {arg}, expected NAME=FILE[,FORMAT][,FILTER] with NAME from A to D");
+        let (name, value) = arg.split_once('=').ok_or_else(invalid)?;
+        let slot = Self::NAMES.iter().position(|candidate| candidate.eq_ignore_ascii_case(name)).ok_or_else(invalid)?;
+
+        let mut options = value.split(',');
+        let path = PathBuf::from(options.next().unwrap_or_default());
+        if path.as_os_str().is_empty() {
+            return Err(invalid());
+        }
+
+        let mut format = wgpu::TextureFormat::Rgba16Float;
+        let mut filter = wgpu::FilterMode::Linear;
+        for option in options {
+            match option {
+                "rgba8unorm" => format = wgpu::TextureFormat::Rgba8Unorm,
+                "rgba16float" => format = wgpu::TextureFormat::Rgba16Float,
+                "rgba32float" => format = wgpu::TextureFormat::Rgba32Float,
+                "linear" => filter = wgpu::FilterMode::Linear,
+                "nearest" => filter = wgpu::FilterMode::Nearest,
+                _ => return Err(format!("unknown buffer option {option}, expected rgba8unorm, rgba16float, rgba32float, linear or nearest"))
+            }
+        }
+        // Filtering 32 bit float textures needs a feature most adapters lack
+        if format == wgpu::TextureFormat::Rgba32Float && filter == wgpu::FilterMode::Linear {
+            return Err(format!("buffer {name} is rgba32float, which can only be sampled with nearest"));
+        }
+
+        Ok(BufferConfig { slot, path, format, filter })
+    }
+}
+
+// Shadertoy style Buffer A to D: offscreen targets drawn by their own fragment shader every frame,
+// in A to D order, followed by a composite pass that draws to the screen. Each buffer has two
+// textures that swap every frame, so a pass can read what it drew in the previous frame.
+pub struct Multipass {
+    vertex_module: wgpu::ShaderModule,
+    pipeline_layout: wgpu::PipelineLayout,
+    bind_group_layout: wgpu::BindGroupLayout,
+    // One per slot, with the filter of the buffer configured there
+    samplers: Vec<wgpu::Sampler>,
+    // Bound in the slots without a buffer
+    placeholder: wgpu::TextureView,
+    buffers: Vec<FeedbackBuffer>,
+    composite: Pass,
+    // Indexed by the parity and then by the pass, the composite pass comes last
+    bind_groups: [Vec<wgpu::BindGroup>; 2],
+    // Which of the two textures of every buffer is drawn this frame
+    parity: usize
+}
+
+struct FeedbackBuffer {
+    slot: usize,
+    pass: Pass,
+    views: [wgpu::TextureView; 2]
+}
+
+struct Pass {
+    shader: ShaderFile,
+    format: wgpu::TextureFormat,
+    // None while the shader has been broken since startup, the pass is skipped
+    pipeline: Option<wgpu::RenderPipeline>
+}
+
+impl Multipass {
+    pub fn new(device: &wgpu::Device, uniform_layout: &wgpu::BindGroupLayout, buffers: &[BufferConfig], composite: Option<PathBuf>, format: wgpu::TextureFormat, size: PhysicalSize<u32>) -> Self {
+        let mut buffers = buffers.to_vec();
+        buffers.sort_by_key(|buffer| buffer.slot);
+        let filter = |slot: usize| buffers.iter().find(|buffer| buffer.slot == slot).map_or(wgpu::FilterMode::Linear, |buffer| buffer.filter);
+
+        let entries: Vec<wgpu::BindGroupLayoutEntry> = (0..BufferConfig::NAMES.len()).flat_map(|slot| {
+            let filterable = filter(slot) == wgpu::FilterMode::Linear;
+            [
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2 * slot as u32,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false
+                    },
+                    count: None
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2 * slot as u32 + 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(match filterable {
+                        true => wgpu::SamplerBindingType::Filtering,
+                        false => wgpu::SamplerBindingType::NonFiltering
+                    }),
+                    count: None
+                }
+            ]
+        }).collect();
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &entries
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[
+                uniform_layout,
+                &bind_group_layout
+            ],
+            ..Default::default()
+        });
+
+        let samplers = (0..BufferConfig::NAMES.len()).map(|slot| device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: filter(slot),
+            min_filter: filter(slot),
+            ..Default::default()
+        })).collect();
+
+        let placeholder = create_texture(device, wgpu::TextureFormat::Rgba8Unorm, PhysicalSize::new(1, 1))
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let composite = composite.unwrap_or(PathBuf::from(SHADERS[0].0));
+        let mut multipass = Multipass {
+            vertex_module: shader::create_shader_module(device, include_str!("fullscreen.wgsl")),
+            pipeline_layout,
+            bind_group_layout,
+            samplers,
+            placeholder,
+            buffers: buffers.into_iter().map(|buffer| FeedbackBuffer {
+                slot: buffer.slot,
+                pass: Pass::new(buffer.path, buffer.format),
+                views: create_views(device, buffer.format, size)
+            }).collect(),
+            composite: Pass::new(composite, format),
+            bind_groups: [Vec::new(), Vec::new()],
+            parity: 0
+        };
+        multipass.create_bind_groups(device);
+        multipass
+    }
+
+    // Builds every pass, falling back to the embedded copy of a shader that is broken on disk.
+    // The first error is returned, the passes without a working shader are skipped.
+    pub fn load(&mut self, device: &wgpu::Device) -> Result<(), ShaderError> {
+        let mut first_error = None;
+        let passes = self.buffers.iter_mut().map(|buffer| &mut buffer.pass).chain([&mut self.composite]);
+        for pass in passes {
+            if let Err(error) = pass.reload(device, &self.pipeline_layout, &self.vertex_module) {
+                if let Ok(source) = pass.shader.load_embedded() {
+                    pass.pipeline = compile(device, &self.pipeline_layout, &self.vertex_module, &source, pass.format).ok();
+                }
+                first_error.get_or_insert(error);
+            }
+        }
+
+        first_error.map_or(Ok(()), Err)
+    }
+
+    // Rebuilds the passes whose files changed on disk, returning the path of the first one with
+    // its result. A broken pass keeps its previous pipeline.
+    pub fn reload_changed(&mut self, device: &wgpu::Device) -> Option<(PathBuf, Result<(), ShaderError>)> {
+        let mut reloaded: Option<(PathBuf, Result<(), ShaderError>)> = None;
+        let passes = self.buffers.iter_mut().map(|buffer| &mut buffer.pass).chain([&mut self.composite]);
+        for pass in passes {
+            if !pass.shader.changed() {
+                continue;
+            }
+
+            let result = pass.reload(device, &self.pipeline_layout, &self.vertex_module);
+            if reloaded.as_ref().is_none_or(|(_, previous)| previous.is_ok() && result.is_err()) {
+                reloaded = Some((pass.shader.path.clone(), result));
+            }
+        }
+
+        reloaded
+    }
+
+    // The buffers start out black at the new size
+    pub fn resize(&mut self, device: &wgpu::Device, size: PhysicalSize<u32>) {
+        for buffer in &mut self.buffers {
+            buffer.views = create_views(device, buffer.pass.format, size);
+        }
+        self.create_bind_groups(device);
+    }
+
+    // Swaps the textures of the buffers, called once per frame before rendering
+    pub fn advance(&mut self) {
+        self.parity = 1 - self.parity;
+    }
+
+    pub fn render_buffers(&self, encoder: &mut wgpu::CommandEncoder, uniform_bind_group: &wgpu::BindGroup) {
+        for (index, buffer) in self.buffers.iter().enumerate() {
+            let Some(pipeline) = &buffer.pass.pipeline else {
+                continue;
+            };
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[
+                    Some(
+                        wgpu::RenderPassColorAttachment {
+                            view: &buffer.views[self.parity],
+                            resolve_target: None,
+                            ops: wgpu::Operations{
+                                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                                store: wgpu::StoreOp::Store
+                            }
+                        }
+                    )
+                ],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, uniform_bind_group, &[]);
+            render_pass.set_bind_group(BUFFERS_GROUP, &self.bind_groups[self.parity][index], &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+
+    // The composite pass, drawn into the frame after `render_buffers`
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, uniform_bind_group: &'a wgpu::BindGroup) {
+        if let Some(pipeline) = &self.composite.pipeline {
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, uniform_bind_group, &[]);
+            render_pass.set_bind_group(BUFFERS_GROUP, &self.bind_groups[self.parity][self.buffers.len()], &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+
+    fn create_bind_groups(&mut self, device: &wgpu::Device) {
+        self.bind_groups = [0, 1].map(|parity| (0..=self.buffers.len()).map(|pass| {
+            let views: Vec<&wgpu::TextureView> = (0..BufferConfig::NAMES.len()).map(|slot| {
+                match self.buffers.iter().position(|buffer| buffer.slot == slot) {
+                    // Drawn earlier this frame, or still holding the previous frame
+                    Some(index) if index < pass => &self.buffers[index].views[parity],
+                    Some(index) => &self.buffers[index].views[1 - parity],
+                    None => &self.placeholder
+                }
+            }).collect();
+
+            let entries: Vec<wgpu::BindGroupEntry> = views.iter().zip(&self.samplers).enumerate().flat_map(|(slot, (view, sampler))| [
+                wgpu::BindGroupEntry {
+                    binding: 2 * slot as u32,
+                    resource: wgpu::BindingResource::TextureView(view)
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2 * slot as u32 + 1,
+                    resource: wgpu::BindingResource::Sampler(sampler)
+                }
+            ]).collect();
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &self.bind_group_layout,
+                entries: &entries
+            })
+        }).collect());
+    }
+}
+
+impl Pass {
+    fn new(path: PathBuf, format: wgpu::TextureFormat) -> Self {
+        let shader = ShaderFile::new(path, SHADERS)
+            .include_dir(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/src/multipass")))
+            .include_dir(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../shaders")))
+            .define("ANIMATED_NOISE");
+
+        Pass {
+            shader,
+            format,
+            pipeline: None
+        }
+    }
+
+    fn reload(&mut self, device: &wgpu::Device, layout: &wgpu::PipelineLayout, vertex_module: &wgpu::ShaderModule) -> Result<(), ShaderError> {
+        let source = self.shader.load()?;
+        self.pipeline = Some(compile(device, layout, vertex_module, &source, self.format)?);
+        Ok(())
+    }
+}
+
+fn compile(device: &wgpu::Device, layout: &wgpu::PipelineLayout, vertex_module: &wgpu::ShaderModule, source: &Preprocessed, format: wgpu::TextureFormat) -> Result<wgpu::RenderPipeline, ShaderError> {
+    let (pipeline, _) = shader::compile(device, source, &UniformRaw::layout(), PARAMS_GROUP, |fragment_module| {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: vertex_module,
+                entry_point: "vs_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[]
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: fragment_module,
+                entry_point: "fs_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[
+                    // No blending, which 32 bit float targets don't support
+                    Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::all()
+                    })
+                ]
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None
+        })
+    })?;
+
+    Ok(pipeline)
+}
+
+fn create_texture(device: &wgpu::Device, format: wgpu::TextureFormat, size: PhysicalSize<u32>) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: 1
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        view_formats: &[],
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+    })
+}
+
+fn create_views(device: &wgpu::Device, format: wgpu::TextureFormat, size: PhysicalSize<u32>) -> [wgpu::TextureView; 2] {
+    [0, 1].map(|_| create_texture(device, format, size).create_view(&wgpu::TextureViewDescriptor::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use common::{layout, preprocessor::preprocess, shader, target::OffscreenTarget};
+    use gpu_layout::UniformLayout;
+    use winit::dpi::PhysicalSize;
+    use crate::{app_state::request_headless_device, uniform::{Uniform, UniformRaw}};
+    use super::{BufferConfig, Multipass};
+
+    #[test]
+    fn parses_buffer_options() {
+        assert_eq!(BufferConfig::parse("b=trails.wgsl,rgba32float,nearest"), Ok(BufferConfig {
+            slot: 1,
+            path: PathBuf::from("trails.wgsl"),
+            format: wgpu::TextureFormat::Rgba32Float,
+            filter: wgpu::FilterMode::Nearest
+        }));
+        assert_eq!(BufferConfig::parse("A=trails.wgsl").map(|buffer| (buffer.format, buffer.filter)), Ok((wgpu::TextureFormat::Rgba16Float, wgpu::FilterMode::Linear)));
+
+        assert!(BufferConfig::parse("E=trails.wgsl").is_err());
+        assert!(BufferConfig::parse("A=").is_err());
+        assert_eq!(BufferConfig::parse("A=trails.wgsl,bgra8"), Err("unknown buffer option bgra8, expected rgba8unorm, rgba16float, rgba32float, linear or nearest".to_string()));
+        assert_eq!(BufferConfig::parse("A=trails.wgsl,rgba32float"), Err("buffer A is rgba32float, which can only be sampled with nearest".to_string()));
+    }
+
+    #[test]
+    fn uniform_raw_matches_the_pass_shaders() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        for name in ["composite.wgsl", "trails.wgsl"] {
+            let source = preprocess(&dir.join("src/multipass").join(name), &[dir.join("../shaders")], &["ANIMATED_NOISE".to_string()], |path| std::fs::read_to_string(path))
+                .unwrap_or_else(|error| panic!("{error}"));
+            let module = shader::validate(&source).unwrap_or_else(|error| panic!("{error}"));
+
+            if let Err(error) = layout::check(&source, &module, "UniformParameters", &UniformRaw::layout()) {
+                panic!("{error}");
+            }
+        }
+    }
+
+    #[test]
+    fn buffers_read_what_they_drew_the_frame_before() {
+        // Adds a quarter to what buffer A held after the previous frame
+        let path = std::env::temp_dir().join(format!("step_02_accumulate_{}.wgsl", std::process::id()));
+        std::fs::write(&path, "#include \"buffers.wgsl\"
+
+@fragment
+fn fs_main(@builtin(position) pos: vec4f) -> @location(0) vec4f {
+    return textureSample(buffer_a, buffer_a_sampler, bufferUv(pos)) + vec4f(0.25);
+}
+").unwrap();
+
+        let (device, queue) = pollster::block_on(request_headless_device(true));
+        let (width, height) = (4, 4);
+        let mut uniform = Uniform::new(&device, width, height);
+        let target = OffscreenTarget::new(&device, width, height);
+        let buffers = [BufferConfig { slot: 0, path: path.clone(), format: wgpu::TextureFormat::Rgba16Float, filter: wgpu::FilterMode::Nearest }];
+        let mut multipass = Multipass::new(&device, &uniform.bind_group_layout, &buffers, None, OffscreenTarget::FORMAT, PhysicalSize::new(width, height));
+        let loaded = multipass.load(&device);
+        std::fs::remove_file(&path).unwrap();
+        loaded.unwrap_or_else(|error| panic!("{error}"));
+
+        let frames: Vec<u8> = (0..2).map(|_| {
+            multipass.advance();
+            uniform.update(&queue);
+
+            let view = target.texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+            multipass.render_buffers(&mut encoder, &uniform.bind_group);
+            {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: None,
+                    color_attachments: &[
+                        Some(
+                            wgpu::RenderPassColorAttachment {
+                                view: &view,
+                                resolve_target: None,
+                                ops: wgpu::Operations {
+                                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                                    store: wgpu::StoreOp::Store
+                                }
+                            }
+                        )
+                    ],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None
+                });
+                multipass.draw(&mut render_pass, &uniform.bind_group);
+            }
+            queue.submit(std::iter::once(encoder.finish()));
+            target.read_image(&device, &queue).get_pixel(1, 2)[0]
+        }).collect();
+
+        // The composite shows buffer A as it is, encoded by the sRGB target
+        let srgb = |linear: f32| ((1.055 * linear.powf(1.0 / 2.4) - 0.055) * 255.0).round() as u8;
+        for (frame, expected) in frames.iter().zip([srgb(0.25), srgb(0.5)]) {
+            assert!(frame.abs_diff(expected) <= 1, "{frames:?}, expected {expected}");
+        }
+    }
+}
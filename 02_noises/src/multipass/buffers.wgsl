@@ -0,0 +1,29 @@
+// Bindings of the multipass shaders, included with `#include "buffers.wgsl"`
+//
+// A pass reads the buffers drawn before it in A to D order as they are this frame, and itself
+// and the buffers after it as they were at the end of the previous frame. The composite pass
+// runs last and sees all of them from this frame. Buffers that are not configured read as black.
+
+struct UniformParameters {
+    resolution: vec2f,
+    mouse: vec2f,
+    time: f32,
+    pixel_ratio: f32,
+    frame: u32
+}
+
+@group(0) @binding(0) var<uniform> uniforms: UniformParameters;
+
+@group(1) @binding(0) var buffer_a: texture_2d<f32>;
+@group(1) @binding(1) var buffer_a_sampler: sampler;
+@group(1) @binding(2) var buffer_b: texture_2d<f32>;
+@group(1) @binding(3) var buffer_b_sampler: sampler;
+@group(1) @binding(4) var buffer_c: texture_2d<f32>;
+@group(1) @binding(5) var buffer_c_sampler: sampler;
+@group(1) @binding(6) var buffer_d: texture_2d<f32>;
+@group(1) @binding(7) var buffer_d_sampler: sampler;
+
+// The coordinates to sample the buffers with at a fragment position
+fn bufferUv(pos: vec4f) -> vec2f {
+    return pos.xy / uniforms.resolution;
+}
@@ -0,0 +1,7 @@
+// The final pass when no --composite shader is given, shows buffer A as it is
+#include "buffers.wgsl"
+
+@fragment
+fn fs_main(@builtin(position) pos: vec4f) -> @location(0) vec4f {
+    return vec4f(textureSample(buffer_a, buffer_a_sampler, bufferUv(pos)).rgb, 1.0);
+}
@@ -0,0 +1,16 @@
+// Example feedback buffer: a blob of noise circling the center that leaves a fading trail,
+// run with `--buffer A=02_noises/src/multipass/trails.wgsl`
+#include "buffers.wgsl"
+#include "noise.wgsl"
+
+@fragment
+fn fs_main(@builtin(position) pos: vec4f) -> @location(0) vec4f {
+    let previous = textureSample(buffer_a, buffer_a_sampler, bufferUv(pos));
+
+    let uv = (pos.xy - uniforms.resolution / 2.0) / uniforms.resolution.y;
+    let center = 0.3 * vec2f(cos(uniforms.time * 2.0), sin(uniforms.time * 3.0));
+    let blob = 1.0 - smoothstep(0.02, 0.08, length(uv - center));
+    let color = blob * (0.5 + 0.5 * vec3f(perlinNoise(uv * 8.0), perlinNoise(uv * 8.0 + 3.1), 1.0));
+
+    return max(vec4f(color, 1.0), previous * 0.97);
+}
@@ -0,0 +1,196 @@
+use std::path::PathBuf;
+
+use common::constants::parse_constant;
+use crate::multipass::BufferConfig;
+
+
+pub struct Options {
+    pub width: u32,
+    pub height: u32,
+    pub time: Option<f32>,
+    pub fixed_step: Option<f32>,
+    pub paused: bool,
+    pub params: Option<PathBuf>,
+    pub constants_file: Option<PathBuf>,
+    pub constants: Vec<(String, f64)>,
+    pub shadertoy: Option<PathBuf>,
+    pub palette: Option<String>,
+    pub palette_cycle: Option<f32>,
+    pub buffers: Vec<BufferConfig>,
+    pub composite: Option<PathBuf>,
+    pub compute: Option<PathBuf>,
+    pub headless: Option<HeadlessOptions>,
+    pub bake: Option<BakeOptions>
+}
+
+pub struct HeadlessOptions {
+    pub frames: u32,
+    pub out: PathBuf,
+    pub software: bool
+}
+
+pub struct BakeOptions {
+    pub expression: String,
+    pub depth: Option<u32>,
+    pub tileable: bool,
+    pub scale: f32,
+    pub bits: u32,
+    pub out: PathBuf,
+    pub software: bool
+}
+
+impl Options {
+    pub fn from_args() -> Result<Self, String> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut size = None;
+        let mut headless = false;
+        let mut frames = 1;
+        let mut out = None;
+        let mut time = None;
+        let mut fixed_step = None;
+        let mut paused = false;
+        let mut software = false;
+        let mut params = None;
+        let mut constants_file = None;
+        let mut constants = Vec::new();
+        let mut shadertoy = None;
+        let mut palette = None;
+        let mut palette_cycle = None;
+        let mut buffers: Vec<BufferConfig> = Vec::new();
+        let mut composite = None;
+        let mut compute = None;
+        let mut bake = None;
+        let mut depth = None;
+        let mut tileable = false;
+        let mut scale = 8.0;
+        let mut bits = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value for {arg}"));
+            match arg.as_str() {
+                "--headless" => headless = true,
+                "--frames" => {
+                    frames = value()?.parse().map_err(|err| format!("invalid --frames: {err}"))?;
+                },
+                "--size" => {
+                    size = Some(parse_size(&value()?)?);
+                },
+                "--out" => out = Some(PathBuf::from(value()?)),
+                "--time" => {
+                    time = Some(value()?.parse().map_err(|err| format!("invalid --time: {err}"))?);
+                },
+                "--fixed-step" => {
+                    fixed_step = Some(parse_fixed_step(&value()?)?);
+                },
+                "--paused" => paused = true,
+                "--software" => software = true,
+                "--params" => params = Some(PathBuf::from(value()?)),
+                "--constants" => constants_file = Some(PathBuf::from(value()?)),
+                "--constant" => constants.push(parse_constant(&value()?)?),
+                "--shadertoy" => shadertoy = Some(PathBuf::from(value()?)),
+                "--palette" => palette = Some(value()?),
+                "--palette-cycle" => {
+                    palette_cycle = Some(value()?.parse().ok().filter(|seconds: &f32| *seconds > 0.0).ok_or("invalid --palette-cycle, expected a positive number of seconds")?);
+                },
+                "--buffer" => {
+                    let buffer = BufferConfig::parse(&value()?)?;
+                    if buffers.iter().any(|other| other.slot == buffer.slot) {
+                        return Err(format!("buffer {} is given twice", BufferConfig::NAMES[buffer.slot]));
+                    }
+                    buffers.push(buffer);
+                },
+                "--composite" => composite = Some(PathBuf::from(value()?)),
+                "--compute" => compute = Some(PathBuf::from(value()?)),
+                "--bake" => bake = Some(value()?),
+                "--depth" => {
+                    depth = Some(value()?.parse().ok().filter(|depth| *depth > 0).ok_or("invalid --depth, expected a positive number")?);
+                },
+                "--tileable" => tileable = true,
+                "--scale" => {
+                    scale = value()?.parse().map_err(|err| format!("invalid --scale: {err}"))?;
+                },
+                "--bits" => {
+                    bits = Some(value()?.parse().map_err(|err| format!("invalid --bits: {err}"))?);
+                },
+                _ => return Err(format!("unknown argument {arg}\n\n{}", Self::usage()))
+            }
+        }
+
+        let scenes = [shadertoy.is_some(), !buffers.is_empty() || composite.is_some(), compute.is_some()];
+        if scenes.iter().filter(|given| **given).count() > 1 {
+            return Err("only one of --shadertoy, --buffer/--composite and --compute can be given".to_string());
+        }
+        if scenes.contains(&true) && !constants.is_empty() {
+            return Err("--constant only applies to the noise shader".to_string());
+        }
+
+        let bake = match bake {
+            Some(_) if headless => return Err("--bake and --headless cannot be combined".to_string()),
+            Some(_) if tileable && depth.is_some() => return Err("--tileable only works for 2D bakes, without --depth".to_string()),
+            Some(expression) => {
+                let out = out.take().unwrap_or(PathBuf::from("output/noise.png"));
+                let bits = match bits {
+                    Some(bits @ (8 | 16 | 32)) => bits,
+                    Some(bits) => return Err(format!("invalid --bits {bits}, expected 8, 16 or 32")),
+                    None if out.extension().is_some_and(|extension| extension == "exr") => 32,
+                    None => 8
+                };
+                Some(BakeOptions { expression, depth, tileable, scale, bits, out, software })
+            },
+            None => None
+        };
+        let (width, height) = size.unwrap_or(match bake {
+            Some(_) => (512, 512),
+            None => (1600, 900)
+        });
+
+        Ok(Options {
+            width,
+            height,
+            time,
+            fixed_step,
+            paused,
+            params,
+            constants_file,
+            constants,
+            shadertoy,
+            palette,
+            palette_cycle,
+            buffers,
+            composite,
+            compute,
+            headless: headless.then(|| HeadlessOptions { frames, out: out.unwrap_or(PathBuf::from("output")), software }),
+            bake
+        })
+    }
+
+    pub fn usage() -> &'static str {
+        "Usage: [--size WIDTHxHEIGHT] [--time SECONDS] [--fixed-step SECONDS] [--paused] [--params FILE] [--constants FILE] [--constant NAME=VALUE]...
+       [--shadertoy FILE] [--palette NAME|FILE] [--palette-cycle SECONDS] [--buffer NAME=FILE[,FORMAT][,FILTER]]... [--composite FILE] [--compute FILE]
+       [--headless [--frames N] [--out DIR] [--software]]
+       --bake EXPRESSION [--size WIDTHxHEIGHT] [--depth N] [--tileable] [--scale UNITS] [--bits 8|16|32] [--time SECONDS] [--out FILE] [--software]"
+    }
+}
+
+fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid --size {size}, expected WIDTHxHEIGHT");
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    let width: u32 = width.parse().map_err(|_| invalid())?;
+    let height: u32 = height.parse().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+
+    Ok((width, height))
+}
+
+// A number of seconds above 0, anything else would stop or reverse the time
+fn parse_fixed_step(step: &str) -> Result<f32, String> {
+    match step.parse::<f32>() {
+        Ok(seconds) if seconds.is_finite() && seconds > 0.0 => Ok(seconds),
+        _ => Err(format!("invalid --fixed-step {step}, expected a number of seconds above 0"))
+    }
+}
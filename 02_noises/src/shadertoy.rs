@@ -0,0 +1,351 @@
+use std::{borrow::Cow, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+
+use common::{layout, preprocessor::{Preprocessed, SourceFile}, shader::{self, ShaderError, ShaderFile}, time::Time};
+use gpu_layout::UniformLayout;
+use wgpu::naga;
+use winit::{dpi::PhysicalSize, event::{ElementState, MouseButton, WindowEvent}};
+
+
+// Runs a Shadertoy style `mainImage` over the whole screen. GLSL shaders are written exactly
+// like on Shadertoy, WGSL ones as `fn mainImage(fragCoord: vec2f) -> vec4f`.
+// Texture channels (`iChannel0..3`) are not supported.
+pub struct Shadertoy {
+    shader: ShaderFile,
+    language: Language,
+    format: wgpu::TextureFormat,
+    vertex_module: wgpu::ShaderModule,
+    pipeline_layout: wgpu::PipelineLayout,
+    pipeline: wgpu::RenderPipeline,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    mouse: Mouse,
+    time: f32,
+    time_delta: f32,
+    frame: i32
+}
+
+#[derive(Clone, Copy)]
+enum Language {
+    Glsl,
+    Wgsl
+}
+
+impl Language {
+    // `.wgsl` files are WGSL, anything else is taken as Shadertoy GLSL
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("wgsl") => Language::Wgsl,
+            _ => Language::Glsl
+        }
+    }
+
+    // Code placed before and after the user's shader, with the paths they are loaded from
+    fn wrapper(self) -> [(&'static str, &'static str); 2] {
+        match self {
+            Language::Glsl => [
+                (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shadertoy/prelude.glsl"), include_str!("shadertoy/prelude.glsl")),
+                (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shadertoy/main.glsl"), include_str!("shadertoy/main.glsl"))
+            ],
+            Language::Wgsl => [
+                (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shadertoy/prelude.wgsl"), include_str!("shadertoy/prelude.wgsl")),
+                (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shadertoy/main.wgsl"), include_str!("shadertoy/main.wgsl"))
+            ]
+        }
+    }
+
+    // Shadertoy's template for a new shader, used until the file on disk compiles
+    fn default_shader(self) -> (&'static str, &'static str) {
+        match self {
+            Language::Glsl => (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shadertoy/default.glsl"), include_str!("shadertoy/default.glsl")),
+            Language::Wgsl => (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shadertoy/default.wgsl"), include_str!("shadertoy/default.wgsl"))
+        }
+    }
+
+    fn entry_point(self) -> &'static str {
+        match self {
+            Language::Glsl => "main",
+            Language::Wgsl => "fs_main"
+        }
+    }
+}
+
+#[derive(UniformLayout)]
+pub struct ShadertoyRaw {
+    resolution: [f32; 3],
+    time: f32,
+    mouse: [f32; 4],
+    date: [f32; 4],
+    time_delta: f32,
+    frame: i32
+}
+
+// Mouse state in window coordinates, flipped to Shadertoy's bottom left origin on upload
+#[derive(Default)]
+struct Mouse {
+    cursor: [f32; 2],
+    position: [f32; 2],
+    click: [f32; 2],
+    down: bool,
+    clicked: bool
+}
+
+impl Shadertoy {
+    pub fn new(device: &wgpu::Device, path: PathBuf, format: wgpu::TextureFormat) -> Self {
+        let language = Language::from_path(&path);
+
+        let vertex_module = shader::create_shader_module(device, include_str!("fullscreen.wgsl"));
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: ShadertoyRaw::SIZE as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None
+                    },
+                    count: None
+                }
+            ]
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding()
+                }
+            ]
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[
+                &bind_group_layout
+            ],
+            ..Default::default()
+        });
+
+        let (default_path, default_source) = language.default_shader();
+        let default_source = wrap(language, PathBuf::from(default_path), default_source.to_string());
+        let pipeline = create_pipeline(device, &pipeline_layout, &vertex_module, &default_source, language, format)
+            .unwrap_or_else(|error| panic!("the default Shadertoy shader is broken:\n{error}"));
+
+        Shadertoy {
+            shader: ShaderFile::new(path, &[]),
+            language,
+            format,
+            vertex_module,
+            pipeline_layout,
+            pipeline,
+            buffer,
+            bind_group,
+            mouse: Mouse::default(),
+            time: 0.0,
+            time_delta: 0.0,
+            frame: 0
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.shader.path
+    }
+
+    pub fn changed(&mut self) -> bool {
+        self.shader.changed()
+    }
+
+    // Rebuilds the pipeline from the file on disk, the previous one is kept on failure
+    pub fn reload(&mut self, device: &wgpu::Device) -> Result<(), ShaderError> {
+        let source = std::fs::read_to_string(&self.shader.path)
+            .map_err(|error| ShaderError::unreadable(&self.shader.path, &error))?;
+        let source = wrap(self.language, self.shader.path.clone(), source);
+
+        self.pipeline = create_pipeline(device, &self.pipeline_layout, &self.vertex_module, &source, self.language, self.format)?;
+        Ok(())
+    }
+
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.mouse.cursor = [position.x as f32, position.y as f32];
+                if self.mouse.down {
+                    self.mouse.position = self.mouse.cursor;
+                }
+            },
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                self.mouse.down = *state == ElementState::Pressed;
+                if self.mouse.down {
+                    self.mouse.clicked = true;
+                    self.mouse.click = self.mouse.cursor;
+                    self.mouse.position = self.mouse.cursor;
+                }
+            },
+            _ => return false
+        }
+
+        true
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, time: &Time, size: PhysicalSize<u32>) {
+        self.time_delta = if self.frame == 0 { 0.0 } else { time.elapsed() - self.time };
+        self.time = time.elapsed();
+
+        queue.write_buffer(&self.buffer, 0, &self.as_raw(size).to_bytes());
+
+        self.mouse.clicked = false;
+        self.frame += 1;
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    pub fn as_raw(&self, size: PhysicalSize<u32>) -> ShadertoyRaw {
+        let height = size.height as f32;
+        let mouse = &self.mouse;
+
+        // Like on Shadertoy the click position is negated once the button is released,
+        // and its y only stays positive on the frame of the click
+        let click_x = if mouse.down { mouse.click[0] } else { -mouse.click[0] };
+        let click_y = if mouse.clicked { height - mouse.click[1] } else { -(height - mouse.click[1]) };
+
+        ShadertoyRaw {
+            resolution: [size.width as f32, height, 1.0],
+            time: self.time,
+            mouse: [mouse.position[0], height - mouse.position[1], click_x, click_y],
+            date: date(),
+            time_delta: self.time_delta,
+            frame: self.frame
+        }
+    }
+}
+
+fn wrap(language: Language, path: PathBuf, source: String) -> Preprocessed {
+    let [(prelude_path, prelude), (main_path, main)] = language.wrapper();
+
+    Preprocessed::concat(vec![
+        SourceFile { path: PathBuf::from(prelude_path), source: prelude.to_string() },
+        SourceFile { path, source },
+        SourceFile { path: PathBuf::from(main_path), source: main.to_string() }
+    ])
+}
+
+fn create_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, vertex_module: &wgpu::ShaderModule, source: &Preprocessed, language: Language, format: wgpu::TextureFormat) -> Result<wgpu::RenderPipeline, ShaderError> {
+    let shader_source = match language {
+        Language::Glsl => wgpu::ShaderSource::Glsl {
+            shader: Cow::Borrowed(&source.code),
+            stage: naga::ShaderStage::Fragment,
+            defines: Default::default()
+        },
+        Language::Wgsl => wgpu::ShaderSource::Wgsl(Cow::Borrowed(&source.code))
+    };
+    validate(source, language)?;
+
+    shader::capture_errors(device, || {
+        let fragment_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: shader_source
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: vertex_module,
+                entry_point: "vs_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[]
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &fragment_module,
+                entry_point: language.entry_point(),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::all()
+                    })
+                ]
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None
+        })
+    }).map_err(|error| ShaderError::device(&source.files[1].path, &error))
+}
+
+fn validate(source: &Preprocessed, language: Language) -> Result<(), ShaderError> {
+    let module = match language {
+        Language::Glsl => validate_glsl(source)?,
+        Language::Wgsl => shader::validate(source)?
+    };
+
+    layout::check(source, &module, "Shadertoy", &ShadertoyRaw::layout())
+}
+
+// Parses the GLSL with naga's frontend, so errors point into the user's file like the WGSL ones
+fn validate_glsl(source: &Preprocessed) -> Result<naga::Module, ShaderError> {
+    let options = naga::front::glsl::Options::from(naga::ShaderStage::Fragment);
+    let module = naga::front::glsl::Frontend::default().parse(&options, &source.code).map_err(|error| {
+        let messages: Vec<String> = error.errors.iter().map(|error| error.kind.to_string()).collect();
+        let labels = shader::map_labels(source, error.errors.iter().zip(&messages).map(|(error, message)| (error.meta, message.as_str())));
+        let message = messages.first().map_or("invalid GLSL", String::as_str);
+        ShaderError::new(&source.files, message, labels, Vec::new())
+    })?;
+
+    shader::validate_module(source, &module)?;
+
+    Ok(module)
+}
+
+// Year, month (from 0), day and seconds since midnight, in UTC
+fn date() -> [f32; 4] {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let days = (since_epoch.as_secs() / 86400) as i64;
+    let seconds = since_epoch.as_secs_f64() % 86400.0;
+
+    // Civil date from the days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    [year as f32, (month - 1) as f32, day as f32, seconds as f32]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{validate, wrap, Language};
+
+    #[test]
+    fn shadertoy_raw_matches_the_preludes() {
+        for language in [Language::Glsl, Language::Wgsl] {
+            let (path, source) = language.default_shader();
+            if let Err(error) = validate(&wrap(language, PathBuf::from(path), source.to_string()), language) {
+                panic!("{error}");
+            }
+        }
+    }
+}
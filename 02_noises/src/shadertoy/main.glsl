@@ -0,0 +1,9 @@
+
+layout(location = 0) out vec4 shadertoy_color;
+
+void main() {
+    vec4 color = vec4(0.0, 0.0, 0.0, 1.0);
+    // Shadertoy puts the origin in the bottom left corner
+    mainImage(color, vec2(gl_FragCoord.x, iResolution.y - gl_FragCoord.y));
+    shadertoy_color = vec4(color.rgb, 1.0);
+}
@@ -0,0 +1,14 @@
+
+@fragment
+fn fs_main(@builtin(position) pos: vec4f) -> @location(0) vec4f {
+    iResolution = shadertoy.iResolution;
+    iTime = shadertoy.iTime;
+    iMouse = shadertoy.iMouse;
+    iDate = shadertoy.iDate;
+    iTimeDelta = shadertoy.iTimeDelta;
+    iFrame = shadertoy.iFrame;
+
+    // Shadertoy puts the origin in the bottom left corner
+    let color = mainImage(vec2f(pos.x, iResolution.y - pos.y));
+    return vec4f(color.rgb, 1.0);
+}
@@ -0,0 +1,11 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform Shadertoy {
+    vec3 iResolution;
+    float iTime;
+    vec4 iMouse;
+    vec4 iDate;
+    float iTimeDelta;
+    int iFrame;
+};
+
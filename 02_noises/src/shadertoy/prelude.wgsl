@@ -0,0 +1,18 @@
+struct Shadertoy {
+    iResolution: vec3f,
+    iTime: f32,
+    iMouse: vec4f,
+    iDate: vec4f,
+    iTimeDelta: f32,
+    iFrame: i32
+}
+
+@group(0) @binding(0) var<uniform> shadertoy: Shadertoy;
+
+var<private> iResolution: vec3f;
+var<private> iTime: f32;
+var<private> iMouse: vec4f;
+var<private> iDate: vec4f;
+var<private> iTimeDelta: f32;
+var<private> iFrame: i32;
+
@@ -0,0 +1,52 @@
+//! Golden-image tests, see `common::golden`.
+//!
+//! Run `UPDATE_GOLDEN=1 cargo test` to regenerate the references after an intended change.
+
+use common::golden::Golden;
+
+
+fn golden() -> Golden {
+    Golden::new(env!("CARGO_BIN_EXE_step_02"), env!("CARGO_MANIFEST_DIR"), env!("CARGO_TARGET_TMPDIR"))
+        // Values tweaked while running the app are saved next to the shader, the tests use the defaults
+        .saved_to("--params", "test.params")
+        .saved_to("--constants", "test.constants")
+}
+
+#[test]
+fn domain_warp_at_one_second() {
+    golden().check("domain_warp_1s", 1.0, &[]);
+}
+
+#[test]
+fn domain_warp_at_two_and_a_half_seconds() {
+    golden().check("domain_warp_2_5s", 2.5, &[]);
+}
+
+#[test]
+fn shadertoy_glsl_template() {
+    let shader = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shadertoy/default.glsl");
+    golden().check("shadertoy_glsl_1s", 1.0, &["--shadertoy", shader]);
+}
+
+#[test]
+fn shadertoy_wgsl_template() {
+    let shader = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shadertoy/default.wgsl");
+    golden().check("shadertoy_wgsl_1s", 1.0, &["--shadertoy", shader]);
+}
+
+#[test]
+fn pipeline_constants() {
+    golden().check("pipeline_constants_1s", 1.0, &["--constant", "warp_strength=2.5", "--constant", "warp_seed=7"]);
+}
+
+#[test]
+fn multipass_trails() {
+    let buffer = concat!("A=", env!("CARGO_MANIFEST_DIR"), "/src/multipass/trails.wgsl");
+    golden().check("multipass_trails_30_frames", 1.0, &["--buffer", buffer, "--frames", "30"]);
+}
+
+#[test]
+fn compute_canvas() {
+    let shader = concat!(env!("CARGO_MANIFEST_DIR"), "/src/canvas/smear.wgsl");
+    golden().check("compute_canvas_10_frames", 1.0, &["--compute", shader, "--frames", "10"]);
+}
@@ -1,5 +1,5 @@
-use bytemuck::NoUninit;
 use glam::{Mat4, Vec3};
+use gpu_layout::UniformLayout;
 
 
 pub struct Camera {
@@ -7,6 +7,8 @@ pub struct Camera {
     aspect_ratio: f32,
     near: f32,
     far: f32,
+    // The position at time 0, the camera circles the origin from there
+    start: Vec3,
     position: Vec3,
     up: Vec3,
     direction: Vec3
@@ -19,12 +21,17 @@ impl Camera {
             aspect_ratio,
             near,
             far,
+            start: position,
             position,
             up,
             direction
         }
     }
 
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        self.aspect_ratio = aspect_ratio;
+    }
+
     pub fn view_matrix(&self) -> Mat4 {
         Mat4::look_to_rh(self.position, self.direction, self.up)
     }
@@ -33,9 +40,10 @@ impl Camera {
         Mat4::perspective_rh(f32::to_radians(self.fov), self.aspect_ratio, self.near, self.far)
     }
 
+    // Places the camera for the shader time, so jumping to a time shows the same view as playing up to it
     pub fn update(&mut self, time: f32) {
         let rotation_matrix = Mat4::from_rotation_y(time * 0.5);
-        self.position = rotation_matrix.transform_vector3(self.position);
+        self.position = rotation_matrix.transform_vector3(self.start);
         self.direction = Vec3::new(0.0, 0.0, 0.0) - self.position;
     }
 
@@ -47,8 +55,7 @@ impl Camera {
     }
 }
 
-#[repr(C)]
-#[derive(Clone, Copy, NoUninit)]
+#[derive(UniformLayout)]
 pub struct CameraRaw {
     view_matrix: [f32; 16],
     perspective_matrix: [f32; 16]
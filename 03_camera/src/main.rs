@@ -1,24 +1,69 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
+use common::time::{Time, TimeMode};
+use options::{HeadlessOptions, Options};
 use winit::{event::{Event, WindowEvent}, event_loop::EventLoop, window::WindowBuilder};
 
 mod app_state;
 mod uniform;
 mod camera;
 mod vertex;
+mod options;
 
 fn main() {
-   pollster::block_on(run());
+    let options = Options::from_args().unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(2);
+    });
+
+    match options.headless {
+        Some(ref headless) => pollster::block_on(run_headless(&options, headless)),
+        None => pollster::block_on(run(&options))
+    }
+}
+
+fn model_path(options: &Options) -> PathBuf {
+    options.model.clone().unwrap_or_else(vertex::default_model)
+}
+
+fn create_time(options: &Options, default_mode: TimeMode) -> Time {
+    let mode = options.fixed_step.map_or(default_mode, TimeMode::FixedStep);
+    let mut time = Time::new(mode);
+    time.set_elapsed(options.time.unwrap_or(0.0));
+    time.set_paused(options.paused);
+    time
+}
+
+async fn run_headless(options: &Options, headless: &HeadlessOptions) {
+    let mut app_state = app_state::AppState::new_headless(options.width, options.height, headless.software, &model_path(options)).await;
+    app_state.set_time(create_time(options, TimeMode::FixedStep(1.0 / 60.0)));
+    if let Some(path) = &options.params {
+        app_state.set_params_file(path.clone());
+    }
+    std::fs::create_dir_all(&headless.out).unwrap();
+
+    for frame in 0..headless.frames {
+        app_state.update();
+        app_state.render().unwrap();
+
+        let path = headless.out.join(format!("frame_{frame:04}.png"));
+        app_state.capture().unwrap().save(&path).unwrap();
+        println!("Saved {}", path.display());
+    }
 }
 
-async fn run() {
+async fn run(options: &Options) {
     let event_loop = EventLoop::new().unwrap();
     let window = WindowBuilder::new()
-    .with_inner_size(winit::dpi::PhysicalSize::new(1600, 900))
+    .with_inner_size(winit::dpi::PhysicalSize::new(options.width, options.height))
     .build(&event_loop).unwrap();
 
     let window = Arc::new(window);
-    let mut app_state = app_state::AppState::new(window.clone()).await;
+    let mut app_state = app_state::AppState::new(window.clone(), &model_path(options)).await;
+    app_state.set_time(create_time(options, TimeMode::RealTime));
+    if let Some(path) = &options.params {
+        app_state.set_params_file(path.clone());
+    }
 
     event_loop.run(move |event, elwt| {
         match event {
@@ -26,15 +71,28 @@ async fn run() {
                 window_id, 
                 event 
             } if window.id() == window_id => {
+                if app_state.input(&event) {
+                    return;
+                }
+
                 match event {
                     WindowEvent::CloseRequested => {
                         elwt.exit();
                     },
+                    WindowEvent::Resized(new_size) => {
+                        app_state.resize(new_size);
+                    },
                     WindowEvent::RedrawRequested => {
                         app_state.update();
                         match app_state.render() {
+                            Ok(_) => {},
+                            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                                app_state.reconfigure();
+                            },
                             Err(wgpu::SurfaceError::OutOfMemory) => { elwt.exit() },
-                            _ => {}
+                            Err(wgpu::SurfaceError::Timeout) => {
+                                eprintln!("Surface timeout, retrying on the next frame");
+                            }
                         }
                     },
                     _ => {}
@@ -0,0 +1,99 @@
+use std::path::PathBuf;
+
+
+pub struct Options {
+    pub width: u32,
+    pub height: u32,
+    pub time: Option<f32>,
+    pub fixed_step: Option<f32>,
+    pub paused: bool,
+    pub params: Option<PathBuf>,
+    pub model: Option<PathBuf>,
+    pub headless: Option<HeadlessOptions>
+}
+
+pub struct HeadlessOptions {
+    pub frames: u32,
+    pub out: PathBuf,
+    pub software: bool
+}
+
+impl Options {
+    pub fn from_args() -> Result<Self, String> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut width = 1600;
+        let mut height = 900;
+        let mut headless = false;
+        let mut frames = 1;
+        let mut out = PathBuf::from("output");
+        let mut time = None;
+        let mut fixed_step = None;
+        let mut paused = false;
+        let mut software = false;
+        let mut params = None;
+        let mut model = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value for {arg}"));
+            match arg.as_str() {
+                "--headless" => headless = true,
+                "--frames" => {
+                    frames = value()?.parse().map_err(|err| format!("invalid --frames: {err}"))?;
+                },
+                "--size" => {
+                    (width, height) = parse_size(&value()?)?;
+                },
+                "--out" => out = PathBuf::from(value()?),
+                "--time" => {
+                    time = Some(value()?.parse().map_err(|err| format!("invalid --time: {err}"))?);
+                },
+                "--fixed-step" => {
+                    fixed_step = Some(parse_fixed_step(&value()?)?);
+                },
+                "--paused" => paused = true,
+                "--software" => software = true,
+                "--params" => params = Some(PathBuf::from(value()?)),
+                "--model" => model = Some(PathBuf::from(value()?)),
+                _ => return Err(format!("unknown argument {arg}\n\n{}", Self::usage()))
+            }
+        }
+
+        Ok(Options {
+            width,
+            height,
+            time,
+            fixed_step,
+            paused,
+            params,
+            model,
+            headless: headless.then_some(HeadlessOptions { frames, out, software })
+        })
+    }
+
+    pub fn usage() -> &'static str {
+        "Usage: [--size WIDTHxHEIGHT] [--time SECONDS] [--fixed-step SECONDS] [--paused] [--params FILE] [--model FILE] [--headless [--frames N] [--out DIR] [--software]]"
+    }
+}
+
+fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid --size {size}, expected WIDTHxHEIGHT");
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    let width: u32 = width.parse().map_err(|_| invalid())?;
+    let height: u32 = height.parse().map_err(|_| invalid())?;
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+
+    Ok((width, height))
+}
+
+// A number of seconds above 0, anything else would stop or reverse the time
+fn parse_fixed_step(step: &str) -> Result<f32, String> {
+    match step.parse::<f32>() {
+        Ok(seconds) if seconds.is_finite() && seconds > 0.0 => Ok(seconds),
+        _ => Err(format!("invalid --fixed-step {step}, expected a number of seconds above 0"))
+    }
+}
@@ -2,7 +2,11 @@
 struct UniformParameters {
     view_matrix: mat4x4<f32>,
     perspective_matrix: mat4x4<f32>,
-    time: f32
+    resolution: vec2f,
+    mouse: vec2f,
+    time: f32,
+    pixel_ratio: f32,
+    frame: u32
 }
 
 struct InputVertex {
@@ -21,8 +25,6 @@ struct OutputVertex {
 
 @group(0) @binding(0) var<uniform> uniforms: UniformParameters;
 
-const resolution: vec2f = vec2f(1600.0, 900.0);
-
 @vertex
 fn vs_main(in_vert: InputVertex) -> OutputVertex {
     var out_vert: OutputVertex;
@@ -1,11 +1,13 @@
+use std::path::{Path, PathBuf};
+
 use bytemuck::NoUninit;
 use glam::{Vec2, Vec3, Vec4};
+use gpu_layout::VertexLayout;
 use project_root::get_project_root;
 use wgpu::util::DeviceExt;
 
 
 pub struct BufferGeometry {
-    vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
@@ -28,7 +30,6 @@ impl BufferGeometry {
         });
 
         BufferGeometry {
-            vertices,
             indices,
             vertex_buffer,
             index_buffer
@@ -55,7 +56,7 @@ impl Vertex {
 }
 
 #[repr(C)]
-#[derive(Clone, Copy, NoUninit)]
+#[derive(Clone, Copy, NoUninit, VertexLayout)]
 pub struct VertexRaw {
     position: [f32; 3],
     normal: [f32; 3],
@@ -63,43 +64,17 @@ pub struct VertexRaw {
     color: [f32; 4]
 }
 
-impl Vertex {
-    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<VertexRaw>() as u64,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x3,
-                    offset: 0,
-                    shader_location: 0
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x3,
-                    offset: std::mem::size_of::<[f32; 3]>() as u64,
-                    shader_location: 1
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x2,
-                    offset: std::mem::size_of::<[f32; 6]>() as u64,
-                    shader_location: 2
-                },
-                wgpu::VertexAttribute {
-                    format: wgpu::VertexFormat::Float32x4,
-                    offset: std::mem::size_of::<[f32; 8]>() as u64,
-                    shader_location: 3
-                },
-            ]
-        }
-    }
-}
-
-pub fn load_model(device: &wgpu::Device) -> BufferGeometry {
+// The model shown unless `--model` gives another one
+pub fn default_model() -> PathBuf {
     let mut root = get_project_root().unwrap();
     root.push("assets");
     root.push("bunny.obj");
+    root
+}
 
-    let (models, _materials) = tobj::load_obj(root, &tobj::GPU_LOAD_OPTIONS).unwrap();
+pub fn load_model(device: &wgpu::Device, path: &Path) -> BufferGeometry {
+    let (models, _materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)
+        .unwrap_or_else(|error| panic!("cannot load {}: {error}", path.display()));
 
     let model = &models[0];
     let mesh = &model.mesh;
@@ -109,9 +84,9 @@ pub fn load_model(device: &wgpu::Device) -> BufferGeometry {
         let positions = &mesh.positions;
         let normals = &mesh.normals;
         let uvs = &mesh.texcoords;
-        let position = Vec3::new(positions[i * 3 + 0], positions[i * 3 + 1], positions[i * 3 + 2]);
-        let normal = Vec3::new(normals[i * 3 + 0], normals[i * 3 + 1], normals[i * 3 + 2]);
-        let uv = Vec2::new(uvs[i * 2 + 0], uvs[i * 2 + 1]);
+        let position = Vec3::new(positions[i * 3], positions[i * 3 + 1], positions[i * 3 + 2]);
+        let normal = Vec3::new(normals[i * 3], normals[i * 3 + 1], normals[i * 3 + 2]);
+        let uv = Vec2::new(uvs[i * 2], uvs[i * 2 + 1]);
 
         vertices.push(Vertex {
             position,
@@ -1,16 +1,40 @@
-use std::sync::Arc;
+use std::{collections::HashMap, path::{Path, PathBuf}, sync::Arc};
 
+use common::{constants::Constants, palette::Palettes, params::{Params, ParamsLayout}, preprocessor::Preprocessed, shader::{self, ErrorOverlay, ShaderError, ShaderFile}, target::{OffscreenTarget, RenderTarget}, time::Time};
 use glam::Vec3;
-use winit::window::Window;
-use crate::{camera::Camera, texture::CubeMap, uniform, vertex::{load_model, BufferGeometry, Vertex}};
+use gpu_layout::UniformLayout;
+use winit::{dpi::PhysicalSize, event::{DeviceEvent, ElementState, KeyEvent, WindowEvent}, keyboard::ModifiersState, window::{CursorGrabMode, Window}};
+use crate::{camera::{Camera, Projection}, texture::CubeMap, uniform, vertex::{load_model, BufferGeometry, VertexRaw}};
+
+// The shader and everything it includes, the fallback when the files on disk are broken
+const SHADERS: &[(&str, &str)] = &[
+    (concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders/shader.wgsl"), include_str!("shaders/shader.wgsl")),
+    (concat!(env!("CARGO_MANIFEST_DIR"), "/../shaders/noise.wgsl"), include_str!("../../shaders/noise.wgsl")),
+    (concat!(env!("CARGO_MANIFEST_DIR"), "/../shaders/palette.wgsl"), include_str!("../../shaders/palette.wgsl"))
+];
+
+// Bind group of the shader's `Params` struct
+const PARAMS_GROUP: u32 = 2;
+// Bind group of the palette lookup table
+const PALETTE_GROUP: u32 = 3;
 
 pub struct AppState {
-    pub window: Arc<Window>,
-    surface: wgpu::Surface<'static>,
+    target: RenderTarget,
+    size: PhysicalSize<u32>,
     device: wgpu::Device,
     queue: wgpu::Queue,
+    shader: ShaderFile,
+    shader_error: Option<ShaderError>,
+    error_overlay: ErrorOverlay,
+    render_pipeline_layout: wgpu::PipelineLayout,
     render_pipeline: wgpu::RenderPipeline,
     uniform: uniform::Uniform,
+    params: Params,
+    constants: Constants,
+    palettes: Palettes,
+    modifiers: ModifiersState,
+    cursor_grabbed: bool,
+    reversed_z: bool,
     model: BufferGeometry,
     depth_texture: wgpu::Texture,
     sky_box: CubeMap,
@@ -18,7 +42,7 @@ pub struct AppState {
 }
 
 impl AppState {
-    pub async fn new(window: Arc<Window>) -> Self {
+    pub async fn new(window: Arc<Window>, model: &Path) -> Self {
         let size = window.inner_size();
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
         let surface = instance.create_surface(window.clone()).unwrap();
@@ -31,8 +55,8 @@ impl AppState {
 
         let surface_caps = surface.get_capabilities(&adapter);
         let config = wgpu::SurfaceConfiguration {
-            width: size.width,
-            height: size.height,
+            width: size.width.max(1),
+            height: size.height.max(1),
             format: surface_caps.formats[0],
             alpha_mode: surface_caps.alpha_modes[0],
             present_mode: surface_caps.present_modes[0],
@@ -42,6 +66,36 @@ impl AppState {
         };
         surface.configure(&device, &config);
 
+        let title = window.title();
+        let pixel_ratio = window.scale_factor() as f32;
+        let target = RenderTarget::Window { window, title, surface, config };
+        let mut app_state = Self::with_target(device, queue, target, size, model);
+        app_state.uniform.set_pixel_ratio(pixel_ratio);
+        app_state
+    }
+
+    pub async fn new_headless(width: u32, height: u32, software: bool, model: &Path) -> Self {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = match instance.request_adapter(&wgpu::RequestAdapterOptions{
+            force_fallback_adapter: software,
+            ..Default::default()
+        }).await {
+            Some(adapter) => adapter,
+            None => instance.request_adapter(&wgpu::RequestAdapterOptions{
+                force_fallback_adapter: true,
+                ..Default::default()
+            }).await.unwrap()
+        };
+
+        let (device, queue) = adapter.request_device(&wgpu::DeviceDescriptor::default(), None).await.unwrap();
+
+        let target = RenderTarget::Offscreen(OffscreenTarget::new(&device, width, height));
+        Self::with_target(device, queue, target, PhysicalSize::new(width, height), model)
+    }
+
+    fn with_target(device: wgpu::Device, queue: wgpu::Queue, target: RenderTarget, size: PhysicalSize<u32>, model: &Path) -> Self {
+        device.on_uncaptured_error(Box::new(|error| eprintln!("Uncaptured wgpu error:\n{error}")));
+
         let camera_pos = Vec3::new(0.0, 5.0, 30.0);
         let camera = Camera::new(
             70.0, 
@@ -49,142 +103,71 @@ impl AppState {
             0.01, 
             1000.0, 
             camera_pos, 
-            Vec3::new(0.0, 1.0, 0.0), 
-            Vec3::new(0.0, 0.0, 0.0) - camera_pos
+            Vec3::new(0.0, 0.0, 0.0)
         );
 
-        let uniform = uniform::Uniform::new(&device, camera);
-        let model = load_model(&device);
+        let uniform = uniform::Uniform::new(&device, size.width, size.height, camera);
+        let model = load_model(&device, model);
         let sky_box = CubeMap::new(&device, &queue);
 
-        let depth_texture_size = wgpu::Extent3d{
-            width: size.width,
-            height: size.height,
-            depth_or_array_layers: 1
-        };
+        let depth_texture = create_depth_texture(&device, size.width, size.height);
 
-        let depth_texture = device.create_texture(&wgpu::TextureDescriptor{
-            label: None,
-            size: depth_texture_size,
-            mip_level_count: 1,
-            sample_count: 1,
-            view_formats: &[],
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Depth32Float,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
-        });
+        let mut shader = ShaderFile::new(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders/shader.wgsl")), SHADERS)
+            .include_dir(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../shaders")))
+            .define("ANIMATED_NOISE");
 
-        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/shader.wgsl").into())
-        });
+        let mut params = Params::new(&device);
+        params.set_file(shader.path.with_extension("params"));
+        let palettes = Palettes::new(&device, "classic");
 
         let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             bind_group_layouts: &[
                 &uniform.bind_group_layout,
                 &sky_box.bind_group_layout,
+                &params.bind_group_layout,
+                &palettes.bind_group_layout
             ],
             ..Default::default()
         });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader_module,
-                entry_point: "vs_main",
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                buffers: &[
-                    Vertex::desc()
-                ]
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader_module,
-                entry_point: "fs_main",
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                targets: &[
-                    Some(wgpu::ColorTargetState {
-                        format: config.format,
-                        blend: Some(wgpu::BlendState::REPLACE),
-                        write_mask: wgpu::ColorWrites::all()
-                    })
-                ]
-            }),
-            primitive: wgpu::PrimitiveState { 
-                topology: wgpu::PrimitiveTopology::TriangleList, 
-                strip_index_format: None, 
-                front_face: wgpu::FrontFace::Ccw, 
-                cull_mode: Some(wgpu::Face::Back),
-                unclipped_depth: false, 
-                polygon_mode: wgpu::PolygonMode::Fill, 
-                conservative: false 
-            },
-            depth_stencil: Some(
-                wgpu::DepthStencilState {
-                    format: wgpu::TextureFormat::Depth32Float,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::LessEqual,
-                    stencil: wgpu::StencilState::default(),
-                    bias: wgpu::DepthBiasState::default()
-                }
-            ),
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None
-        });
-
-        // Sky
-        let sky_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader_module,
-                entry_point: "sky_vs_main",
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                buffers: &[]
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader_module,
-                entry_point: "sky_fs_main",
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                targets: &[
-                    Some(wgpu::ColorTargetState {
-                        format: config.format,
-                        blend: Some(wgpu::BlendState::REPLACE),
-                        write_mask: wgpu::ColorWrites::all()
-                    })
-                ]
-            }),
-            primitive: wgpu::PrimitiveState { 
-                topology: wgpu::PrimitiveTopology::TriangleList, 
-                strip_index_format: None, 
-                front_face: wgpu::FrontFace::Ccw, 
-                cull_mode: None,
-                unclipped_depth: false, 
-                polygon_mode: wgpu::PolygonMode::Fill, 
-                conservative: false 
-            },
-            depth_stencil: Some(
-                wgpu::DepthStencilState {
-                    format: wgpu::TextureFormat::Depth32Float,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::LessEqual,
-                    stencil: wgpu::StencilState::default(),
-                    bias: wgpu::DepthBiasState::default()
-                }
-            ),
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None
-        });
-
-
+        // The pipeline constants are applied by `set_constants`, the shader starts with its defaults
+        let create_pipelines = |shader_module: &wgpu::ShaderModule| {
+            (
+                create_render_pipeline(&device, &render_pipeline_layout, shader_module, target.format(), &HashMap::new(), false),
+                create_sky_pipeline(&device, &render_pipeline_layout, shader_module, target.format(), &HashMap::new(), false)
+            )
+        };
+        let (((render_pipeline, sky_pipeline), params_layout), shader_error) = match shader.load().and_then(|source| shader::compile(&device, &source, &uniform::UniformRaw::layout(), PARAMS_GROUP, create_pipelines)) {
+            Ok(compiled) => (compiled, None),
+            Err(error) => {
+                eprintln!("{error}\nUsing the embedded shader until {} is fixed", shader.path.display());
+                let compiled = shader.load_embedded()
+                    .and_then(|source| shader::compile(&device, &source, &uniform::UniformRaw::layout(), PARAMS_GROUP, create_pipelines))
+                    .unwrap_or_else(|error| panic!("the embedded shader is broken:\n{error}"));
+                (compiled, Some(error))
+            }
+        };
+        params.set_layout(&device, params_layout);
+        target.show_error(shader_error.as_ref().map(|error| error.summary.as_str()));
+        let error_overlay = ErrorOverlay::new(&device, target.format());
 
         AppState {
-            window,
-            surface,
+            target,
+            size,
             device,
             queue,
+            shader,
+            shader_error,
+            error_overlay,
+            render_pipeline_layout,
             render_pipeline,
             uniform,
+            params,
+            constants: Constants::new(),
+            palettes,
+            modifiers: ModifiersState::default(),
+            cursor_grabbed: false,
+            reversed_z: false,
             model,
             depth_texture,
             sky_box,
@@ -192,13 +175,221 @@ impl AppState {
         }
     }
 
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        self.size = new_size;
+        if new_size.width == 0 || new_size.height == 0 {
+            return;
+        }
+
+        self.target.resize(&self.device, new_size.width, new_size.height);
+        self.depth_texture = create_depth_texture(&self.device, new_size.width, new_size.height);
+        self.uniform.resize(new_size.width, new_size.height);
+    }
+
+    pub fn reconfigure(&mut self) {
+        self.resize(self.size);
+    }
+
+    // Where the tweaked params are saved, next to the shader by default
+    pub fn set_params_file(&mut self, path: PathBuf) {
+        self.params.set_file(path);
+    }
+
+    // Pipeline constants from `path`, next to the shader by default, and from the command line.
+    // The pipelines are rebuilt whenever the file changes.
+    pub fn set_constants(&mut self, path: Option<PathBuf>, values: &[(String, f64)]) {
+        self.constants.set_file(path.unwrap_or(self.shader.path.with_extension("constants")));
+        for (name, value) in values {
+            self.constants.set(name, *value);
+        }
+
+        let error = self.rebuild_pipelines().err();
+        if let Some(error) = &error {
+            eprintln!("{error}\nKeeping the default pipeline constants until this is fixed");
+        }
+        self.set_shader_error(error);
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.uniform.camera_mut().set_projection(projection);
+    }
+
+    // Flips the projection, the depth test and the clear value. The pipelines are built first and
+    // from the embedded shader if the one on disk is broken, nothing flips unless one of them builds.
+    pub fn set_reversed_z(&mut self, reversed_z: bool) {
+        let source = self.constants.load().and_then(|()| self.shader.load());
+        let built = match source.and_then(|source| self.build_pipelines(&source, reversed_z)) {
+            Ok(built) => built,
+            Err(error) => {
+                eprintln!("{error}\nUsing the embedded shader until {} is fixed", self.shader.path.display());
+                let built = self.shader.load_embedded().and_then(|source| self.build_pipelines(&source, reversed_z));
+                self.set_shader_error(Some(error));
+                match built {
+                    Ok(built) => built,
+                    Err(error) => {
+                        eprintln!("{error}\nKeeping the depth as it is");
+                        return;
+                    }
+                }
+            }
+        };
+
+        self.set_pipelines(built);
+        self.reversed_z = reversed_z;
+        self.uniform.camera_mut().set_reversed_z(reversed_z);
+    }
+
+    // Camera keyframes recorded with K are saved to `path`, next to the shader by default.
+    // A path given explicitly is played right away.
+    pub fn set_camera_path(&mut self, path: Option<PathBuf>) -> Result<(), String> {
+        let play = path.is_some();
+        let path = path.unwrap_or(self.shader.path.with_extension("camera"));
+        self.uniform.camera_mut().set_path_file(path, play)
+    }
+
+    pub fn set_time(&mut self, time: Time) {
+        self.uniform.set_time(time);
+    }
+
+    // A preset name or a gradient file, see palette.rs
+    pub fn set_palette(&mut self, name_or_path: &str) -> Result<(), String> {
+        self.palettes.select(name_or_path)
+    }
+
+    // Moves on to the next palette every `seconds` of shader time
+    pub fn set_palette_cycle(&mut self, seconds: Option<f32>) {
+        self.palettes.set_cycle(seconds);
+    }
+
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        let handled = self.handle_input(event);
+        self.grab_cursor();
+        handled
+    }
+
+    // Raw mouse motion, which the fly camera looks around with
+    pub fn device_input(&mut self, event: &DeviceEvent) -> bool {
+        self.uniform.camera_mut().device_input(event)
+    }
+
+    fn handle_input(&mut self, event: &WindowEvent) -> bool {
+        if self.uniform.camera_mut().input(event) {
+            return true;
+        }
+
+        match event {
+            WindowEvent::KeyboardInput {
+                event: KeyEvent { state: ElementState::Pressed, logical_key, .. },
+                ..
+            } => {
+                self.uniform.time_mut().handle_key(logical_key)
+                    || self.uniform.camera_mut().handle_key(logical_key)
+                    || self.params.handle_key(logical_key, self.modifiers.shift_key())
+                    || self.palettes.handle_key(logical_key, self.modifiers.shift_key())
+            },
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+                false
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                self.uniform.set_mouse(position.x as f32, position.y as f32);
+                false
+            },
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.uniform.set_pixel_ratio(*scale_factor as f32);
+                false
+            },
+            _ => false
+        }
+    }
+
+    // Follows the camera, the cursor is hidden and held in the window while the fly camera looks around
+    fn grab_cursor(&mut self) {
+        let grabbed = self.uniform.camera_mut().cursor_grabbed();
+        let RenderTarget::Window { window, .. } = &self.target else {
+            return;
+        };
+        if grabbed == self.cursor_grabbed {
+            return;
+        }
+        self.cursor_grabbed = grabbed;
+
+        // Not every platform can lock the cursor in place, confining it works as well with raw motion
+        let result = match grabbed {
+            true => window.set_cursor_grab(CursorGrabMode::Locked).or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined)),
+            false => window.set_cursor_grab(CursorGrabMode::None)
+        };
+        if let Err(error) = result {
+            eprintln!("Cannot grab the cursor: {error}");
+        }
+        window.set_cursor_visible(!grabbed);
+    }
+
     pub fn update(&mut self) {
+        self.reload_shader();
         self.uniform.update(&self.queue);
+        self.params.update(&self.queue);
+        self.palettes.update(&self.queue, self.uniform.time().elapsed());
+    }
+
+    fn reload_shader(&mut self) {
+        // Both are polled, so a change of either is not reported twice
+        if !(self.shader.changed() | self.constants.changed()) {
+            return;
+        }
+
+        match self.rebuild_pipelines() {
+            Ok(()) => {
+                self.set_shader_error(None);
+                println!("Reloaded {}", self.shader.path.display());
+            },
+            Err(error) => {
+                eprintln!("{error}\nKeeping the previous pipelines until {} is fixed", self.shader.path.display());
+                self.set_shader_error(Some(error));
+            }
+        }
+    }
+
+    fn rebuild_pipelines(&mut self) -> Result<(), ShaderError> {
+        self.constants.load()?;
+        let source = self.shader.load()?;
+        let built = self.build_pipelines(&source, self.reversed_z)?;
+        self.set_pipelines(built);
+        Ok(())
+    }
+
+    // The render and sky pipelines for `source`, with the depth test `reversed_z` asks for
+    fn build_pipelines(&self, source: &Preprocessed, reversed_z: bool) -> Result<((wgpu::RenderPipeline, wgpu::RenderPipeline), ParamsLayout), ShaderError> {
+        self.constants.check(source)?;
+
+        let format = self.target.format();
+        let mut constants = self.constants.values();
+        constants.insert("reversed_z".to_string(), reversed_z as i32 as f64);
+        shader::compile(&self.device, source, &uniform::UniformRaw::layout(), PARAMS_GROUP, |shader_module| {
+            (
+                create_render_pipeline(&self.device, &self.render_pipeline_layout, shader_module, format, &constants, reversed_z),
+                create_sky_pipeline(&self.device, &self.render_pipeline_layout, shader_module, format, &constants, reversed_z)
+            )
+        })
+    }
+
+    fn set_pipelines(&mut self, ((render_pipeline, sky_pipeline), params_layout): ((wgpu::RenderPipeline, wgpu::RenderPipeline), ParamsLayout)) {
+        self.render_pipeline = render_pipeline;
+        self.sky_pipeline = sky_pipeline;
+        self.params.set_layout(&self.device, params_layout);
+    }
+
+    fn set_shader_error(&mut self, error: Option<ShaderError>) {
+        self.target.show_error(error.as_ref().map(|error| error.summary.as_str()));
+        self.shader_error = error;
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let texture = self.surface.get_current_texture()?;
-        let view = texture.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        if self.size.width == 0 || self.size.height == 0 {
+            return Ok(());
+        }
+
+        let frame = self.target.acquire()?;
         let depth_view = self.depth_texture.create_view(&wgpu::TextureViewDescriptor::default());
 
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
@@ -208,7 +399,7 @@ impl AppState {
                 color_attachments: &[
                     Some(
                         wgpu::RenderPassColorAttachment {
-                            view: &view,
+                            view: &frame.view,
                             resolve_target: None,
                             ops: wgpu::Operations{
                                 load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
@@ -222,7 +413,7 @@ impl AppState {
                         view: &depth_view, 
                         depth_ops: Some(
                             wgpu::Operations { 
-                                load: wgpu::LoadOp::Clear(1.0),
+                                load: wgpu::LoadOp::Clear(if self.reversed_z { 0.0 } else { 1.0 }),
                                 store: wgpu::StoreOp::Store 
                             }
                         ), 
@@ -234,6 +425,8 @@ impl AppState {
             });
             render_pass.set_bind_group(0, &self.uniform.bind_group, &[]);
             render_pass.set_bind_group(1, &self.sky_box.bind_group, &[]);
+            render_pass.set_bind_group(PARAMS_GROUP, &self.params.bind_group, &[]);
+            render_pass.set_bind_group(PALETTE_GROUP, &self.palettes.bind_group, &[]);
 
             // Sky
             render_pass.set_pipeline(&self.sky_pipeline);
@@ -246,9 +439,149 @@ impl AppState {
             render_pass.set_index_buffer(self.model.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
             render_pass.draw_indexed(0..self.model.indices.len() as u32, 0, 0..1);
         }
+        if self.shader_error.is_some() {
+            self.error_overlay.draw(&mut encoder, &frame.view);
+        }
         self.queue.submit(std::iter::once(encoder.finish()));
-        texture.present();
+        frame.present();
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    pub fn capture(&self) -> Option<image::RgbaImage> {
+        match &self.target {
+            RenderTarget::Offscreen(target) => Some(target.read_image(&self.device, &self.queue)),
+            RenderTarget::Window { .. } => None
+        }
+    }
+}
+
+fn create_depth_texture(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Texture {
+    let depth_texture_size = wgpu::Extent3d{
+        width,
+        height,
+        depth_or_array_layers: 1
+    };
+
+    device.create_texture(&wgpu::TextureDescriptor{
+        label: None,
+        size: depth_texture_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        view_formats: &[],
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Depth32Float,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+    })
+}
+
+fn create_render_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, shader_module: &wgpu::ShaderModule, format: wgpu::TextureFormat, constants: &HashMap<String, f64>, reversed_z: bool) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader_module,
+            entry_point: "vs_main",
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants,
+                ..Default::default()
+            },
+            buffers: &[
+                VertexRaw::desc()
+            ]
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader_module,
+            entry_point: "fs_main",
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants,
+                ..Default::default()
+            },
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::all()
+                })
+            ]
+        }),
+        primitive: wgpu::PrimitiveState { 
+            topology: wgpu::PrimitiveTopology::TriangleList, 
+            strip_index_format: None, 
+            front_face: wgpu::FrontFace::Ccw, 
+            cull_mode: Some(wgpu::Face::Back),
+            unclipped_depth: false, 
+            polygon_mode: wgpu::PolygonMode::Fill, 
+            conservative: false 
+        },
+        depth_stencil: Some(
+            wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: depth_compare(reversed_z),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default()
+            }
+        ),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None
+    })
+}
+
+fn create_sky_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, shader_module: &wgpu::ShaderModule, format: wgpu::TextureFormat, constants: &HashMap<String, f64>, reversed_z: bool) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader_module,
+            entry_point: "sky_vs_main",
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants,
+                ..Default::default()
+            },
+            buffers: &[]
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader_module,
+            entry_point: "sky_fs_main",
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants,
+                ..Default::default()
+            },
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::all()
+                })
+            ]
+        }),
+        primitive: wgpu::PrimitiveState { 
+            topology: wgpu::PrimitiveTopology::TriangleList, 
+            strip_index_format: None, 
+            front_face: wgpu::FrontFace::Ccw, 
+            cull_mode: None,
+            unclipped_depth: false, 
+            polygon_mode: wgpu::PolygonMode::Fill, 
+            conservative: false 
+        },
+        depth_stencil: Some(
+            wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: depth_compare(reversed_z),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default()
+            }
+        ),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None
+    })
+}
+
+fn depth_compare(reversed_z: bool) -> wgpu::CompareFunction {
+    match reversed_z {
+        true => wgpu::CompareFunction::GreaterEqual,
+        false => wgpu::CompareFunction::LessEqual
+    }
+}
@@ -1,5 +1,10 @@
-use bytemuck::NoUninit;
+use std::path::PathBuf;
+
+use common::time::Time;
 use glam::{Mat4, Vec3};
+use gpu_layout::UniformLayout;
+use winit::{event::{DeviceEvent, WindowEvent}, keyboard::Key};
+use crate::{camera_path::{CameraPath, Keyframe}, fly::FlyController, orbit::OrbitController};
 
 
 pub struct Camera {
@@ -7,51 +12,359 @@ pub struct Camera {
     aspect_ratio: f32,
     near: f32,
     far: f32,
-    position: Vec3,
-    up: Vec3,
-    direction: Vec3
+    projection: Projection,
+    // Maps the near plane to depth 1 and the far plane to 0, which spreads the float precision evenly
+    reversed_z: bool,
+    // Height of the view in world units under the orthographic projection. The orbit controller
+    // zooms it along with its distance, the fly controller keeps it as it is.
+    ortho_height: f32,
+    mode: CameraMode,
+    orbit: OrbitController,
+    fly: FlyController,
+    // Recorded with K and played with V, which takes over from the controllers
+    path: CameraPath,
+    playing: bool,
+    // Shader time of the last update, the path is timed by it
+    time: f32
+}
+
+// Cycled with O
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Projection {
+    Perspective,
+    // Without a far plane, the depth approaches 1 at infinity
+    InfinitePerspective,
+    // Shows the height the perspective has at the orbit's distance, so the view matches when switching
+    // in orbit mode
+    Orthographic
+}
+
+// Switched with C, the view carries over to the other controller
+#[derive(Clone, Copy, PartialEq)]
+enum CameraMode {
+    Orbit,
+    Fly
+}
+
+impl Projection {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "perspective" => Ok(Projection::Perspective),
+            "infinite" => Ok(Projection::InfinitePerspective),
+            "orthographic" => Ok(Projection::Orthographic),
+            _ => Err(format!("unknown projection {name}, expected perspective, infinite or orthographic"))
+        }
+    }
 }
 
 impl Camera {
-    pub fn new(fov: f32, aspect_ratio: f32, near: f32, far: f32, position: Vec3, up: Vec3, direction: Vec3) -> Self {
+    // Degrees per press of [ or ]
+    const FOV_STEP: f32 = 5.0;
+    const FOV_RANGE: (f32, f32) = (10.0, 120.0);
+
+    pub fn new(fov: f32, aspect_ratio: f32, near: f32, far: f32, position: Vec3, target: Vec3) -> Self {
         Camera {
             fov,
             aspect_ratio,
             near,
             far,
-            position,
-            up,
-            direction
+            projection: Projection::Perspective,
+            reversed_z: false,
+            ortho_height: Self::height_at(fov, position.distance(target)),
+            mode: CameraMode::Orbit,
+            orbit: OrbitController::new(position, target),
+            fly: FlyController::new(position, target),
+            path: CameraPath::new(),
+            playing: false,
+            time: 0.0
         }
     }
 
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        self.aspect_ratio = aspect_ratio;
+    }
+
     pub fn view_matrix(&self) -> Mat4 {
-        Mat4::look_to_rh(self.position, self.direction, self.up)
+        if let Some(keyframe) = self.played() {
+            return Mat4::from_rotation_translation(keyframe.rotation, keyframe.position).inverse();
+        }
+
+        match self.mode {
+            CameraMode::Orbit => Mat4::look_at_rh(self.orbit.position(), self.orbit.target(), Vec3::Y),
+            CameraMode::Fly => Mat4::look_to_rh(self.fly.position(), self.fly.forward(), Vec3::Y)
+        }
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    pub fn set_reversed_z(&mut self, reversed_z: bool) {
+        self.reversed_z = reversed_z;
+    }
+
+    pub fn projection_matrix(&self) -> Mat4 {
+        let fov = f32::to_radians(self.played().map_or(self.fov, |keyframe| keyframe.fov));
+        // Swapping the planes reverses the depth. Flipping the finished matrix instead would
+        // cancel out most of the precision that reversing gains.
+        let (near, far) = match self.reversed_z {
+            true => (self.far, self.near),
+            false => (self.near, self.far)
+        };
+
+        match (self.projection, self.reversed_z) {
+            (Projection::Perspective, _) => Mat4::perspective_rh(fov, self.aspect_ratio, near, far),
+            (Projection::InfinitePerspective, false) => Mat4::perspective_infinite_rh(fov, self.aspect_ratio, self.near),
+            (Projection::InfinitePerspective, true) => Mat4::perspective_infinite_reverse_rh(fov, self.aspect_ratio, self.near),
+            (Projection::Orthographic, _) => {
+                let half_height = self.ortho_height / 2.0;
+                let half_width = half_height * self.aspect_ratio;
+                Mat4::orthographic_rh(-half_width, half_width, -half_height, half_height, near, far)
+            }
+        }
+    }
+
+    // Height the perspective with the field of view `fov` in degrees shows at `distance`
+    fn height_at(fov: f32, distance: f32) -> f32 {
+        2.0 * distance * (f32::to_radians(fov) / 2.0).tan()
+    }
+
+    // Whether the cursor should be hidden and held in the window
+    pub fn cursor_grabbed(&self) -> bool {
+        !self.playing && self.mode == CameraMode::Fly && self.fly.grabbed()
+    }
+
+    // Loads the keyframes in `path`, where the recorded ones are saved, and plays them if `play` is set
+    pub fn set_path_file(&mut self, path: PathBuf, play: bool) -> Result<(), String> {
+        self.path.set_file(path)?;
+        self.set_playing(play);
+        Ok(())
+    }
+
+    // The path in place of the controllers, or the view at the current time
+    fn played(&self) -> Option<Keyframe> {
+        self.path.sample(self.time).filter(|_| self.playing)
+    }
+
+    fn set_playing(&mut self, playing: bool) {
+        if playing && self.path.is_empty() {
+            println!("The camera path is empty, K records the current view at the current time");
+            return;
+        }
+
+        // The controller takes over where the path left off
+        if let (false, Some(keyframe)) = (playing, self.played()) {
+            let position = keyframe.position;
+            let forward = keyframe.rotation * Vec3::NEG_Z;
+            match self.mode {
+                CameraMode::Orbit => self.orbit.look_at(position, position + forward * self.orbit.distance()),
+                CameraMode::Fly => self.fly.look_at(position, position + forward)
+            }
+            self.fov = keyframe.fov;
+        }
+        self.playing = playing;
     }
 
-    pub fn perspective_matrix(&self) -> Mat4 {
-        Mat4::perspective_rh(f32::to_radians(self.fov), self.aspect_ratio, self.near, self.far)
+    fn record_keyframe(&mut self) {
+        let (_, rotation, position) = self.view_matrix().inverse().to_scale_rotation_translation();
+        let fov = self.played().map_or(self.fov, |keyframe| keyframe.fov);
+        self.path.record(Keyframe { time: self.time, position, rotation, fov });
+        println!("Recorded a keyframe at {:.2}s, the path has {}", self.time, self.path.len());
     }
 
-    pub fn update(&mut self, time: f32) {
-        let rotation_matrix = Mat4::from_rotation_y(time * 0.5);
-        self.position = rotation_matrix.transform_vector3(self.position);
-        self.direction = Vec3::new(0.0, 0.0, 0.0) - self.position;
+    fn set_mode(&mut self, mode: CameraMode) {
+        if mode == self.mode {
+            return;
+        }
+
+        match mode {
+            CameraMode::Orbit => {
+                // Orbits the point in front of the camera at the distance it had before
+                let position = self.fly.position();
+                self.orbit.look_at(position, position + self.fly.forward() * self.orbit.distance());
+                self.fly.set_grabbed(false);
+            },
+            CameraMode::Fly => {
+                self.fly.look_at(self.orbit.position(), self.orbit.target());
+                self.fly.set_grabbed(true);
+            }
+        }
+        self.mode = mode;
+    }
+
+    pub fn handle_key(&mut self, key: &Key) -> bool {
+        match key {
+            Key::Character(c) if c == "o" => {
+                self.projection = match self.projection {
+                    Projection::Perspective => Projection::InfinitePerspective,
+                    Projection::InfinitePerspective => Projection::Orthographic,
+                    Projection::Orthographic => Projection::Perspective
+                };
+                println!("{:?} projection", self.projection);
+                true
+            },
+            Key::Character(c) if c == "c" => {
+                let mode = match self.mode {
+                    CameraMode::Orbit => CameraMode::Fly,
+                    CameraMode::Fly => CameraMode::Orbit
+                };
+                self.set_mode(mode);
+                println!("{} camera", if mode == CameraMode::Fly { "Fly" } else { "Orbit" });
+                true
+            },
+            Key::Character(c) if c == "[" || c == "]" => {
+                let (min, max) = Self::FOV_RANGE;
+                let step = if c == "[" { -Self::FOV_STEP } else { Self::FOV_STEP };
+                self.fov = (self.fov + step).clamp(min, max);
+                println!("Field of view {}°", self.fov);
+                true
+            },
+            Key::Character(c) if c == "k" => {
+                self.record_keyframe();
+                true
+            },
+            Key::Character(c) if c == "K" => {
+                if let Some(keyframe) = self.path.remove_last() {
+                    println!("Removed the keyframe at {:.2}s, the path has {}", keyframe.time, self.path.len());
+                }
+                true
+            },
+            Key::Character(c) if c == "v" => {
+                self.set_playing(!self.playing);
+                if self.playing {
+                    println!("Playing the camera path, Home starts it over");
+                }
+                true
+            },
+            _ if self.mode == CameraMode::Orbit => self.orbit.handle_key(key),
+            _ => false
+        }
+    }
+
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        // Both keep track of the modifiers, which may change before a switch
+        if let WindowEvent::ModifiersChanged(_) = event {
+            self.orbit.input(event);
+            self.fly.input(event);
+            return false;
+        }
+        if self.playing {
+            return false;
+        }
+
+        match self.mode {
+            CameraMode::Orbit => self.orbit.input(event),
+            CameraMode::Fly => self.fly.input(event)
+        }
+    }
+
+    pub fn device_input(&mut self, event: &DeviceEvent) -> bool {
+        !self.playing && self.mode == CameraMode::Fly && self.fly.device_input(event)
+    }
+
+    pub fn update(&mut self, time: &Time) {
+        self.time = time.elapsed();
+        match self.mode {
+            CameraMode::Orbit => {
+                self.orbit.update(time.elapsed(), time.elapsed_frame());
+                self.ortho_height = Self::height_at(self.fov, self.orbit.distance());
+            },
+            CameraMode::Fly => self.fly.update(time.elapsed_frame())
+        }
     }
 
     pub fn as_raw(&self) -> CameraRaw {
+        let view_matrix = self.view_matrix();
+        let projection_matrix = self.projection_matrix();
+
         CameraRaw { 
-            view_matrix: self.view_matrix().to_cols_array(), 
-            perspective_matrix: self.perspective_matrix().to_cols_array(),
-            inv_perspective_matrix: self.perspective_matrix().inverse().to_cols_array()
+            view_matrix: view_matrix.to_cols_array(), 
+            inv_view_matrix: view_matrix.inverse().to_cols_array(),
+            projection_matrix: projection_matrix.to_cols_array(),
+            inv_projection_matrix: projection_matrix.inverse().to_cols_array()
         }
     }
 }
 
-#[repr(C)]
-#[derive(Clone, Copy, NoUninit)]
+#[derive(UniformLayout)]
 pub struct CameraRaw {
     view_matrix: [f32; 16],
-    perspective_matrix: [f32; 16],
-    inv_perspective_matrix: [f32; 16],
-}
\ No newline at end of file
+    inv_view_matrix: [f32; 16],
+    projection_matrix: [f32; 16],
+    inv_projection_matrix: [f32; 16],
+}
+
+#[cfg(test)]
+mod tests {
+    use common::time::{Time, TimeMode};
+    use glam::{Vec3, Vec4};
+    use winit::keyboard::Key;
+    use super::{Camera, Projection};
+
+    #[test]
+    fn reversed_z_swaps_the_depth_of_the_planes() {
+        let mut camera = Camera::new(70.0, 1.0, 0.01, 1000.0, Vec3::new(0.0, 0.0, 10.0), Vec3::ZERO);
+        let depth = |camera: &Camera, distance: f32| {
+            let clip = camera.projection_matrix() * Vec4::new(0.0, 0.0, -distance, 1.0);
+            clip.z / clip.w
+        };
+
+        for projection in [Projection::Perspective, Projection::Orthographic] {
+            camera.set_projection(projection);
+            camera.set_reversed_z(false);
+            assert!((depth(&camera, 0.01) - 0.0).abs() < 1e-4 && (depth(&camera, 1000.0) - 1.0).abs() < 1e-4, "{projection:?}");
+            camera.set_reversed_z(true);
+            assert!((depth(&camera, 0.01) - 1.0).abs() < 1e-4 && (depth(&camera, 1000.0) - 0.0).abs() < 1e-4, "{projection:?}");
+        }
+
+        camera.set_projection(Projection::InfinitePerspective);
+        assert!((depth(&camera, 0.01) - 1.0).abs() < 1e-4 && depth(&camera, 1e6) < 1e-6);
+    }
+
+    #[test]
+    fn jumping_to_a_time_shows_the_played_camera() {
+        let camera = || Camera::new(70.0, 1.0, 0.01, 1000.0, Vec3::new(0.0, 10.0, 10.0), Vec3::new(0.0, 1.0, 0.0));
+
+        // As with --time 2.5
+        let (mut jumped, mut time) = (camera(), Time::new(TimeMode::FixedStep(0.25)));
+        time.set_elapsed(2.5);
+        time.update();
+        jumped.update(&time);
+
+        let (mut played, mut time) = (camera(), Time::new(TimeMode::FixedStep(0.25)));
+        for _ in 0..11 {
+            time.update();
+            played.update(&time);
+        }
+        assert_eq!(time.elapsed(), 2.5);
+        assert!(played.view_matrix().abs_diff_eq(jumped.view_matrix(), 1e-4));
+        assert!(!played.view_matrix().abs_diff_eq(camera().view_matrix(), 1e-2));
+
+        // Home brings the camera back to where it started
+        time.set_elapsed(0.0);
+        time.set_paused(true);
+        time.update();
+        played.update(&time);
+        assert!(played.view_matrix().abs_diff_eq(camera().view_matrix(), 1e-4));
+    }
+
+    #[test]
+    fn flying_keeps_the_orthographic_height() {
+        let mut camera = Camera::new(70.0, 1.0, 0.01, 1000.0, Vec3::new(0.0, 0.0, 10.0), Vec3::ZERO);
+        camera.set_projection(Projection::Orthographic);
+        let mut time = Time::new(TimeMode::FixedStep(0.25));
+        time.update();
+        camera.update(&time);
+        let orbit = camera.projection_matrix();
+        let half_height = 10.0 * f32::to_radians(35.0).tan();
+        assert!((orbit.y_axis.y - 1.0 / half_height).abs() < 1e-5);
+
+        // The fly controller has no distance of its own, the view keeps its size
+        camera.handle_key(&Key::Character("c".into()));
+        for _ in 0..4 {
+            time.update();
+            camera.update(&time);
+        }
+        assert!(camera.projection_matrix().abs_diff_eq(orbit, 1e-6));
+    }
+}
@@ -0,0 +1,222 @@
+use std::{ops::{Add, Mul, Sub}, path::PathBuf};
+
+use glam::{Quat, Vec3, Vec4};
+
+
+// Camera keyframes to fly through, timed by the shader time. Between the keyframes the position
+// and field of view follow a Catmull-Rom spline and the rotation is slerped. Recorded keyframes
+// are saved to `path` right away, one per line.
+pub struct CameraPath {
+    path: Option<PathBuf>,
+    keyframes: Vec<Keyframe>
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Keyframe {
+    pub time: f32,
+    pub position: Vec3,
+    // From the camera's space to the world, the camera looks down its -Z axis
+    pub rotation: Quat,
+    // Vertical, in degrees
+    pub fov: f32
+}
+
+impl CameraPath {
+    pub fn new() -> Self {
+        CameraPath {
+            path: None,
+            keyframes: Vec::new()
+        }
+    }
+
+    // Loads the keyframes saved in `path`, a missing file is an empty path
+    pub fn set_file(&mut self, path: PathBuf) -> Result<(), String> {
+        self.keyframes = match std::fs::read_to_string(&path) {
+            Ok(contents) => parse(&contents).map_err(|error| format!("{}: {error}", path.display()))?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(error) => return Err(format!("cannot read {}: {error}", path.display()))
+        };
+        self.path = Some(path);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    // Replaces the keyframe at the same time, if there is one
+    pub fn record(&mut self, keyframe: Keyframe) {
+        let index = self.keyframes.partition_point(|other| other.time < keyframe.time);
+        match self.keyframes.get(index) {
+            Some(other) if other.time == keyframe.time => self.keyframes[index] = keyframe,
+            _ => self.keyframes.insert(index, keyframe)
+        }
+        self.save();
+    }
+
+    pub fn remove_last(&mut self) -> Option<Keyframe> {
+        let keyframe = self.keyframes.pop();
+        self.save();
+        keyframe
+    }
+
+    // Holds the first and last keyframes before and after the path
+    pub fn sample(&self, time: f32) -> Option<Keyframe> {
+        let last = self.keyframes.len().checked_sub(1)?;
+        let next = self.keyframes.partition_point(|keyframe| keyframe.time <= time);
+        if next == 0 || next > last {
+            return Some(self.keyframes[next.min(last)]);
+        }
+
+        // The ends repeat themselves as their missing neighbours
+        let [k0, k1, k2, k3] = [next.saturating_sub(2), next - 1, next, (next + 1).min(last)].map(|index| self.keyframes[index]);
+        let t = (time - k1.time) / (k2.time - k1.time);
+        let times = [k0.time, k1.time, k2.time, k3.time];
+
+        Some(Keyframe {
+            time,
+            position: catmull_rom(times, [k0.position, k1.position, k2.position, k3.position], t),
+            rotation: k1.rotation.slerp(k2.rotation, t),
+            fov: catmull_rom(times, [k0.fov, k1.fov, k2.fov, k3.fov], t)
+        })
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        if let Err(error) = std::fs::write(path, format(&self.keyframes)) {
+            eprintln!("Failed to save the camera path to {}: {error}", path.display());
+        }
+    }
+}
+
+// Cubic Hermite curve from `points[1]` to `points[2]` at `t` between 0 and 1, with the tangents
+// of a Catmull-Rom spline. They are scaled by the times, so the keyframes need not be evenly spaced.
+fn catmull_rom<T>(times: [f32; 4], points: [T; 4], t: f32) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>
+{
+    let [t0, t1, t2, t3] = times;
+    let [p0, p1, p2, p3] = points;
+    let span = t2 - t1;
+    let m1 = (p2 - p0) * (span / (t2 - t0));
+    let m2 = (p3 - p1) * (span / (t3 - t1));
+
+    let (t2, t3) = (t * t, t * t * t);
+    p1 * (2.0 * t3 - 3.0 * t2 + 1.0) + m1 * (t3 - 2.0 * t2 + t) + p2 * (3.0 * t2 - 2.0 * t3) + m2 * (t3 - t2)
+}
+
+// One keyframe per line, `//` starts a comment. Floats are written with as many digits as it
+// takes to read them back exactly, so a saved path replays the same views.
+fn format(keyframes: &[Keyframe]) -> String {
+    let mut contents = String::from("// time, position x y z, rotation x y z w, fov\n");
+    for keyframe in keyframes {
+        let values = [
+            keyframe.time,
+            keyframe.position.x, keyframe.position.y, keyframe.position.z,
+            keyframe.rotation.x, keyframe.rotation.y, keyframe.rotation.z, keyframe.rotation.w,
+            keyframe.fov
+        ];
+        let values: Vec<String> = values.iter().map(f32::to_string).collect();
+        contents += &values.join(" ");
+        contents += "\n";
+    }
+    contents
+}
+
+fn parse(contents: &str) -> Result<Vec<Keyframe>, String> {
+    let mut keyframes: Vec<Keyframe> = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let text = line.split("//").next().unwrap();
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        let values = text.split_whitespace()
+            .map(|value| value.parse().ok().filter(|value: &f32| value.is_finite()))
+            .collect::<Option<Vec<f32>>>();
+        let Some(&[time, x, y, z, qx, qy, qz, qw, fov]) = values.as_deref() else {
+            return Err(format!("line {}: expected 9 numbers, the time, position, rotation and fov", number + 1));
+        };
+        if keyframes.last().is_some_and(|last| last.time >= time) {
+            return Err(format!("line {}: the keyframes are not in the order of their times", number + 1));
+        }
+        // Edited by hand, it may be a little off
+        let Some(rotation) = Vec4::new(qx, qy, qz, qw).try_normalize().map(Quat::from_vec4) else {
+            return Err(format!("line {}: the rotation has to be a quaternion of non-zero length", number + 1));
+        };
+        if fov <= 0.0 || fov >= 180.0 {
+            return Err(format!("line {}: the fov has to be between 0 and 180 degrees", number + 1));
+        }
+
+        keyframes.push(Keyframe {
+            time,
+            position: Vec3::new(x, y, z),
+            rotation,
+            fov
+        });
+    }
+
+    Ok(keyframes)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Quat, Vec3};
+    use super::{format, parse, CameraPath, Keyframe};
+
+    fn keyframe(time: f32, x: f32, yaw: f32) -> Keyframe {
+        Keyframe {
+            time,
+            position: Vec3::new(x, 1.0, 0.0),
+            rotation: Quat::from_rotation_y(yaw),
+            fov: 70.0
+        }
+    }
+
+    #[test]
+    fn passes_through_the_keyframes() {
+        let mut path = CameraPath::new();
+        assert_eq!(path.sample(1.0), None);
+
+        // Out of order and unevenly spaced
+        for keyframe in [keyframe(3.0, 4.0, 1.0), keyframe(0.0, 0.0, 0.0), keyframe(1.0, 1.0, 0.5), keyframe(5.0, 4.0, 1.0)] {
+            path.record(keyframe);
+        }
+        path.record(keyframe(1.0, 2.0, 0.5));
+        assert_eq!(path.len(), 4);
+
+        for (time, x) in [(-1.0, 0.0), (0.0, 0.0), (1.0, 2.0), (3.0, 4.0), (9.0, 4.0)] {
+            let sampled = path.sample(time).unwrap();
+            assert!((sampled.position.x - x).abs() < 1e-5, "{time}: {sampled:?}");
+        }
+
+        // Halfway in time is halfway round, and the position moves on smoothly across a keyframe
+        let sampled = path.sample(2.0).unwrap();
+        assert!(sampled.rotation.abs_diff_eq(Quat::from_rotation_y(0.75), 1e-5));
+        assert!(sampled.position.x > 2.0 && sampled.position.x < 4.0 && sampled.position.y == 1.0);
+        let slope = |time: f32| (path.sample(time + 1e-3).unwrap().position.x - path.sample(time - 1e-3).unwrap().position.x) / 2e-3;
+        assert!((slope(1.0 - 1e-2) - slope(1.0 + 1e-2)).abs() < 0.1, "{} and {}", slope(0.99), slope(1.01));
+    }
+
+    #[test]
+    fn saves_and_loads_exactly() {
+        let keyframes = vec![keyframe(0.0, -1.5, 0.1), keyframe(1.0 / 3.0, 1e-7, std::f32::consts::PI)];
+        assert_eq!(parse(&format(&keyframes)), Ok(keyframes));
+
+        assert_eq!(parse("// time x y z\n\n0 0 0 0 0 0 0 1 70 // start\n").unwrap().len(), 1);
+        assert_eq!(parse("0 0 0 0 0 0 0 1 70\n0 0 0 0 0 0 0 1\n"), Err("line 2: expected 9 numbers, the time, position, rotation and fov".to_string()));
+        assert_eq!(parse("1 0 0 0 0 0 0 1 70\n0 0 0 0 0 0 0 1 70\n"), Err("line 2: the keyframes are not in the order of their times".to_string()));
+        assert_eq!(parse("0 0 0 0 0 0 0 1 70\n\n1 0 0 0 0 0 0 0 70\n"), Err("line 3: the rotation has to be a quaternion of non-zero length".to_string()));
+        for fov in ["0", "-70", "180"] {
+            assert_eq!(parse(&std::format!("0 0 0 0 0 0 0 1 {fov}\n")), Err("line 1: the fov has to be between 0 and 180 degrees".to_string()));
+        }
+        assert_eq!(parse("0 0 0 0 0 0 0 1 inf\n"), Err("line 1: expected 9 numbers, the time, position, rotation and fov".to_string()));
+    }
+}
@@ -0,0 +1,204 @@
+use glam::{Vec2, Vec3};
+use winit::{event::{DeviceEvent, ElementState, KeyEvent, MouseButton, WindowEvent}, keyboard::{Key, KeyCode, ModifiersState, NamedKey, PhysicalKey}};
+
+
+// First person camera. WASD move, Q and E go down and up, Shift is faster and Control slower.
+// The mouse looks around while the cursor is grabbed, Escape releases it and a click grabs it again.
+pub struct FlyController {
+    position: Vec3,
+    // Around the Y axis, 0 looks towards +Z
+    yaw: f32,
+    pitch: f32,
+    velocity: Vec3,
+    // Movement keys held down, in the order of `KEYS`
+    held: [bool; 6],
+    modifiers: ModifiersState,
+    grabbed: bool
+}
+
+impl FlyController {
+    // Forward, back, left, right, down and up by their position, so any keyboard layout works
+    const KEYS: [KeyCode; 6] = [KeyCode::KeyW, KeyCode::KeyS, KeyCode::KeyA, KeyCode::KeyD, KeyCode::KeyQ, KeyCode::KeyE];
+    // Units per second
+    const SPEED: f32 = 10.0;
+    const FAST: f32 = 5.0;
+    const SLOW: f32 = 0.2;
+    // Radians per unit of mouse motion
+    const LOOK_SPEED: f32 = 0.002;
+    const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+    // How fast the velocity follows the keys, higher stops sooner
+    const DAMPING: f32 = 12.0;
+
+    pub fn new(position: Vec3, target: Vec3) -> Self {
+        let mut fly = FlyController {
+            position,
+            yaw: 0.0,
+            pitch: 0.0,
+            velocity: Vec3::ZERO,
+            held: [false; 6],
+            modifiers: ModifiersState::default(),
+            grabbed: false
+        };
+        fly.look_at(position, target);
+        fly
+    }
+
+    pub fn look_at(&mut self, position: Vec3, target: Vec3) {
+        let direction = (target - position).normalize();
+        self.position = position;
+        self.yaw = direction.x.atan2(direction.z);
+        self.pitch = direction.y.asin().clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+        self.velocity = Vec3::ZERO;
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        Vec3::new(self.pitch.cos() * self.yaw.sin(), self.pitch.sin(), self.pitch.cos() * self.yaw.cos())
+    }
+
+    pub fn grabbed(&self) -> bool {
+        self.grabbed
+    }
+
+    pub fn set_grabbed(&mut self, grabbed: bool) {
+        self.grabbed = grabbed;
+        if !grabbed {
+            self.held = [false; 6];
+        }
+    }
+
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput { event: KeyEvent { physical_key, logical_key, state, .. }, .. } => {
+                if *logical_key == Key::Named(NamedKey::Escape) && self.grabbed {
+                    self.set_grabbed(false);
+                    return true;
+                }
+
+                match physical_key {
+                    PhysicalKey::Code(code) => self.press(*code, *state == ElementState::Pressed),
+                    PhysicalKey::Unidentified(_) => false
+                }
+            },
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+                false
+            },
+            WindowEvent::MouseInput { state: ElementState::Pressed, button: MouseButton::Left, .. } if !self.grabbed => {
+                self.set_grabbed(true);
+                true
+            },
+            // Keys released while the window is in the background never arrive
+            WindowEvent::Focused(false) => {
+                self.set_grabbed(false);
+                false
+            },
+            _ => false
+        }
+    }
+
+    // The movement keys only move the camera while the cursor is grabbed, otherwise they are left
+    // to the other shortcuts
+    fn press(&mut self, code: KeyCode, pressed: bool) -> bool {
+        match Self::KEYS.iter().position(|key| *key == code) {
+            Some(index) if self.grabbed => {
+                self.held[index] = pressed;
+                true
+            },
+            _ => false
+        }
+    }
+
+    // Raw motion keeps coming at the edge of the screen, unlike the cursor position
+    pub fn device_input(&mut self, event: &DeviceEvent) -> bool {
+        match event {
+            DeviceEvent::MouseMotion { delta: (x, y) } if self.grabbed => {
+                self.look(Vec2::new(*x as f32, *y as f32));
+                true
+            },
+            _ => false
+        }
+    }
+
+    // `elapsed_frame` in seconds, so the speed is the same at any frame rate
+    pub fn update(&mut self, elapsed_frame: f32) {
+        let axis = |negative: usize, positive: usize| self.held[positive] as i32 as f32 - self.held[negative] as i32 as f32;
+        let forward = self.forward();
+        let right = forward.cross(Vec3::Y).normalize();
+        let direction = forward * axis(1, 0) + right * axis(2, 3) + Vec3::Y * axis(4, 5);
+
+        let speed = Self::SPEED * match (self.modifiers.shift_key(), self.modifiers.control_key()) {
+            (true, false) => Self::FAST,
+            (false, true) => Self::SLOW,
+            _ => 1.0
+        };
+        // The velocity approaches the goal exponentially, integrated exactly over the frame
+        let goal = direction.normalize_or_zero() * speed;
+        let decay = (-Self::DAMPING * elapsed_frame).exp();
+        self.position += goal * elapsed_frame + (self.velocity - goal) * (1.0 - decay) / Self::DAMPING;
+        self.velocity = goal + (self.velocity - goal) * decay;
+    }
+
+    fn look(&mut self, delta: Vec2) {
+        self.yaw -= delta.x * Self::LOOK_SPEED;
+        self.pitch = (self.pitch - delta.y * Self::LOOK_SPEED).clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+    use winit::keyboard::KeyCode;
+    use super::FlyController;
+
+    #[test]
+    fn looks_at_the_target() {
+        let mut fly = FlyController::new(Vec3::new(0.0, 5.0, 30.0), Vec3::ZERO);
+        assert!(fly.forward().abs_diff_eq(Vec3::new(0.0, -5.0, -30.0).normalize(), 1e-5), "{}", fly.forward());
+
+        // Moving the mouse right turns right, towards +X when looking down -Z
+        fly.look(Vec2::new(100.0, 0.0));
+        assert!(fly.forward().x > 0.0);
+        fly.look(Vec2::new(0.0, -100000.0));
+        assert_eq!(fly.pitch, FlyController::MAX_PITCH);
+    }
+
+    #[test]
+    fn moves_the_same_at_any_frame_rate() {
+        let fly = |steps: usize| {
+            let mut fly = FlyController::new(Vec3::ZERO, Vec3::new(0.0, 0.0, -1.0));
+            fly.held[0] = true;
+            for _ in 0..steps {
+                fly.update(2.0 / steps as f32);
+            }
+            fly.position()
+        };
+
+        // Two seconds forward at full speed, minus the time taken to speed up
+        let position = fly(120);
+        assert!(position.z < -19.0 && position.z > -20.0 && position.x.abs() < 1e-4, "{position}");
+        assert!(position.abs_diff_eq(fly(480), 1e-3), "{position} and {}", fly(480));
+    }
+
+    #[test]
+    fn moves_only_while_grabbed() {
+        let mut fly = FlyController::new(Vec3::ZERO, Vec3::new(0.0, 0.0, -1.0));
+        assert!(!fly.press(KeyCode::KeyE, true));
+        fly.update(1.0);
+        assert_eq!(fly.position(), Vec3::ZERO);
+
+        fly.set_grabbed(true);
+        assert!(fly.press(KeyCode::KeyE, true));
+        assert!(!fly.press(KeyCode::KeyR, true));
+        fly.update(1.0);
+        assert!(fly.position().y > 0.0);
+
+        // Releasing the cursor lets go of the keys
+        fly.set_grabbed(false);
+        assert!(!fly.press(KeyCode::KeyE, false));
+        assert_eq!(fly.held, [false; 6]);
+    }
+}
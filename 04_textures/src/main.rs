@@ -1,25 +1,104 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
+use common::time::{Time, TimeMode};
+use options::{HeadlessOptions, Options};
 use winit::{event::{Event, WindowEvent}, event_loop::EventLoop, window::WindowBuilder};
 
 mod app_state;
 mod uniform;
 mod camera;
+mod orbit;
+mod fly;
+mod camera_path;
 mod vertex;
 mod texture;
+mod options;
 
 fn main() {
-   pollster::block_on(run());
+    let options = Options::from_args().unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(2);
+    });
+
+    match options.headless {
+        Some(ref headless) => pollster::block_on(run_headless(&options, headless)),
+        None => pollster::block_on(run(&options))
+    }
+}
+
+fn model_path(options: &Options) -> PathBuf {
+    options.model.clone().unwrap_or_else(vertex::default_model)
+}
+
+fn create_time(options: &Options, default_mode: TimeMode) -> Time {
+    let mode = options.fixed_step.map_or(default_mode, TimeMode::FixedStep);
+    let mut time = Time::new(mode);
+    time.set_elapsed(options.time.unwrap_or(0.0));
+    time.set_paused(options.paused);
+    time
 }
 
-async fn run() {
+fn set_camera(app_state: &mut app_state::AppState, options: &Options) {
+    app_state.set_projection(options.projection);
+    if options.reversed_z {
+        app_state.set_reversed_z(true);
+    }
+    if let Err(error) = app_state.set_camera_path(options.camera_path.clone()) {
+        // Only a path given on the command line has to load, the one next to the shader is optional
+        if options.camera_path.is_some() {
+            eprintln!("{error}");
+            std::process::exit(2);
+        }
+        eprintln!("{error}\nStarting without a camera path, recorded keyframes are not saved");
+    }
+}
+
+fn set_palette(app_state: &mut app_state::AppState, options: &Options) {
+    if let Some(palette) = &options.palette {
+        if let Err(error) = app_state.set_palette(palette) {
+            eprintln!("{error}");
+            std::process::exit(2);
+        }
+    }
+    app_state.set_palette_cycle(options.palette_cycle);
+}
+
+async fn run_headless(options: &Options, headless: &HeadlessOptions) {
+    let mut app_state = app_state::AppState::new_headless(options.width, options.height, headless.software, &model_path(options)).await;
+    app_state.set_time(create_time(options, TimeMode::FixedStep(1.0 / 60.0)));
+    if let Some(path) = &options.params {
+        app_state.set_params_file(path.clone());
+    }
+    app_state.set_constants(options.constants_file.clone(), &options.constants);
+    set_camera(&mut app_state, options);
+    set_palette(&mut app_state, options);
+    std::fs::create_dir_all(&headless.out).unwrap();
+
+    for frame in 0..headless.frames {
+        app_state.update();
+        app_state.render().unwrap();
+
+        let path = headless.out.join(format!("frame_{frame:04}.png"));
+        app_state.capture().unwrap().save(&path).unwrap();
+        println!("Saved {}", path.display());
+    }
+}
+
+async fn run(options: &Options) {
     let event_loop = EventLoop::new().unwrap();
     let window = WindowBuilder::new()
-    .with_inner_size(winit::dpi::PhysicalSize::new(1600, 900))
+    .with_inner_size(winit::dpi::PhysicalSize::new(options.width, options.height))
     .build(&event_loop).unwrap();
 
     let window = Arc::new(window);
-    let mut app_state = app_state::AppState::new(window.clone()).await;
+    let mut app_state = app_state::AppState::new(window.clone(), &model_path(options)).await;
+    app_state.set_time(create_time(options, TimeMode::RealTime));
+    if let Some(path) = &options.params {
+        app_state.set_params_file(path.clone());
+    }
+    app_state.set_constants(options.constants_file.clone(), &options.constants);
+    set_camera(&mut app_state, options);
+    set_palette(&mut app_state, options);
 
     event_loop.run(move |event, elwt| {
         match event {
@@ -27,20 +106,36 @@ async fn run() {
                 window_id, 
                 event 
             } if window.id() == window_id => {
+                if app_state.input(&event) {
+                    return;
+                }
+
                 match event {
                     WindowEvent::CloseRequested => {
                         elwt.exit();
                     },
+                    WindowEvent::Resized(new_size) => {
+                        app_state.resize(new_size);
+                    },
                     WindowEvent::RedrawRequested => {
                         app_state.update();
                         match app_state.render() {
+                            Ok(_) => {},
+                            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                                app_state.reconfigure();
+                            },
                             Err(wgpu::SurfaceError::OutOfMemory) => { elwt.exit() },
-                            _ => {}
+                            Err(wgpu::SurfaceError::Timeout) => {
+                                eprintln!("Surface timeout, retrying on the next frame");
+                            }
                         }
                     },
                     _ => {}
                 }
             },
+            Event::DeviceEvent { event, .. } => {
+                app_state.device_input(&event);
+            },
             Event::AboutToWait => {
                 window.request_redraw();
             },
@@ -0,0 +1,273 @@
+use std::f32::consts::FRAC_PI_2;
+
+use glam::{Vec2, Vec3};
+use winit::{event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent}, keyboard::{Key, ModifiersState}};
+
+
+// Circles the camera around a target point. Dragging with the left mouse button rotates, the
+// scroll wheel zooms and dragging with the middle button, or the left one with Shift held, pans.
+// R toggles the auto-rotation. Input moves a goal that the camera eases towards, which damps the motion.
+// The auto-rotation is added on top by the shader time, so jumping to a time shows the view playing up to it does.
+pub struct OrbitController {
+    goal: Orbit,
+    current: Orbit,
+    auto_rotate: bool,
+    time: f32,
+    modifiers: ModifiersState,
+    cursor: Option<Vec2>,
+    drag: Option<Drag>
+}
+
+#[derive(Clone, Copy)]
+struct Orbit {
+    target: Vec3,
+    // Around the Y axis, 0 looks from +Z
+    yaw: f32,
+    pitch: f32,
+    distance: f32
+}
+
+#[derive(Clone, Copy)]
+enum Drag {
+    Rotate,
+    Pan
+}
+
+impl OrbitController {
+    // Radians per second
+    const AUTO_ROTATE_SPEED: f32 = 0.5;
+    // Radians per pixel dragged
+    const ROTATE_SPEED: f32 = 0.005;
+    // Fraction of the distance per pixel dragged
+    const PAN_SPEED: f32 = 0.0015;
+    // Distance factor per line scrolled
+    const ZOOM_STEP: f32 = 0.9;
+    const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+    const DISTANCE_RANGE: (f32, f32) = (0.5, 500.0);
+    // How fast the camera catches up with the goal, higher is stiffer
+    const DAMPING: f32 = 10.0;
+
+    pub fn new(position: Vec3, target: Vec3) -> Self {
+        let orbit = Orbit::look_at(position, target);
+
+        OrbitController {
+            goal: orbit,
+            current: orbit,
+            auto_rotate: true,
+            time: 0.0,
+            modifiers: ModifiersState::default(),
+            cursor: None,
+            drag: None
+        }
+    }
+
+    // Jumps to the new view without easing
+    pub fn look_at(&mut self, position: Vec3, target: Vec3) {
+        self.goal = Orbit::look_at(position, target);
+        self.goal.yaw -= self.spin();
+        self.current = self.goal;
+    }
+
+    pub fn position(&self) -> Vec3 {
+        Orbit { yaw: self.current.yaw + self.spin(), ..self.current }.position()
+    }
+
+    pub fn target(&self) -> Vec3 {
+        self.current.target
+    }
+
+    pub fn distance(&self) -> f32 {
+        self.current.distance
+    }
+
+    pub fn handle_key(&mut self, key: &Key) -> bool {
+        match key {
+            Key::Character(c) if c == "r" => {
+                // The view stays where it is, the rotation stops or starts from there
+                let spin = self.spin();
+                self.auto_rotate = !self.auto_rotate;
+                self.goal.yaw += spin - self.spin();
+                self.current.yaw += spin - self.spin();
+                println!("Auto-rotation {}", if self.auto_rotate { "on" } else { "off" });
+                true
+            },
+            _ => false
+        }
+    }
+
+    // The cursor and modifier events are left for the others to see as well
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+                false
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                let cursor = Vec2::new(position.x as f32, position.y as f32);
+                if let (Some(drag), Some(previous)) = (self.drag, self.cursor) {
+                    self.drag_by(drag, cursor - previous);
+                }
+                self.cursor = Some(cursor);
+                false
+            },
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor = None;
+                false
+            },
+            WindowEvent::MouseInput { state, button, .. } => {
+                let drag = match button {
+                    MouseButton::Left if self.modifiers.shift_key() => Drag::Pan,
+                    MouseButton::Left => Drag::Rotate,
+                    MouseButton::Middle => Drag::Pan,
+                    _ => return false
+                };
+                self.drag = match state {
+                    ElementState::Pressed => Some(drag),
+                    ElementState::Released => None
+                };
+                true
+            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 100.0
+                };
+                self.zoom(lines);
+                true
+            },
+            _ => false
+        }
+    }
+
+    // `time` is the shader time and `elapsed_frame` the time since the last update in seconds,
+    // so the easing takes the same time at any frame rate
+    pub fn update(&mut self, time: f32, elapsed_frame: f32) {
+        self.time = time;
+
+        let t = 1.0 - (-Self::DAMPING * elapsed_frame).exp();
+        self.current = Orbit {
+            target: self.current.target.lerp(self.goal.target, t),
+            yaw: self.current.yaw + (self.goal.yaw - self.current.yaw) * t,
+            pitch: self.current.pitch + (self.goal.pitch - self.current.pitch) * t,
+            distance: self.current.distance + (self.goal.distance - self.current.distance) * t
+        };
+    }
+
+    fn drag_by(&mut self, drag: Drag, delta: Vec2) {
+        match drag {
+            Drag::Rotate => {
+                self.goal.yaw -= delta.x * Self::ROTATE_SPEED;
+                self.goal.pitch = (self.goal.pitch + delta.y * Self::ROTATE_SPEED).clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+            },
+            Drag::Pan => {
+                // The target follows the cursor across the screen
+                let goal = Orbit { yaw: self.goal.yaw + self.spin(), ..self.goal };
+                let forward = (goal.target - goal.position()).normalize();
+                let right = forward.cross(Vec3::Y).normalize();
+                let up = right.cross(forward);
+                self.goal.target += (up * delta.y - right * delta.x) * self.goal.distance * Self::PAN_SPEED;
+            }
+        }
+    }
+
+    // Yaw the auto-rotation has added by now
+    fn spin(&self) -> f32 {
+        match self.auto_rotate {
+            true => Self::AUTO_ROTATE_SPEED * self.time,
+            false => 0.0
+        }
+    }
+
+    fn zoom(&mut self, lines: f32) {
+        let (min, max) = Self::DISTANCE_RANGE;
+        self.goal.distance = (self.goal.distance * Self::ZOOM_STEP.powf(lines)).clamp(min, max);
+    }
+}
+
+impl Orbit {
+    fn look_at(position: Vec3, target: Vec3) -> Self {
+        let offset = position - target;
+        let distance = offset.length();
+
+        Orbit {
+            target,
+            yaw: offset.x.atan2(offset.z),
+            pitch: (offset.y / distance).asin().clamp(-OrbitController::MAX_PITCH, OrbitController::MAX_PITCH),
+            distance
+        }
+    }
+
+    fn position(&self) -> Vec3 {
+        let direction = Vec3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos()
+        );
+        self.target + direction * self.distance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+    use winit::keyboard::Key;
+    use super::{Drag, OrbitController};
+
+    #[test]
+    fn starts_at_the_given_position() {
+        let orbit = OrbitController::new(Vec3::new(0.0, 5.0, 30.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(orbit.position().abs_diff_eq(Vec3::new(0.0, 5.0, 30.0), 1e-4), "{}", orbit.position());
+        assert_eq!(orbit.target(), Vec3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn rotates_by_the_time() {
+        let mut orbit = OrbitController::new(Vec3::new(0.0, 0.0, 10.0), Vec3::ZERO);
+        orbit.update(std::f32::consts::PI, 0.0);
+        assert!(orbit.position().abs_diff_eq(Vec3::new(10.0, 0.0, 0.0), 1e-4), "{}", orbit.position());
+
+        // Stopping keeps the view, and so does looking from a new position
+        orbit.handle_key(&Key::Character("r".into()));
+        orbit.update(10.0, 0.1);
+        assert!(orbit.position().abs_diff_eq(Vec3::new(10.0, 0.0, 0.0), 1e-4), "{}", orbit.position());
+        orbit.handle_key(&Key::Character("r".into()));
+        orbit.look_at(Vec3::new(0.0, 0.0, -10.0), Vec3::ZERO);
+        assert!(orbit.position().abs_diff_eq(Vec3::new(0.0, 0.0, -10.0), 1e-4), "{}", orbit.position());
+
+        // Going back in time turns the camera back, by a quarter turn here
+        orbit.update(10.0 - std::f32::consts::PI, 0.1);
+        assert!(orbit.position().abs_diff_eq(Vec3::new(10.0, 0.0, 0.0), 1e-4), "{}", orbit.position());
+    }
+
+    #[test]
+    fn eases_towards_the_clamped_goal() {
+        let dragged = || {
+            let mut orbit = OrbitController::new(Vec3::new(0.0, 0.0, 10.0), Vec3::ZERO);
+            orbit.auto_rotate = false;
+            orbit.drag_by(Drag::Rotate, Vec2::new(0.0, 10000.0));
+            orbit.zoom(-1000.0);
+            orbit
+        };
+
+        let mut orbit = dragged();
+        assert_eq!(orbit.goal.pitch, OrbitController::MAX_PITCH);
+        assert_eq!(orbit.goal.distance, OrbitController::DISTANCE_RANGE.1);
+
+        // Nothing moves until time passes
+        orbit.update(0.0, 0.0);
+        assert!(orbit.position().abs_diff_eq(Vec3::new(0.0, 0.0, 10.0), 1e-4));
+
+        // Two half steps end where a whole one does
+        let mut halves = dragged();
+        orbit.update(0.1, 0.1);
+        halves.update(0.05, 0.05);
+        halves.update(0.1, 0.05);
+        assert!((orbit.current.distance - halves.current.distance).abs() < 1e-2);
+        assert!(orbit.current.pitch > 0.0 && orbit.current.pitch < OrbitController::MAX_PITCH);
+
+        for _ in 0..100 {
+            orbit.update(0.0, 0.1);
+        }
+        assert!((orbit.current.pitch - OrbitController::MAX_PITCH).abs() < 1e-4);
+    }
+}
@@ -1,9 +1,14 @@
 
 struct UniformParameters {
     view_matrix: mat4x4<f32>,
-    perspective_matrix: mat4x4<f32>,
-    inv_perspective_matrix: mat4x4<f32>,
-    time: f32
+    inv_view_matrix: mat4x4<f32>,
+    projection_matrix: mat4x4<f32>,
+    inv_projection_matrix: mat4x4<f32>,
+    resolution: vec2f,
+    mouse: vec2f,
+    time: f32,
+    pixel_ratio: f32,
+    frame: u32
 }
 
 struct InputVertex {
@@ -24,12 +29,32 @@ struct OutputVertex {
 @group(1) @binding(0) var sky_texture: texture_cube<f32>;
 @group(1) @binding(1) var sky_sampler: sampler;
 
-const resolution: vec2f = vec2f(1600.0, 900.0);
+// Tweakable at runtime, see README.md
+struct Params {
+    uv_scale: f32, // 100.0, 1.0..400.0
+    octaves: i32, // 2, 1..8
+    light_dir: vec3f // 1.0, -1.0..1.0
+}
+
+@group(2) @binding(0) var<uniform> params: Params;
+
+// Pipeline constants, set in shader.constants or with --constant, see README.md
+override warp_strength: f32 = 1.0;
+override warp_seed: f32 = 0.0;
+// Set by the app to match the depth test, the far plane is at depth 0 instead of 1
+override reversed_z: bool = false;
+
+// The palette selected with --palette or the P key
+@group(3) @binding(0) var palette_lut: texture_2d<f32>;
+@group(3) @binding(1) var palette_sampler: sampler;
+
+#include "noise.wgsl"
+#include "palette.wgsl"
 
 @vertex
 fn vs_main(in_vert: InputVertex) -> OutputVertex {
     var out_vert: OutputVertex;
-    out_vert.position = uniforms.perspective_matrix * uniforms.view_matrix * vec4f(in_vert.position * 5.0, 1.0);
+    out_vert.position = uniforms.projection_matrix * uniforms.view_matrix * vec4f(in_vert.position * 5.0, 1.0);
     out_vert.normal = in_vert.normal;
     out_vert.uv = in_vert.uv;
     out_vert.color = in_vert.color;
@@ -38,11 +63,10 @@ fn vs_main(in_vert: InputVertex) -> OutputVertex {
 
 @fragment
 fn fs_main(frag: OutputVertex) -> @location(0) vec4f {   
-    let noise = domainWarp(frag.uv * 100.0);
-    let diffuse_color = vec3f(pallete(noise));
+    let noise = domainWarpWith(frag.uv * params.uv_scale, params.octaves, warp_strength, warp_seed);
+    let diffuse_color = palette(noise);
 
-    let light_dir = vec3f(1.0, 1.0, 1.0);
-    let diffuse_value = saturate(dot(frag.normal, normalize(light_dir)));
+    let diffuse_value = saturate(dot(frag.normal, normalize(params.light_dir)));
     let color = diffuse_color * diffuse_value;
 
     return vec4f(color, 1.0);
@@ -62,24 +86,27 @@ fn sky_vs_main(@builtin(vertex_index) id: u32) -> SkyVSOut {
     let x = i32(id) & 2;
     let y = i32(id) & 1;
 
+    let near_depth = select(0.0, 1.0, reversed_z);
+    let far_depth = 1.0 - near_depth;
+
+    // On the far plane, behind everything else
     let pos = vec4f(
         f32(x) * 4.0 - 1.0,
         1.0 - f32(y) * 4.0,
-        1.0,
+        far_depth,
         1.0
     );
 
-    let view_inv = transpose(mat3x3<f32>(
-        uniforms.view_matrix[0].xyz,
-        uniforms.view_matrix[1].xyz,
-        uniforms.view_matrix[2].xyz
-    ));
-
-    let view_pos = uniforms.inv_perspective_matrix * pos;
+    // The ray through the pixel goes from its point on the near plane to the one on the far plane.
+    // They are not divided by w, which is 0 on an infinite far plane, and the rays of an
+    // orthographic projection come out parallel.
+    let near = uniforms.inv_projection_matrix * vec4f(pos.xy, near_depth, 1.0);
+    let far = uniforms.inv_projection_matrix * vec4f(pos.xy, far_depth, 1.0);
+    let direction = far.xyz * near.w - near.xyz * far.w;
 
     var out_vert: SkyVSOut;
     out_vert.position = pos;
-    out_vert.uv = view_inv * view_pos.xyz;
+    out_vert.uv = (uniforms.inv_view_matrix * vec4f(direction, 0.0)).xyz;
 
     return out_vert;
 }
@@ -88,79 +115,3 @@ fn sky_vs_main(@builtin(vertex_index) id: u32) -> SkyVSOut {
 fn sky_fs_main(frag: SkyVSOut) -> @location(0) vec4f {   
     return textureSample(sky_texture, sky_sampler, frag.uv);
 }
-
-
-
-
-
-//Misc 
-fn pallete(t: f32) -> vec3f {
-    let offset = vec3f(0.500, 0.500, 0.500); 
-    let amp = vec3f(0.500, 0.500, 0.500);
-    let freq =  vec3f(0.800, 0.800, 0.500);
-    let phase = vec3f(0.000, 0.200, 0.500);
-
-    return offset + amp * cos( 6.28 * (freq * t + phase));
-}
-
-fn domainWarp(pos2: vec2f) -> f32 {
-    let r = length(pos2);
-    let t = length(atan(pos2));
-    let pos = vec2f(r, t);
-    let offset = vec2f(
-        fbm(pos + vec2f(15.424, 42.14), 2),
-        fbm(pos + vec2f(74.824, 378.54), 2)
-    );
-
-    return fbm(pos + offset, 2);
-}
-
-fn fbm(pos: vec2f, octaves: i32) -> f32 {
-    var accum = 0.0;
-    var result = 0.0;
-    var freq = 1.0;
-    var amp = 1.0;
-
-    for(var i = 0; i < octaves; i += 1) {
-        let noise = perlinNoise(pos * freq) * 0.5 + 0.5;
-        result += noise * amp;
-        accum += amp;
-        freq *= 2.0;
-        amp *= 0.5;
-    }
-
-    return result / accum;
-}
-
-fn perlinNoise(pos: vec2f) -> f32 {
-    let base = floor(pos);
-    let dist = fract(pos);
-
-    let d1 = dot(randomGradient(base + vec2f(0.0, 0.0)), dist - vec2f(0.0, 0.0));
-    let d2 = dot(randomGradient(base + vec2f(1.0, 0.0)), dist - vec2f(1.0, 0.0));
-    let d3 = dot(randomGradient(base + vec2f(0.0, 1.0)), dist - vec2f(0.0, 1.0));
-    let d4 = dot(randomGradient(base + vec2f(1.0, 1.0)), dist - vec2f(1.0, 1.0));
-
-    let k = smoothstep(vec2f(0.0), vec2f(1.0), dist);
-
-    let lerp1 = mix(d1, d2, k.x);
-    let lerp2 = mix(d3, d4, k.x);
-
-    return mix(lerp1, lerp2, k.y);
-}
-
-fn randomGradient(posf: vec2f) -> vec2f {
-    let posi = vec2i(posf);
-    let w = u32(32);
-    let s = w / 2; // rotation width
-    var a = u32(posi.x);
-    var b = u32(posi.y);
-    a *= u32(3284157443); b ^= a << s | a >> w-s;
-    b *= u32(1911520717); a ^= b << s | b >> w-s;
-    a *= u32(2048419325);
-    let random = 0.5 * uniforms.time * f32(a) * (3.14159265 / f32(~(~u32(0) >> 1))); // in [0, 2*Pi]
-
-    return vec2f(cos(random), sin(random));
-}
-
-
@@ -1,11 +1,9 @@
 use project_root::get_project_root;
-use wgpu::{naga::back::msl::sampler, ImageCopyTexture};
 
 
 
+// The bind group keeps the texture and the sampler alive
 pub struct CubeMap {
-    pub texture: wgpu::Texture,
-    pub sampler: wgpu::Sampler,
     pub bind_group_layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup
 }
@@ -25,7 +23,7 @@ impl CubeMap {
             "yellowcloud_bk.jpg",
         ];
 
-        let faces_img = faces_str.into_iter().enumerate().map(|(id,f)| {
+        let faces_img = faces_str.iter().enumerate().map(|(id,f)| {
             let mut root = root.clone();
             root.push(f);
 
@@ -130,8 +128,6 @@ impl CubeMap {
 
         
         CubeMap {
-            texture,
-            sampler,
             bind_group_layout,
             bind_group
         }
@@ -1,57 +1,39 @@
-use bytemuck::NoUninit;
+use common::time::{Time, TimeMode};
+use gpu_layout::UniformLayout;
 
 use crate::camera::{Camera, CameraRaw};
 
 
-pub struct Time {
-    start: std::time::Instant,
-    prev_frame: std::time::Instant,
-    elapsed_frame: f32
-}
-
-impl Time {
-    pub fn new() -> Self {
-        Time {
-            start: std::time::Instant::now(),
-            prev_frame: std::time::Instant::now(),
-            elapsed_frame: 0.0
-        }
-    }
-
-    pub fn update(&mut self) {
-        let now = std::time::Instant::now();
-        self.elapsed_frame = (now - self.prev_frame).as_secs_f32();
-        self.prev_frame = now;
-    }
-
-    pub fn elapsed(&self) -> f32 {
-        self.start.elapsed().as_secs_f32()
-    }
-}
-
-#[repr(C)]
-#[derive(Clone, Copy, NoUninit)]
+#[derive(UniformLayout)]
 pub struct UniformRaw {
+    #[uniform(flatten)]
     camera: CameraRaw,
+    resolution: [f32; 2],
+    mouse: [f32; 2],
     time: f32,
-    _padding: [f32; 3]
+    pixel_ratio: f32,
+    frame: u32
 }
 
 pub struct Uniform {
     time: Time,
     camera: Camera,
+    resolution: [f32; 2],
+    // Cursor position in physical pixels from the top left corner
+    mouse: [f32; 2],
+    pixel_ratio: f32,
     buffer: wgpu::Buffer,
     pub bind_group_layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup
 }
 
 impl Uniform {
-    pub fn new(device: &wgpu::Device, camera: Camera) -> Self {
-        let time = Time::new();
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, camera: Camera) -> Self {
+        let time = Time::new(TimeMode::RealTime);
 
         let buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
-            size: std::mem::size_of::<UniformRaw>() as u64,
+            size: UniformRaw::SIZE as u64,
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false
         });
@@ -89,21 +71,78 @@ impl Uniform {
             buffer,
             bind_group,
             bind_group_layout,
-            camera
+            camera,
+            resolution: [width as f32, height as f32],
+            mouse: [0.0; 2],
+            pixel_ratio: 1.0
         }
     }
 
+    pub fn set_time(&mut self, time: Time) {
+        self.time = time;
+    }
+
+    pub fn time(&self) -> &Time {
+        &self.time
+    }
+
+    pub fn time_mut(&mut self) -> &mut Time {
+        &mut self.time
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    pub fn set_mouse(&mut self, x: f32, y: f32) {
+        self.mouse = [x, y];
+    }
+
+    pub fn set_pixel_ratio(&mut self, pixel_ratio: f32) {
+        self.pixel_ratio = pixel_ratio;
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.resolution = [width as f32, height as f32];
+        self.camera.set_aspect_ratio(width as f32 / height as f32);
+    }
+
     pub fn update(&mut self, queue: &wgpu::Queue) {
         self.time.update();
-        self.camera.update(self.time.elapsed_frame);
-        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.as_raw()]));
+        self.camera.update(&self.time);
+        queue.write_buffer(&self.buffer, 0, &self.as_raw().to_bytes());
     }
 
     pub fn as_raw(&self) -> UniformRaw {
         UniformRaw { 
             camera: self.camera.as_raw(), 
+            resolution: self.resolution,
+            mouse: self.mouse,
             time: self.time.elapsed(),
-            _padding: [0.0, 0.0, 0.0]
+            pixel_ratio: self.pixel_ratio,
+            frame: self.time.frame()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use common::{layout, preprocessor::preprocess, shader};
+    use gpu_layout::UniformLayout;
+    use super::UniformRaw;
+
+    #[test]
+    fn uniform_raw_matches_the_shader() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let source = preprocess(&dir.join("src/shaders/shader.wgsl"), &[dir.join("../shaders")], &["ANIMATED_NOISE".to_string()], |path| std::fs::read_to_string(path))
+            .unwrap_or_else(|error| panic!("{error}"));
+        let module = shader::validate(&source).unwrap_or_else(|error| panic!("{error}"));
+
+        if let Err(error) = layout::check(&source, &module, "UniformParameters", &UniformRaw::layout()) {
+            panic!("{error}");
         }
     }
-}
\ No newline at end of file
+
+}
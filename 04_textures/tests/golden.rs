@@ -0,0 +1,27 @@
+//! Golden-image tests, see `common::golden`.
+//!
+//! Run `UPDATE_GOLDEN=1 cargo test` to regenerate the references after an intended change.
+
+use std::path::Path;
+
+use common::golden::Golden;
+
+
+fn golden() -> Golden {
+    Golden::new(env!("CARGO_BIN_EXE_step_04"), env!("CARGO_MANIFEST_DIR"), env!("CARGO_TARGET_TMPDIR"))
+        // The bunny is not in the repository, the references show a small sphere instead
+        .arg("--model").arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("../assets/sphere.obj"))
+        // Values tweaked while running the app are saved next to the shader, the tests use the defaults
+        .saved_to("--params", "test.params")
+        .saved_to("--constants", "test.constants")
+}
+
+#[test]
+fn sky_and_model_at_one_second() {
+    golden().check("sky_model_1s", 1.0, &[]);
+}
+
+#[test]
+fn sky_and_model_at_two_and_a_half_seconds() {
+    golden().check("sky_model_2_5s", 2.5, &[]);
+}
@@ -0,0 +1,191 @@
+use std::{collections::HashMap, path::{Path, PathBuf}, time::{Duration, Instant, SystemTime}};
+
+use wgpu::naga;
+use crate::{preprocessor::{Preprocessed, SourceFile}, shader::ShaderError};
+
+
+// Values of the `override` declarations of a shader, passed to its pipelines through
+// `PipelineCompilationOptions::constants`. They are read from a file with one `name = value`
+// per line, which is watched for changes, and `--constant NAME=VALUE` takes precedence over it.
+pub struct Constants {
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+    from_file: HashMap<String, f64>,
+    from_args: HashMap<String, f64>
+}
+
+impl Default for Constants {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Constants {
+    const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+    pub fn new() -> Self {
+        Constants {
+            path: None,
+            modified: None,
+            last_check: Instant::now(),
+            from_file: HashMap::new(),
+            from_args: HashMap::new()
+        }
+    }
+
+    pub fn set_file(&mut self, path: PathBuf) {
+        self.path = Some(path);
+    }
+
+    pub fn set(&mut self, name: &str, value: f64) {
+        self.from_args.insert(name.to_string(), value);
+    }
+
+    // Reads the file again, a missing file sets nothing
+    pub fn load(&mut self) -> Result<(), ShaderError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        self.modified = modified(path);
+
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(ShaderError::unreadable(path, &error))
+        };
+        self.from_file = parse_file(&contents).map_err(|(range, message)| {
+            let file = SourceFile { path: path.clone(), source: contents.clone() };
+            ShaderError::new(&[file], &message, vec![(0, range, String::new())], Vec::new())
+        })?;
+
+        Ok(())
+    }
+
+    pub fn changed(&mut self) -> bool {
+        let Some(path) = &self.path else {
+            return false;
+        };
+        if self.last_check.elapsed() < Self::POLL_INTERVAL {
+            return false;
+        }
+        self.last_check = Instant::now();
+
+        modified(path) != self.modified
+    }
+
+    pub fn values(&self) -> HashMap<String, f64> {
+        let mut values = self.from_file.clone();
+        values.extend(self.from_args.iter().map(|(name, value)| (name.clone(), *value)));
+        values
+    }
+
+    // naga skips the constants a shader does not declare, which would hide a misspelled name
+    pub fn check(&self, source: &Preprocessed) -> Result<(), ShaderError> {
+        // Syntax errors are reported when the shader is compiled
+        let Ok(module) = naga::front::wgsl::parse_str(&source.code) else {
+            return Ok(());
+        };
+        let declared: Vec<String> = module.overrides.iter()
+            .filter_map(|(_, constant)| constant.id.map(|id| id.to_string()).or(constant.name.clone()))
+            .collect();
+
+        let mut names: Vec<&String> = self.from_file.keys().chain(self.from_args.keys()).collect();
+        names.sort();
+        match names.into_iter().find(|name| !declared.contains(name)) {
+            Some(name) => {
+                let declared = match declared.is_empty() {
+                    true => "no override".to_string(),
+                    false => declared.join(", ")
+                };
+                let message = format!("{name} is not a pipeline constant of the shader, it declares {declared}");
+                Err(ShaderError::new(&source.files, &message, Vec::new(), Vec::new()))
+            },
+            None => Ok(())
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+// `NAME=VALUE`, where the value is a number or `true` or `false`
+pub fn parse_constant(text: &str) -> Result<(String, f64), String> {
+    let (name, value) = text.split_once('=').ok_or(format!("invalid constant {text}, expected NAME=VALUE"))?;
+    let (name, value) = (name.trim(), value.trim());
+    if name.is_empty() {
+        return Err(format!("invalid constant {text}, the name is missing"));
+    }
+
+    let value = match value {
+        "true" => 1.0,
+        "false" => 0.0,
+        _ => value.parse().ok().filter(|value: &f64| value.is_finite()).ok_or(format!("invalid value {value} of constant {name}"))?
+    };
+    Ok((name.to_string(), value))
+}
+
+// One `name = value` per line, `//` starts a comment. Errors carry the byte range of their line.
+fn parse_file(contents: &str) -> Result<HashMap<String, f64>, (std::ops::Range<usize>, String)> {
+    let mut values = HashMap::new();
+    let mut start = 0;
+    for line in contents.split_inclusive('\n') {
+        let range = start..start + line.trim_end().len();
+        start += line.len();
+
+        let text = line.split("//").next().unwrap().trim();
+        if text.is_empty() {
+            continue;
+        }
+        let (name, value) = parse_constant(text).map_err(|message| (range, message))?;
+        values.insert(name, value);
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::preprocessor::preprocess;
+    use super::{parse_constant, parse_file, Constants};
+
+    #[test]
+    fn parses_constants() {
+        assert_eq!(parse_constant("warp_strength=1.5"), Ok(("warp_strength".to_string(), 1.5)));
+        assert_eq!(parse_constant(" shadows = true "), Ok(("shadows".to_string(), 1.0)));
+        assert!(parse_constant("warp_strength").is_err());
+        assert!(parse_constant("=1").is_err());
+        assert!(parse_constant("warp_strength=inf").is_err());
+
+        let values = parse_file("// Tuned for the bunny\nwarp_strength = 2\n\nwarp_seed = 0.5 // looks nice\n").unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values["warp_seed"], 0.5);
+        assert_eq!(parse_file("warp_strength = 2\nwarp_seed\n"), Err((18..27, "invalid constant warp_seed, expected NAME=VALUE".to_string())));
+    }
+
+    #[test]
+    fn checks_the_names_against_the_shader() {
+        let code = "
+            override warp_strength: f32 = 1.0;
+            @id(7) override shadows: bool;
+
+            @fragment
+            fn fs_main() -> @location(0) vec4f {
+                return vec4f(select(0.0, warp_strength, shadows));
+            }
+        ";
+        let source = preprocess(Path::new("test.wgsl"), &[], &[], |_| Ok(code.to_string())).unwrap_or_else(|error| panic!("{error}"));
+
+        let mut constants = Constants::new();
+        constants.set("warp_strength", 2.0);
+        constants.set("7", 1.0);
+        assert!(constants.check(&source).is_ok());
+
+        constants.set("warp_strenght", 2.0);
+        let error = constants.check(&source).expect_err("the name is misspelled");
+        assert_eq!(error.summary, "test.wgsl: warp_strenght is not a pipeline constant of the shader, it declares warp_strength, 7");
+    }
+}
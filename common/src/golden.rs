@@ -0,0 +1,117 @@
+use std::{ffi::OsString, path::{Path, PathBuf}, process::Command};
+
+
+// The golden-image harness of the steps' `tests/golden.rs`. Fixed frames are rendered headlessly
+// on the software adapter with a frozen time value and compared against the reference images in
+// the step's `tests/golden`. Run `UPDATE_GOLDEN=1 cargo test` to regenerate the references after
+// an intended change.
+pub struct Golden {
+    exe: PathBuf,
+    references: PathBuf,
+    out: PathBuf,
+    args: Vec<OsString>,
+    // Options naming a file the app saves tweaked values to, with the file name used in the output
+    // directory so the tests always start from the defaults
+    saved: Vec<(&'static str, &'static str)>
+}
+
+impl Golden {
+    pub const WIDTH: u32 = 320;
+    pub const HEIGHT: u32 = 180;
+    // Largest allowed difference of a single color channel
+    pub const TOLERANCE: u8 = 8;
+
+    // `exe` is the step's `CARGO_BIN_EXE_*`, the others its `CARGO_MANIFEST_DIR` and `CARGO_TARGET_TMPDIR`
+    pub fn new(exe: &str, manifest_dir: &str, target_tmpdir: &str) -> Self {
+        let exe = PathBuf::from(exe);
+        let out = Path::new(target_tmpdir).join(exe.file_stem().unwrap_or_default());
+
+        Golden {
+            exe,
+            references: Path::new(manifest_dir).join("tests").join("golden"),
+            out,
+            args: Vec::new(),
+            saved: Vec::new()
+        }
+    }
+
+    // Passed to every render
+    pub fn arg(mut self, arg: impl Into<OsString>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn saved_to(mut self, option: &'static str, file_name: &'static str) -> Self {
+        self.saved.push((option, file_name));
+        self
+    }
+
+    // Renders `name` at `time` with the extra `args` and compares the last frame to `tests/golden/{name}.png`
+    pub fn check(&self, name: &str, time: f32, args: &[&str]) {
+        let actual = self.render(name, time, args);
+        let reference_path = self.reference_path(name);
+
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::create_dir_all(reference_path.parent().unwrap()).unwrap();
+            actual.save(&reference_path).unwrap();
+            return;
+        }
+
+        let reference = match image::open(&reference_path) {
+            Ok(image) => image.to_rgba8(),
+            Err(err) => panic!("missing reference {} ({err}), run with UPDATE_GOLDEN=1 to create it", reference_path.display())
+        };
+        assert_eq!(actual.dimensions(), reference.dimensions(), "{name}: image size differs from the reference");
+
+        let mut diff = image::RgbaImage::new(actual.width(), actual.height());
+        let mut mismatched = 0;
+        for (x, y, pixel) in actual.enumerate_pixels() {
+            let expected = reference.get_pixel(x, y);
+            let max_delta = pixel.0.iter().zip(expected.0).map(|(a, b)| a.abs_diff(b)).max().unwrap();
+
+            if max_delta > Self::TOLERANCE {
+                mismatched += 1;
+                diff.put_pixel(x, y, image::Rgba([255, 0, 0, 255]));
+            } else {
+                let luma = (expected[0] as u32 + expected[1] as u32 + expected[2] as u32) / 6;
+                diff.put_pixel(x, y, image::Rgba([luma as u8, luma as u8, luma as u8, 255]));
+            }
+        }
+
+        if mismatched > 0 {
+            let diff_path = self.output_dir(name).join("diff.png");
+            diff.save(&diff_path).unwrap();
+            panic!("{name}: {mismatched} pixels differ from {} by more than {}, diff written to {}",
+                reference_path.display(), Self::TOLERANCE, diff_path.display());
+        }
+    }
+
+    fn render(&self, name: &str, time: f32, args: &[&str]) -> image::RgbaImage {
+        let out = self.output_dir(name);
+        let mut command = Command::new(&self.exe);
+        command
+            .args(["--headless", "--software"])
+            .args(["--size", &format!("{}x{}", Self::WIDTH, Self::HEIGHT)])
+            .args(["--time", &time.to_string()])
+            .args(&self.args)
+            .args(args)
+            .arg("--out").arg(&out);
+        for (option, file_name) in &self.saved {
+            command.arg(option).arg(out.join(file_name));
+        }
+        let status = command.status().unwrap();
+        assert!(status.success(), "headless render of {name} failed with {status}");
+
+        // The last frame is compared, feedback buffers take a few frames to build up
+        let frames: u32 = args.iter().position(|arg| *arg == "--frames").map_or(1, |index| args[index + 1].parse().unwrap());
+        image::open(out.join(format!("frame_{:04}.png", frames - 1))).unwrap().to_rgba8()
+    }
+
+    fn output_dir(&self, name: &str) -> PathBuf {
+        self.out.join(name)
+    }
+
+    fn reference_path(&self, name: &str) -> PathBuf {
+        self.references.join(format!("{name}.png"))
+    }
+}
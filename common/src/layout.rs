@@ -0,0 +1,186 @@
+use std::ops::Range;
+
+use gpu_layout::HostLayout;
+use wgpu::naga;
+use crate::{preprocessor::Preprocessed, shader::ShaderError};
+
+
+// Compares the struct `name` declared by the shader with `host` member by member, the members
+// are paired in declaration order. Shaders that don't declare the struct are left alone.
+pub fn check(source: &Preprocessed, module: &naga::Module, name: &str, host: &HostLayout) -> Result<(), ShaderError> {
+    let Some((handle, ty)) = module.types.iter().find(|(_, ty)| ty.name.as_deref() == Some(name)) else {
+        return Ok(());
+    };
+    let struct_range = struct_range(&source.code, module.types.get_span(handle));
+    let naga::TypeInner::Struct { members, span: size } = &ty.inner else {
+        return Err(error(source, struct_range, &format!("{name} has to be a struct")));
+    };
+
+    let mut layouter = naga::proc::Layouter::default();
+    if let Err(layout_error) = layouter.update(module.to_ctx()) {
+        return Err(error(source, struct_range, &format!("cannot lay out {name}: {layout_error}")));
+    }
+
+    let mut labels = Vec::new();
+    for (member, field) in members.iter().zip(&host.fields) {
+        let member_name = member.name.as_deref().unwrap_or_default();
+        let member_size = module.types[member.ty].inner.size(module.to_ctx()) as usize;
+        let alignment = layouter[member.ty].alignment;
+        let range = member_range(&source.code, struct_range.clone(), member_name).unwrap_or(struct_range.clone());
+
+        if member.offset as usize != field.offset {
+            labels.push((range, format!(
+                "{name}.{member_name} is at offset {} (aligned to {alignment}) but {}.{} is at offset {}",
+                member.offset, host.name, field.name, field.offset
+            )));
+        } else if member_size != field.size {
+            labels.push((range, format!(
+                "{name}.{member_name} is {member_size} bytes but {}.{} is {} bytes",
+                host.name, field.name, field.size
+            )));
+        }
+    }
+
+    if members.len() != host.fields.len() {
+        labels.push((struct_range.clone(), format!("{name} has {} members but {} has {} fields", members.len(), host.name, host.fields.len())));
+    } else if *size as usize != host.size {
+        labels.push((struct_range.clone(), format!("{name} is {size} bytes but {} is {} bytes", host.name, host.size)));
+    }
+
+    match labels.first() {
+        Some((_, message)) => {
+            let message = message.clone();
+            let notes = vec![format!("the layout of {name} has to match the #[repr(C)] struct {} it is uploaded from", host.name)];
+            let labels = labels.into_iter().map(|(range, label)| {
+                let (file, range) = source.map(range);
+                (file, range, label)
+            }).collect();
+            Err(ShaderError::new(&source.files, &message, labels, notes))
+        },
+        None => Ok(())
+    }
+}
+
+// Byte range of a struct declaration in `code`. The span naga records can stop short of
+// the comment after the last member, so it is extended up to the closing brace.
+pub fn struct_range(code: &str, span: naga::Span) -> Range<usize> {
+    let start = span.to_range().unwrap_or_default().start;
+    let end = code[start..].find('}').map_or(start, |end| start + end + 1);
+    start..end
+}
+
+// The line declaring the member `name`, without its indentation and line break
+pub fn member_range(code: &str, struct_range: Range<usize>, name: &str) -> Option<Range<usize>> {
+    let mut offset = struct_range.start;
+    for line in code.get(struct_range)?.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+
+        if line.split_once(':').map(|(member, _)| member.trim()) == Some(name) {
+            let indent = line.len() - line.trim_start().len();
+            return Some(start + indent..start + line.trim_end().len());
+        }
+    }
+
+    None
+}
+
+fn error(source: &Preprocessed, range: Range<usize>, message: &str) -> ShaderError {
+    let (file, range) = source.map(range);
+    ShaderError::new(&source.files, message, vec![(file, range, String::new())], Vec::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use gpu_layout::UniformLayout;
+    use crate::{preprocessor::{Preprocessed, SourceFile}, shader};
+    use super::check;
+
+    const SHADER: &str = "struct Params {
+    scale: f32,
+    light_dir: vec3f
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+";
+
+    #[derive(UniformLayout)]
+    struct ParamsRaw {
+        scale: f32,
+        light_dir: [f32; 3]
+    }
+
+    #[derive(UniformLayout)]
+    struct MismatchedRaw {
+        scale: f32,
+        light_dir: [f32; 2]
+    }
+
+    const NESTED_SHADER: &str = "struct Light {
+    intensity: f32
+}
+
+struct Scene {
+    light: Light,
+    @align(16) exposure: f32
+}
+
+@group(0) @binding(0) var<uniform> scene: Scene;
+";
+
+    #[derive(UniformLayout)]
+    struct LightRaw {
+        intensity: f32
+    }
+
+    #[derive(UniformLayout)]
+    struct SceneRaw {
+        light: LightRaw,
+        exposure: f32
+    }
+
+    fn source() -> (Preprocessed, wgpu::naga::Module) {
+        parse(SHADER)
+    }
+
+    fn parse(code: &str) -> (Preprocessed, wgpu::naga::Module) {
+        let source = Preprocessed::concat(vec![SourceFile { path: PathBuf::from("params.wgsl"), source: code.to_string() }]);
+        let module = shader::validate(&source).unwrap_or_else(|error| panic!("{error}"));
+        (source, module)
+    }
+
+    #[test]
+    fn pads_the_fields_like_wgsl() {
+        let (source, module) = source();
+        if let Err(error) = check(&source, &module, "Params", &ParamsRaw::layout()) {
+            panic!("{error}");
+        }
+
+        let bytes = ParamsRaw { scale: 2.0, light_dir: [3.0, 4.0, 5.0] }.to_bytes();
+        let floats: Vec<f32> = bytes.chunks(4).map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap())).collect();
+        assert_eq!(floats, [2.0, 0.0, 0.0, 0.0, 3.0, 4.0, 5.0, 0.0]);
+    }
+
+    #[test]
+    fn names_the_wrong_offset() {
+        let (source, module) = source();
+        let error = check(&source, &module, "Params", &MismatchedRaw::layout()).unwrap_err();
+
+        assert_eq!(error.summary, "params.wgsl:3:5: Params.light_dir is at offset 16 (aligned to 16) but MismatchedRaw.light_dir is at offset 8");
+        assert_eq!(MismatchedRaw { scale: 2.0, light_dir: [3.0, 4.0] }.to_bytes().len(), 16);
+    }
+
+    #[test]
+    fn skips_the_padding_after_a_nested_struct() {
+        let (source, module) = parse(NESTED_SHADER);
+        if let Err(error) = check(&source, &module, "Scene", &SceneRaw::layout()) {
+            panic!("{error}");
+        }
+
+        let bytes = SceneRaw { light: LightRaw { intensity: 2.0 }, exposure: 3.0 }.to_bytes();
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(bytes[16..20], 3.0f32.to_le_bytes());
+    }
+}
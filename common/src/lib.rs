@@ -0,0 +1,13 @@
+// The shader loading, preprocessing, error reporting, parameter reflection and override constants
+// every step shares, along with the window or texture the steps render to, the shader time, the
+// color palettes, a CPU version of the noise library and the golden-image test harness.
+pub mod constants;
+pub mod golden;
+pub mod layout;
+pub mod noise;
+pub mod palette;
+pub mod params;
+pub mod preprocessor;
+pub mod shader;
+pub mod target;
+pub mod time;
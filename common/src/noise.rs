@@ -0,0 +1,792 @@
+use std::ops::{Add, Mul, Sub};
+
+use glam::{IVec2, IVec3, IVec4, Vec2, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
+
+
+// CPU version of the noise library in `shaders/noise.wgsl`, for baking noise on the CPU, checking
+// the baked textures and catching changes to its look. The hash matches the WGSL bit for bit, the float math
+// follows the WGSL builtins step by step so the results only differ by the GPU's rounding.
+// The seedable noises don't depend on the defines and are free functions.
+
+// The defines the WGSL library is compiled with
+#[derive(Clone, Copy, Default)]
+pub struct Noise {
+    // QUINTIC_FADE
+    pub quintic_fade: bool,
+    // ANIMATED_NOISE, with the value of `uniforms.time`
+    pub time: Option<f32>
+}
+
+impl Noise {
+    pub fn domain_warp(&self, pos2: Vec2, octaves: i32) -> f32 {
+        let r = pos2.length();
+        let t = Vec2::new(pos2.x.atan(), pos2.y.atan()).length();
+        let pos = Vec2::new(r, t);
+        let offset = Vec2::new(
+            self.fbm(pos + Vec2::new(15.424, 42.14), octaves),
+            self.fbm(pos + Vec2::new(74.824, 378.54), octaves)
+        );
+
+        self.fbm(pos + offset, octaves)
+    }
+
+    pub fn worley(&self, pos: Vec2) -> f32 {
+        let base = pos.floor();
+        let dist = pos.fract_gl();
+
+        let mut accum = 0.0;
+        let k = -5.0;
+
+        let mut x = -1.0;
+        while x <= 1.0 {
+            let mut y = -1.0;
+            while y <= 1.0 {
+                let offset = Vec2::new(x, y);
+                let cell_p = Vec2::new(
+                    self.perlin(base + offset + Vec2::new(12.515, 166.424)),
+                    self.perlin(base + offset + Vec2::new(82.115, 76.624))
+                ).abs();
+                let curr_dist = cell_p + offset - dist;
+                accum += (curr_dist.length() * k).exp();
+                y += 1.0;
+            }
+            x += 1.0;
+        }
+
+        accum.ln() / k
+    }
+
+    pub fn fbm(&self, pos: Vec2, octaves: i32) -> f32 {
+        let mut accum = 0.0;
+        let mut result = 0.0;
+        let mut freq = 1.0;
+        let mut amp = 1.0;
+
+        for _ in 0..octaves {
+            let noise = self.perlin(pos * freq) * 0.5 + 0.5;
+            result += noise * amp;
+            accum += amp;
+            freq *= 2.0;
+            amp *= 0.5;
+        }
+
+        result / accum
+    }
+
+    // Returns values in range -1 to 1
+    pub fn perlin(&self, pos: Vec2) -> f32 {
+        let base = pos.floor();
+        let dist = pos.fract_gl();
+
+        let d1 = self.random_gradient(base + Vec2::new(0.0, 0.0)).dot(dist - Vec2::new(0.0, 0.0));
+        let d2 = self.random_gradient(base + Vec2::new(1.0, 0.0)).dot(dist - Vec2::new(1.0, 0.0));
+        let d3 = self.random_gradient(base + Vec2::new(0.0, 1.0)).dot(dist - Vec2::new(0.0, 1.0));
+        let d4 = self.random_gradient(base + Vec2::new(1.0, 1.0)).dot(dist - Vec2::new(1.0, 1.0));
+
+        let k = match self.quintic_fade {
+            true => dist * dist * dist * (dist * (dist * 6.0 - 15.0) + 10.0),
+            false => Vec2::new(smoothstep(0.0, 1.0, dist.x), smoothstep(0.0, 1.0, dist.y))
+        };
+
+        let lerp1 = mix(d1, d2, k.x);
+        let lerp2 = mix(d3, d4, k.x);
+
+        mix(lerp1, lerp2, k.y)
+    }
+
+    pub fn random_gradient(&self, posf: Vec2) -> Vec2 {
+        let a = hash_cell(posf.as_ivec2());
+        let scale = std::f32::consts::PI / 2147483648.0;
+        let random = match self.time {
+            Some(time) => 0.5 * time * a as f32 * scale,
+            None => a as f32 * scale
+        };
+
+        Vec2::new(random.cos(), random.sin())
+    }
+}
+
+// `hashCell` of the WGSL library
+pub fn hash_cell(posi: IVec2) -> u32 {
+    let mut a = posi.x as u32;
+    let mut b = posi.y as u32;
+    a = a.wrapping_mul(3284157443);
+    b ^= a.rotate_left(16);
+    b = b.wrapping_mul(1911520717);
+    a ^= b.rotate_left(16);
+    a.wrapping_mul(2048419325)
+}
+
+// `BASIS_*`, the noise the fractal noises sum up
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Basis {
+    #[default]
+    Perlin,
+    Value,
+    Simplex
+}
+
+#[derive(Clone, Copy)]
+pub struct Fractal {
+    pub basis: Basis,
+    pub octaves: i32,
+    pub lacunarity: f32,
+    pub gain: f32,
+    pub seed: u32
+}
+
+pub fn default_fractal(seed: u32) -> Fractal {
+    Fractal {
+        basis: Basis::Perlin,
+        octaves: 5,
+        lacunarity: 2.0,
+        gain: 0.5,
+        seed
+    }
+}
+
+pub fn value_noise2(pos: Vec2, seed: u32) -> f32 {
+    let cell = pos.floor().as_ivec2();
+    let k = quintic_fade(pos.fract_gl());
+
+    let a = hash_to_signed(hash2(cell, seed));
+    let b = hash_to_signed(hash2(cell + IVec2::new(1, 0), seed));
+    let c = hash_to_signed(hash2(cell + IVec2::new(0, 1), seed));
+    let d = hash_to_signed(hash2(cell + IVec2::new(1, 1), seed));
+
+    mix(mix(a, b, k.x), mix(c, d, k.x), k.y)
+}
+
+pub fn value_noise3(pos: Vec3, seed: u32) -> f32 {
+    let cell = pos.floor().as_ivec3();
+    let k = quintic_fade(pos.fract_gl());
+
+    let values = std::array::from_fn(|i| hash_to_signed(hash3(cell + cube_corner(i as i32), seed)));
+
+    interpolate3(values, k)
+}
+
+pub fn perlin_noise2(pos: Vec2, seed: u32) -> f32 {
+    let cell = pos.floor().as_ivec2();
+    let dist = pos.fract_gl();
+    let k = quintic_fade(dist);
+
+    let a = gradient2(hash2(cell, seed), dist);
+    let b = gradient2(hash2(cell + IVec2::new(1, 0), seed), dist - Vec2::new(1.0, 0.0));
+    let c = gradient2(hash2(cell + IVec2::new(0, 1), seed), dist - Vec2::new(0.0, 1.0));
+    let d = gradient2(hash2(cell + IVec2::new(1, 1), seed), dist - Vec2::new(1.0, 1.0));
+
+    0.5 * mix(mix(a, b, k.x), mix(c, d, k.x), k.y)
+}
+
+pub fn perlin_noise3(pos: Vec3, seed: u32) -> f32 {
+    let cell = pos.floor().as_ivec3();
+    let dist = pos.fract_gl();
+
+    let values = std::array::from_fn(|i| {
+        let corner = cube_corner(i as i32);
+        gradient3(hash3(cell + corner, seed), dist - corner.as_vec3())
+    });
+
+    interpolate3(values, quintic_fade(dist))
+}
+
+pub fn perlin_noise4(pos: Vec4, seed: u32) -> f32 {
+    let cell = pos.floor().as_ivec4();
+    let dist = pos.fract_gl();
+    let k = quintic_fade(dist);
+
+    let mut values: [f32; 16] = std::array::from_fn(|i| {
+        let i = i as i32;
+        let corner = IVec4::new(i & 1, (i >> 1) & 1, (i >> 2) & 1, (i >> 3) & 1);
+        gradient4(hash4(cell + corner, seed), dist - corner.as_vec4())
+    });
+    for i in 0..8 {
+        values[i] = mix(values[i * 2], values[i * 2 + 1], k.x);
+    }
+    for i in 0..4 {
+        values[i] = mix(values[i * 2], values[i * 2 + 1], k.y);
+    }
+    for i in 0..2 {
+        values[i] = mix(values[i * 2], values[i * 2 + 1], k.z);
+    }
+
+    mix(values[0], values[1], k.w)
+}
+
+pub fn simplex_noise2(pos: Vec2, seed: u32) -> f32 {
+    let skew = (3.0f32.sqrt() - 1.0) / 2.0;
+    let unskew = (3.0 - 3.0f32.sqrt()) / 6.0;
+
+    let cell = (pos + (pos.x + pos.y) * skew).floor();
+    let d0 = pos - (cell - (cell.x + cell.y) * unskew);
+    let corner = if d0.x > d0.y { Vec2::new(1.0, 0.0) } else { Vec2::new(0.0, 1.0) };
+    let d1 = d0 - corner + unskew;
+    let d2 = d0 - 1.0 + 2.0 * unskew;
+
+    let c = cell.as_ivec2();
+    let n0 = simplex_corner(0.5 - d0.dot(d0), gradient2(hash2(c, seed), d0));
+    let n1 = simplex_corner(0.5 - d1.dot(d1), gradient2(hash2(c + corner.as_ivec2(), seed), d1));
+    let n2 = simplex_corner(0.5 - d2.dot(d2), gradient2(hash2(c + IVec2::new(1, 1), seed), d2));
+
+    40.0 * (n0 + n1 + n2)
+}
+
+pub fn simplex_noise3(pos: Vec3, seed: u32) -> f32 {
+    let skew = 1.0 / 3.0;
+    let unskew = 1.0 / 6.0;
+
+    let cell = (pos + (pos.x + pos.y + pos.z) * skew).floor();
+    let d0 = pos - (cell - (cell.x + cell.y + cell.z) * unskew);
+
+    let g = step(d0.yzx(), d0);
+    let l = 1.0 - g;
+    let corner1 = g.min(l.zxy());
+    let corner2 = g.max(l.zxy());
+    let d1 = d0 - corner1 + unskew;
+    let d2 = d0 - corner2 + 2.0 * unskew;
+    let d3 = d0 - 1.0 + 3.0 * unskew;
+
+    let c = cell.as_ivec3();
+    let n0 = simplex_corner(0.6 - d0.dot(d0), gradient3(hash3(c, seed), d0));
+    let n1 = simplex_corner(0.6 - d1.dot(d1), gradient3(hash3(c + corner1.as_ivec3(), seed), d1));
+    let n2 = simplex_corner(0.6 - d2.dot(d2), gradient3(hash3(c + corner2.as_ivec3(), seed), d2));
+    let n3 = simplex_corner(0.6 - d3.dot(d3), gradient3(hash3(c + IVec3::new(1, 1, 1), seed), d3));
+
+    32.0 * (n0 + n1 + n2 + n3)
+}
+
+pub fn simplex_noise4(pos: Vec4, seed: u32) -> f32 {
+    let skew = (5.0f32.sqrt() - 1.0) / 4.0;
+    let unskew = (5.0 - 5.0f32.sqrt()) / 20.0;
+
+    let cell = (pos + (pos.x + pos.y + pos.z + pos.w) * skew).floor();
+    let d0 = pos - (cell - (cell.x + cell.y + cell.z + cell.w) * unskew);
+
+    let is_x = step(d0.yzw(), d0.xxx());
+    let is_yz = step(d0.zww(), d0.yyz());
+    let mut rank = Vec4::new(is_x.x + is_x.y + is_x.z, 1.0 - is_x.x, 1.0 - is_x.y, 1.0 - is_x.z);
+    rank.y += is_yz.x + is_yz.y;
+    rank.z += 1.0 - is_yz.x;
+    rank.w += 1.0 - is_yz.y;
+    rank.z += is_yz.z;
+    rank.w += 1.0 - is_yz.z;
+
+    let corner3 = rank.clamp(Vec4::ZERO, Vec4::ONE);
+    let corner2 = (rank - 1.0).clamp(Vec4::ZERO, Vec4::ONE);
+    let corner1 = (rank - 2.0).clamp(Vec4::ZERO, Vec4::ONE);
+    let d1 = d0 - corner1 + unskew;
+    let d2 = d0 - corner2 + 2.0 * unskew;
+    let d3 = d0 - corner3 + 3.0 * unskew;
+    let d4 = d0 - 1.0 + 4.0 * unskew;
+
+    let c = cell.as_ivec4();
+    let n0 = simplex_corner(0.6 - d0.dot(d0), gradient4(hash4(c, seed), d0));
+    let n1 = simplex_corner(0.6 - d1.dot(d1), gradient4(hash4(c + corner1.as_ivec4(), seed), d1));
+    let n2 = simplex_corner(0.6 - d2.dot(d2), gradient4(hash4(c + corner2.as_ivec4(), seed), d2));
+    let n3 = simplex_corner(0.6 - d3.dot(d3), gradient4(hash4(c + corner3.as_ivec4(), seed), d3));
+    let n4 = simplex_corner(0.6 - d4.dot(d4), gradient4(hash4(c + IVec4::new(1, 1, 1, 1), seed), d4));
+
+    27.0 * (n0 + n1 + n2 + n3 + n4)
+}
+
+pub fn fractal_fbm2(pos: Vec2, fractal: Fractal) -> f32 {
+    sum_octaves(fractal, |freq, seed| basis_noise2(fractal.basis, pos * freq, seed))
+}
+
+pub fn fractal_fbm3(pos: Vec3, fractal: Fractal) -> f32 {
+    sum_octaves(fractal, |freq, seed| basis_noise3(fractal.basis, pos * freq, seed))
+}
+
+pub fn fractal_ridged2(pos: Vec2, fractal: Fractal) -> f32 {
+    sum_octaves(fractal, |freq, seed| {
+        let ridge = 1.0 - basis_noise2(fractal.basis, pos * freq, seed).abs();
+        ridge * ridge
+    })
+}
+
+pub fn fractal_ridged3(pos: Vec3, fractal: Fractal) -> f32 {
+    sum_octaves(fractal, |freq, seed| {
+        let ridge = 1.0 - basis_noise3(fractal.basis, pos * freq, seed).abs();
+        ridge * ridge
+    })
+}
+
+pub fn fractal_billow2(pos: Vec2, fractal: Fractal) -> f32 {
+    sum_octaves(fractal, |freq, seed| basis_noise2(fractal.basis, pos * freq, seed).abs() * 2.0 - 1.0)
+}
+
+pub fn fractal_billow3(pos: Vec3, fractal: Fractal) -> f32 {
+    sum_octaves(fractal, |freq, seed| basis_noise3(fractal.basis, pos * freq, seed).abs() * 2.0 - 1.0)
+}
+
+// F1, F2 and the distance to the cell border
+pub fn voronoi2(pos: Vec2, seed: u32) -> Vec3 {
+    let cell = pos.floor().as_ivec2();
+    let dist = pos.fract_gl();
+
+    let mut f1 = 8.0f32;
+    let mut f2 = 8.0f32;
+    let mut closest = Vec2::ZERO;
+    let mut closest_cell = IVec2::ZERO;
+    for y in -1..=1 {
+        for x in -1..=1 {
+            let offset = IVec2::new(x, y);
+            let to_point = feature_point2(hash2(cell + offset, seed)) + offset.as_vec2() - dist;
+            let d = to_point.dot(to_point);
+            if d < f1 {
+                f2 = f1;
+                f1 = d;
+                closest = to_point;
+                closest_cell = offset;
+            } else if d < f2 {
+                f2 = d;
+            }
+        }
+    }
+
+    let mut edge = 8.0f32;
+    for y in -2..=2 {
+        for x in -2..=2 {
+            let offset = closest_cell + IVec2::new(x, y);
+            let to_point = feature_point2(hash2(cell + offset, seed)) + offset.as_vec2() - dist;
+            let between = to_point - closest;
+            if between.dot(between) > 0.00001 {
+                edge = edge.min((0.5 * (closest + to_point)).dot(between.normalize()));
+            }
+        }
+    }
+
+    Vec3::new(f1.sqrt(), f2.sqrt(), edge)
+}
+
+pub fn curl_noise2(pos: Vec2, seed: u32) -> Vec2 {
+    let e = 0.01;
+    let dx = simplex_noise2(pos + Vec2::new(e, 0.0), seed) - simplex_noise2(pos - Vec2::new(e, 0.0), seed);
+    let dy = simplex_noise2(pos + Vec2::new(0.0, e), seed) - simplex_noise2(pos - Vec2::new(0.0, e), seed);
+
+    Vec2::new(dy, -dx) / (2.0 * e)
+}
+
+pub fn curl_noise3(pos: Vec3, seed: u32) -> Vec3 {
+    let e = 0.01;
+    let dx = curl_potential3(pos + Vec3::new(e, 0.0, 0.0), seed) - curl_potential3(pos - Vec3::new(e, 0.0, 0.0), seed);
+    let dy = curl_potential3(pos + Vec3::new(0.0, e, 0.0), seed) - curl_potential3(pos - Vec3::new(0.0, e, 0.0), seed);
+    let dz = curl_potential3(pos + Vec3::new(0.0, 0.0, e), seed) - curl_potential3(pos - Vec3::new(0.0, 0.0, e), seed);
+
+    Vec3::new(dy.z - dz.y, dz.x - dx.z, dx.y - dy.x) / (2.0 * e)
+}
+
+fn curl_potential3(pos: Vec3, seed: u32) -> Vec3 {
+    Vec3::new(simplex_noise3(pos, seed), simplex_noise3(pos, seed.wrapping_add(1)), simplex_noise3(pos, seed.wrapping_add(2)))
+}
+
+fn basis_noise2(basis: Basis, pos: Vec2, seed: u32) -> f32 {
+    match basis {
+        Basis::Perlin => perlin_noise2(pos, seed),
+        Basis::Value => value_noise2(pos, seed),
+        Basis::Simplex => simplex_noise2(pos, seed)
+    }
+}
+
+fn basis_noise3(basis: Basis, pos: Vec3, seed: u32) -> f32 {
+    match basis {
+        Basis::Perlin => perlin_noise3(pos, seed),
+        Basis::Value => value_noise3(pos, seed),
+        Basis::Simplex => simplex_noise3(pos, seed)
+    }
+}
+
+// The loop the WGSL fractal noises share, `octave` gets the frequency and the seed
+fn sum_octaves(fractal: Fractal, octave: impl Fn(f32, u32) -> f32) -> f32 {
+    let mut result = 0.0;
+    let mut accum = 0.0;
+    let mut freq = 1.0;
+    let mut amp = 1.0;
+
+    for i in 0..fractal.octaves {
+        result += octave(freq, fractal.seed.wrapping_add(i as u32)) * amp;
+        accum += amp;
+        freq *= fractal.lacunarity;
+        amp *= fractal.gain;
+    }
+
+    result / accum
+}
+
+fn gradient2(hash: u32, dist: Vec2) -> f32 {
+    let h = hash & 7;
+    let (u, v) = match h < 4 {
+        true => (dist.x, dist.y),
+        false => (dist.y, dist.x)
+    };
+
+    signed(u, h & 1 == 0) + signed(2.0 * v, h & 2 == 0)
+}
+
+fn gradient3(hash: u32, dist: Vec3) -> f32 {
+    let h = hash & 15;
+    let u = if h < 8 { dist.x } else { dist.y };
+    let v = match h {
+        0..=3 => dist.y,
+        12 | 14 => dist.x,
+        _ => dist.z
+    };
+
+    signed(u, h & 1 == 0) + signed(v, h & 2 == 0)
+}
+
+fn gradient4(hash: u32, dist: Vec4) -> f32 {
+    let h = hash & 31;
+    let u = if h < 24 { dist.x } else { dist.y };
+    let v = if h < 16 { dist.y } else { dist.z };
+    let w = if h < 8 { dist.z } else { dist.w };
+
+    signed(u, h & 1 == 0) + signed(v, h & 2 == 0) + signed(w, h & 4 == 0)
+}
+
+fn signed(value: f32, positive: bool) -> f32 {
+    match positive {
+        true => value,
+        false => -value
+    }
+}
+
+fn simplex_corner(falloff: f32, gradient: f32) -> f32 {
+    let t = falloff.max(0.0);
+    let t2 = t * t;
+
+    t2 * t2 * gradient
+}
+
+fn feature_point2(hash: u32) -> Vec2 {
+    Vec2::new((hash & 0xffff) as f32, (hash >> 16) as f32) / 65536.0
+}
+
+fn cube_corner(i: i32) -> IVec3 {
+    IVec3::new(i & 1, (i >> 1) & 1, (i >> 2) & 1)
+}
+
+fn interpolate3(mut values: [f32; 8], k: Vec3) -> f32 {
+    for i in 0..4 {
+        values[i] = mix(values[i * 2], values[i * 2 + 1], k.x);
+    }
+    for i in 0..2 {
+        values[i] = mix(values[i * 2], values[i * 2 + 1], k.y);
+    }
+
+    mix(values[0], values[1], k.z)
+}
+
+fn quintic_fade<T>(t: T) -> T where T: Copy + Mul<Output = T> + Mul<f32, Output = T> + Sub<f32, Output = T> + Add<f32, Output = T> {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn hash_to_signed(hash: u32) -> f32 {
+    (hash >> 8) as f32 * (2.0 / 16777216.0) - 1.0
+}
+
+pub fn hash2(cell: IVec2, seed: u32) -> u32 {
+    hash_u32(cell.x as u32 ^ hash_u32(cell.y as u32 ^ hash_u32(seed)))
+}
+
+pub fn hash3(cell: IVec3, seed: u32) -> u32 {
+    hash_u32(cell.x as u32 ^ hash2(cell.yz(), seed))
+}
+
+pub fn hash4(cell: IVec4, seed: u32) -> u32 {
+    hash_u32(cell.x as u32 ^ hash3(cell.yzw(), seed))
+}
+
+// lowbias32 by Chris Wellons
+pub fn hash_u32(value: u32) -> u32 {
+    let mut x = value;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846ca68b);
+    x ^= x >> 16;
+    x
+}
+
+// The WGSL builtins, spelled out like the WGSL spec defines them. glam's `fract_gl` is WGSL's `fract`.
+
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    a * (1.0 - t) + b * t
+}
+
+fn smoothstep(low: f32, high: f32, x: f32) -> f32 {
+    let t = ((x - low) / (high - low)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn step(edge: Vec3, x: Vec3) -> Vec3 {
+    Vec3::select(x.cmpge(edge), Vec3::ONE, Vec3::ZERO)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use glam::{IVec2, IVec3, IVec4, Vec2, Vec3, Vec4};
+    use wgpu::util::DeviceExt;
+    use crate::{preprocessor::preprocess, shader};
+    use super::*;
+
+    // Evaluates `expression`, a `u32` computed from `pos: vec2f` and its `cell: vec2i`, for every point in a compute shader
+    fn evaluate_on_gpu(expression: &str, defines: &[&str], time: f32, points: &[Vec2]) -> Vec<u32> {
+        let path = PathBuf::from("noise_test.wgsl");
+        let code = format!("
+struct UniformParameters {{
+    time: f32
+}}
+
+@group(0) @binding(0) var<uniform> uniforms: UniformParameters;
+@group(0) @binding(1) var<storage, read> points: array<vec2f>;
+@group(0) @binding(2) var<storage, read_write> results: array<u32>;
+
+#include \"noise.wgsl\"
+
+fn pos3(pos: vec2f) -> vec3f {{
+    return vec3f(pos, pos.x * 0.37 - pos.y * 0.21);
+}}
+
+fn pos4(pos: vec2f) -> vec4f {{
+    return vec4f(pos, pos.yx * 0.53);
+}}
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3u) {{
+    if id.x >= arrayLength(&points) {{
+        return;
+    }}
+    let pos = points[id.x];
+    let cell = vec2i(floor(pos));
+    results[id.x] = {expression};
+}}
+");
+        let include_dirs = [Path::new(env!("CARGO_MANIFEST_DIR")).join("../shaders")];
+        let defines: Vec<String> = defines.iter().map(ToString::to_string).collect();
+        let source = preprocess(&path, &include_dirs, &defines, |file| match file == path {
+            true => Ok(code.clone()),
+            false => std::fs::read_to_string(file)
+        }).unwrap_or_else(|error| panic!("{error}"));
+        shader::validate(&source).unwrap_or_else(|error| panic!("{error}"));
+
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            force_fallback_adapter: true,
+            ..Default::default()
+        })).or_else(|| pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))).unwrap();
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None)).unwrap();
+
+        // The layout is spelled out, a derived one would lack `uniforms` when the noise isn't animated
+        let storage = |read_only| wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None },
+                storage(true),
+                storage(false)
+            ].into_iter().enumerate().map(|(binding, ty)| wgpu::BindGroupLayoutEntry {
+                binding: binding as u32,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty,
+                count: None
+            }).collect::<Vec<_>>()
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[&bind_group_layout],
+            ..Default::default()
+        });
+
+        let module = shader::create_shader_module(&device, &source.code);
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            module: &module,
+            entry_point: "main",
+            compilation_options: wgpu::PipelineCompilationOptions::default()
+        });
+
+        let size = (points.len() * 4) as u64;
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[time, 0.0, 0.0, 0.0]),
+            usage: wgpu::BufferUsages::UNIFORM
+        });
+        let points_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&points.iter().map(|pos| pos.to_array()).collect::<Vec<_>>()),
+            usage: wgpu::BufferUsages::STORAGE
+        });
+        let results_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: uniform_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: points_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: results_buffer.as_entire_binding() }
+            ]
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            compute_pass.set_pipeline(&pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups((points.len() as u32).div_ceil(64), 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&results_buffer, 0, &readback_buffer, 0, size);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = readback_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let results = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        results
+    }
+
+    // A grid over positive and negative cells, off the cell corners
+    fn points() -> Vec<Vec2> {
+        (0..32 * 32).map(|i| Vec2::new((i % 32) as f32 * 1.37 - 21.3, (i / 32) as f32 * 1.61 - 25.7)).collect()
+    }
+
+    fn assert_close(name: &str, gpu: &[u32], cpu: impl Fn(Vec2) -> f32, tolerance: f32) {
+        for (pos, gpu) in points().into_iter().zip(gpu) {
+            let (gpu, cpu) = (f32::from_bits(*gpu), cpu(pos));
+            assert!((gpu - cpu).abs() <= tolerance, "{name} at {pos}: {gpu} on the GPU, {cpu} on the CPU");
+        }
+    }
+
+    // The CPU version of a WGSL expression
+    type Reference<I, O> = Box<dyn Fn(I) -> O>;
+
+    // The 3D and 4D noises are sampled on slanted planes through the points, `pos3` and `pos4` in WGSL
+    fn pos3(pos: Vec2) -> Vec3 {
+        Vec3::new(pos.x, pos.y, pos.x * 0.37 - pos.y * 0.21)
+    }
+
+    fn pos4(pos: Vec2) -> Vec4 {
+        Vec4::new(pos.x, pos.y, pos.y * 0.53, pos.x * 0.53)
+    }
+
+    #[test]
+    fn hash_matches_the_gpu() {
+        let gpu = evaluate_on_gpu("hashCell(vec2i(floor(pos)))", &[], 0.0, &points());
+        for (pos, gpu) in points().into_iter().zip(gpu) {
+            assert_eq!(hash_cell(pos.floor().as_ivec2()), gpu, "hash of the cell at {pos}");
+        }
+
+        let seeded: [(&str, Reference<IVec2, u32>); 3] = [
+            ("hash2(cell, 7u)", Box::new(|cell| hash2(cell, 7))),
+            ("hash3(vec3i(cell, cell.x - cell.y), 7u)", Box::new(|cell| hash3(IVec3::new(cell.x, cell.y, cell.x - cell.y), 7))),
+            ("hash4(vec4i(cell, cell.yx), 7u)", Box::new(|cell| hash4(IVec4::new(cell.x, cell.y, cell.y, cell.x), 7)))
+        ];
+        for (expression, cpu) in seeded {
+            let gpu = evaluate_on_gpu(expression, &[], 0.0, &points());
+            for (pos, gpu) in points().into_iter().zip(gpu) {
+                assert_eq!(cpu(pos.floor().as_ivec2()), gpu, "{expression} at {pos}");
+            }
+        }
+    }
+
+    #[test]
+    fn noise_matches_the_gpu() {
+        let noise = Noise::default();
+        let gpu = evaluate_on_gpu("bitcast<u32>(perlinNoise(pos))", &[], 0.0, &points());
+        assert_close("perlinNoise", &gpu, |pos| noise.perlin(pos), 1e-5);
+        let gpu = evaluate_on_gpu("bitcast<u32>(fbm(pos, 5))", &[], 0.0, &points());
+        assert_close("fbm", &gpu, |pos| noise.fbm(pos, 5), 1e-5);
+        let gpu = evaluate_on_gpu("bitcast<u32>(domainWarp(pos, 2))", &[], 0.0, &points());
+        assert_close("domainWarp", &gpu, |pos| noise.domain_warp(pos, 2), 1e-4);
+        let gpu = evaluate_on_gpu("bitcast<u32>(worleyNoise(pos))", &[], 0.0, &points());
+        assert_close("worleyNoise", &gpu, |pos| noise.worley(pos), 1e-4);
+    }
+
+    #[test]
+    fn seeded_noise_matches_the_gpu() {
+        let fractal = Fractal { basis: Basis::Simplex, octaves: 4, lacunarity: 2.3, gain: 0.4, seed: 11 };
+        let value_fractal = Fractal { basis: Basis::Value, ..default_fractal(3) };
+        let cases: [(&str, Reference<Vec2, f32>, f32); 19] = [
+            ("valueNoise2(pos, 7u)", Box::new(|pos| value_noise2(pos, 7)), 1e-5),
+            ("valueNoise3(pos3(pos), 7u)", Box::new(|pos| value_noise3(pos3(pos), 7)), 1e-5),
+            ("perlinNoise2(pos, 7u)", Box::new(|pos| perlin_noise2(pos, 7)), 1e-5),
+            ("perlinNoise3(pos3(pos), 7u)", Box::new(|pos| perlin_noise3(pos3(pos), 7)), 1e-5),
+            ("perlinNoise4(pos4(pos), 7u)", Box::new(|pos| perlin_noise4(pos4(pos), 7)), 1e-5),
+            ("simplexNoise2(pos, 7u)", Box::new(|pos| simplex_noise2(pos, 7)), 1e-4),
+            ("simplexNoise3(pos3(pos), 7u)", Box::new(|pos| simplex_noise3(pos3(pos), 7)), 1e-4),
+            ("simplexNoise4(pos4(pos), 7u)", Box::new(|pos| simplex_noise4(pos4(pos), 7)), 1e-4),
+            ("fractalFbm2(pos, defaultFractal(5u))", Box::new(|pos| fractal_fbm2(pos, default_fractal(5))), 1e-5),
+            ("fractalFbm3(pos3(pos), Fractal(BASIS_VALUE, 5, 2.0, 0.5, 3u))", Box::new(move |pos| fractal_fbm3(pos3(pos), value_fractal)), 1e-5),
+            ("fractalRidged2(pos, Fractal(BASIS_SIMPLEX, 4, 2.3, 0.4, 11u))", Box::new(move |pos| fractal_ridged2(pos, fractal)), 1e-4),
+            ("fractalRidged3(pos3(pos), defaultFractal(5u))", Box::new(|pos| fractal_ridged3(pos3(pos), default_fractal(5))), 1e-5),
+            ("fractalBillow2(pos, Fractal(BASIS_VALUE, 5, 2.0, 0.5, 3u))", Box::new(move |pos| fractal_billow2(pos, value_fractal)), 1e-5),
+            ("fractalBillow3(pos3(pos), Fractal(BASIS_SIMPLEX, 4, 2.3, 0.4, 11u))", Box::new(move |pos| fractal_billow3(pos3(pos), fractal)), 1e-4),
+            ("voronoi2(pos, 7u).x", Box::new(|pos| voronoi2(pos, 7).x), 1e-5),
+            ("voronoi2(pos, 7u).y", Box::new(|pos| voronoi2(pos, 7).y), 1e-5),
+            ("voronoi2(pos, 7u).z", Box::new(|pos| voronoi2(pos, 7).z), 1e-4),
+            ("curlNoise2(pos, 7u).y", Box::new(|pos| curl_noise2(pos, 7).y), 1e-3),
+            ("curlNoise3(pos3(pos), 7u).x", Box::new(|pos| curl_noise3(pos3(pos), 7).x), 1e-3)
+        ];
+
+        for (expression, cpu, tolerance) in cases {
+            let gpu = evaluate_on_gpu(&format!("bitcast<u32>({expression})"), &[], 0.0, &points());
+            assert_close(expression, &gpu, cpu, tolerance);
+        }
+    }
+
+    #[test]
+    fn seeds_change_the_noise() {
+        let differing = points().into_iter().filter(|pos| perlin_noise2(*pos, 1) != perlin_noise2(*pos, 2)).count();
+        assert!(differing > points().len() * 9 / 10, "only {differing} points differ between seeds 1 and 2");
+
+        // Every octave uses its own seed, so octaves don't line up
+        let fractal = default_fractal(1);
+        let shifted = Fractal { seed: 2, ..fractal };
+        assert_ne!(fractal_fbm2(Vec2::new(3.7, -1.2), fractal), fractal_fbm2(Vec2::new(3.7, -1.2), shifted));
+    }
+
+    #[test]
+    fn defines_match_the_gpu() {
+        let noise = Noise { quintic_fade: true, time: Some(1.5) };
+        let gpu = evaluate_on_gpu("bitcast<u32>(perlinNoise(pos))", &["QUINTIC_FADE", "ANIMATED_NOISE"], 1.5, &points());
+        assert_close("animated perlinNoise", &gpu, |pos| noise.perlin(pos), 1e-5);
+    }
+
+    // Changing any of these changes the look of every step
+    #[test]
+    fn reference_values_are_stable() {
+        assert_eq!(hash_cell(IVec2::new(0, 0)), 0);
+        assert_eq!(hash_cell(IVec2::new(1, 0)), 3094586479);
+        assert_eq!(hash_cell(IVec2::new(-3, 7)), 4024871230);
+
+        let noise = Noise::default();
+        let pos = Vec2::new(3.7, -1.2);
+        for (name, value, expected) in [
+            ("perlin", noise.perlin(pos), -0.111766),
+            ("fbm", noise.fbm(pos, 5), 0.529366),
+            ("domain_warp", noise.domain_warp(pos, 2), 0.475569),
+            ("worley", noise.worley(pos), 0.473514)
+        ] {
+            assert!((value - expected).abs() < 1e-5, "{name} at {pos} is {value}, expected {expected}");
+        }
+    }
+}
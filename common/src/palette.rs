@@ -0,0 +1,388 @@
+use std::path::Path;
+
+use winit::keyboard::Key;
+
+
+// Colors for values from 0 to 1
+#[derive(Clone, Debug, PartialEq)]
+pub enum Palette {
+    // `offset + amp * cos(TAU * (freq * t + phase))` per channel, `cosinePalette` in palette.wgsl
+    Cosine {
+        offset: [f32; 3],
+        amp: [f32; 3],
+        freq: [f32; 3],
+        phase: [f32; 3]
+    },
+    // Colors at increasing positions, interpolated linearly and extended past the first and last stop
+    Gradient(Vec<(f32, [f32; 3])>)
+}
+
+impl Palette {
+    pub fn color(&self, t: f32) -> [f32; 3] {
+        match self {
+            Palette::Cosine { offset, amp, freq, phase } => std::array::from_fn(|i| {
+                offset[i] + amp[i] * (std::f32::consts::TAU * (freq[i] * t + phase[i])).cos()
+            }),
+            Palette::Gradient(stops) => match stops.iter().position(|(position, _)| *position > t) {
+                Some(0) => stops[0].1,
+                Some(next) => {
+                    let (start, from) = stops[next - 1];
+                    let (end, to) = stops[next];
+                    let k = (t - start) / (end - start);
+                    std::array::from_fn(|i| from[i] + (to[i] - from[i]) * k)
+                },
+                None => stops[stops.len() - 1].1
+            }
+        }
+    }
+
+    // A gradient file, see `parse_gradient`
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|error| format!("cannot read {}: {error}", path.display()))?;
+        Self::parse_gradient(&text).map_err(|error| format!("{}: {error}", path.display()))
+    }
+
+    // One `POSITION COLOR` stop per line in increasing order, the color as `#rrggbb` or as three
+    // numbers from 0 to 1. Empty lines and lines starting with `//` are skipped.
+    pub fn parse_gradient(text: &str) -> Result<Self, String> {
+        let mut stops: Vec<(f32, [f32; 3])> = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+
+            let stop = parse_stop(line).map_err(|message| format!("line {}: {message}", number + 1))?;
+            if stops.last().is_some_and(|(position, _)| stop.0 < *position) {
+                return Err(format!("line {}: the stops have to be in increasing order", number + 1));
+            }
+            stops.push(stop);
+        }
+
+        match stops.is_empty() {
+            true => Err("the gradient has no stops".to_string()),
+            false => Ok(Palette::Gradient(stops))
+        }
+    }
+}
+
+// The built in palettes, `classic` is the one the steps started out with
+pub fn presets() -> Vec<(String, Palette)> {
+    let cosine = |offset, amp, freq, phase| Palette::Cosine { offset, amp, freq, phase };
+
+    [
+        ("classic", cosine([0.5; 3], [0.5; 3], [0.8, 0.8, 0.5], [0.0, 0.2, 0.5])),
+        ("rainbow", cosine([0.5; 3], [0.5; 3], [1.0; 3], [0.0, 0.33, 0.67])),
+        ("sunset", cosine([0.5; 3], [0.5; 3], [1.0; 3], [0.0, 0.1, 0.2])),
+        ("forest", cosine([0.5; 3], [0.5; 3], [1.0; 3], [0.3, 0.2, 0.2])),
+        ("candy", cosine([0.5; 3], [0.5; 3], [1.0, 1.0, 0.5], [0.8, 0.9, 0.3])),
+        ("ice", cosine([0.5; 3], [0.5; 3], [1.0, 0.7, 0.4], [0.0, 0.15, 0.2])),
+        ("fire", cosine([0.8, 0.5, 0.4], [0.2, 0.4, 0.2], [2.0, 1.0, 1.0], [0.0, 0.25, 0.25])),
+        ("water", Palette::Gradient(vec![(0.0, [0.0, 0.16863, 1.0]), (1.0, [0.19216, 0.75294, 1.0])])),
+        ("lava", Palette::Gradient(vec![(0.0, [0.0; 3]), (0.45, [0.55, 0.0, 0.0]), (0.75, [1.0, 0.27, 0.0]), (1.0, [1.0, 0.84, 0.0])])),
+        ("grayscale", Palette::Gradient(vec![(0.0, [0.0; 3]), (1.0, [1.0; 3])]))
+    ].into_iter().map(|(name, palette)| (name.to_string(), palette)).collect()
+}
+
+// The palette `palette()` in palette.wgsl reads, as a lookup table texture. P and Shift+P switch
+// to the next and previous palette, fading over a second of shader time.
+pub struct Palettes {
+    palettes: Vec<(String, Palette)>,
+    selected: usize,
+    // The table shown when the switch happened, and the shader time it happened at
+    fade: Option<(Vec<[f32; 3]>, f32)>,
+    // Shader time of the last update
+    time: f32,
+    // Seconds of shader time each palette is shown for before fading to the next one
+    cycle: Option<f32>,
+    shown: Vec<[f32; 3]>,
+    texture: wgpu::Texture,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup
+}
+
+impl Palettes {
+    // `PALETTE_LUT_SIZE` in palette.wgsl
+    const LUT_SIZE: usize = 256;
+    const FADE_SECONDS: f32 = 1.0;
+
+    pub fn new(device: &wgpu::Device, default: &str) -> Self {
+        let palettes = presets();
+        let selected = palettes.iter().position(|(name, _)| name == default).expect("the default palette is a preset");
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: Self::LUT_SIZE as u32,
+                height: 1,
+                depth_or_array_layers: 1
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[]
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::all(),
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false
+                    },
+                    count: None
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::all(),
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None
+                }
+            ]
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view)
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler)
+                }
+            ]
+        });
+
+        Palettes {
+            palettes,
+            selected,
+            fade: None,
+            time: 0.0,
+            cycle: None,
+            shown: Vec::new(),
+            texture,
+            bind_group_layout,
+            bind_group
+        }
+    }
+
+    // Selects a preset by name, or imports a gradient file and selects it
+    pub fn select(&mut self, name_or_path: &str) -> Result<(), String> {
+        let path = Path::new(name_or_path);
+        let index = match self.palettes.iter().position(|(name, _)| name == name_or_path) {
+            Some(index) => index,
+            None if path.is_file() => {
+                let name = path.file_stem().map_or(name_or_path.into(), |stem| stem.to_string_lossy()).to_string();
+                self.palettes.push((name, Palette::load(path)?));
+                self.palettes.len() - 1
+            },
+            None => {
+                let names: Vec<&str> = self.palettes.iter().map(|(name, _)| name.as_str()).collect();
+                return Err(format!("unknown palette {name_or_path}, expected a gradient file or one of {}", names.join(", ")));
+            }
+        };
+
+        self.selected = index;
+        self.fade = None;
+        Ok(())
+    }
+
+    pub fn set_cycle(&mut self, seconds: Option<f32>) {
+        self.cycle = seconds;
+    }
+
+    pub fn handle_key(&mut self, key: &Key, backwards: bool) -> bool {
+        match key {
+            Key::Character(c) if c.eq_ignore_ascii_case("p") => {
+                let count = self.palettes.len();
+                self.selected = match backwards {
+                    true => (self.selected + count - 1) % count,
+                    false => (self.selected + 1) % count
+                };
+                self.fade = Some((self.shown.clone(), self.time));
+                println!("palette = {}", self.palettes[self.selected].0);
+                true
+            },
+            _ => false
+        }
+    }
+
+    // `time` is the shader time, which drives the cycling and the fades
+    pub fn update(&mut self, queue: &wgpu::Queue, time: f32) {
+        let table = self.table(time);
+        if table == self.shown {
+            return;
+        }
+
+        let texels: Vec<u8> = table.iter().flat_map(|color| {
+            let [r, g, b] = color.map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8);
+            [r, g, b, 255]
+        }).collect();
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All
+            },
+            &texels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * Self::LUT_SIZE as u32),
+                rows_per_image: None
+            },
+            self.texture.size()
+        );
+        self.shown = table;
+    }
+
+    // The table to show at the shader time `time`
+    fn table(&mut self, time: f32) -> Vec<[f32; 3]> {
+        self.time = time;
+        match (self.cycle, &self.fade) {
+            (Some(seconds), _) => {
+                let count = self.palettes.len();
+                let shown = (time / seconds).floor().max(0.0) as usize;
+                let since_switch = time - shown as f32 * seconds;
+                let from = lookup_table(&self.palettes[(self.selected + shown + count - 1) % count].1);
+                let to = lookup_table(&self.palettes[(self.selected + shown) % count].1);
+                match shown {
+                    0 => to,
+                    _ => blend(&from, &to, since_switch / Self::FADE_SECONDS)
+                }
+            },
+            (None, Some((from, start))) => {
+                // Going back before the switch ends the fade, like pausing holds it
+                let k = match time < *start {
+                    true => 1.0,
+                    false => (time - start) / Self::FADE_SECONDS
+                };
+                let table = blend(from, &lookup_table(&self.palettes[self.selected].1), k);
+                if k >= 1.0 {
+                    self.fade = None;
+                }
+                table
+            },
+            (None, None) => lookup_table(&self.palettes[self.selected].1)
+        }
+    }
+}
+
+// The palette sampled at the texel centers, the first and last texel hold the colors at 0 and 1
+pub fn lookup_table(palette: &Palette) -> Vec<[f32; 3]> {
+    (0..Palettes::LUT_SIZE).map(|i| palette.color(i as f32 / (Palettes::LUT_SIZE - 1) as f32)).collect()
+}
+
+// Eases from `from` to `to` as `k` goes from 0 to 1
+fn blend(from: &[[f32; 3]], to: &[[f32; 3]], k: f32) -> Vec<[f32; 3]> {
+    let k = k.clamp(0.0, 1.0);
+    let k = k * k * (3.0 - 2.0 * k);
+    if from.len() != to.len() {
+        return to.to_vec();
+    }
+
+    from.iter().zip(to).map(|(from, to)| std::array::from_fn(|i| from[i] + (to[i] - from[i]) * k)).collect()
+}
+
+fn parse_stop(line: &str) -> Result<(f32, [f32; 3]), String> {
+    let parse = |value: &str| value.parse::<f32>().map_err(|_| format!("invalid number {value}"));
+    let parts: Vec<&str> = line.split_whitespace().collect();
+
+    let color = match parts[1..] {
+        [hex] => parse_hex(hex)?,
+        [r, g, b] => [parse(r)?, parse(g)?, parse(b)?],
+        _ => return Err("expected a position and a color, either #rrggbb or three numbers".to_string())
+    };
+
+    Ok((parse(parts[0])?, color))
+}
+
+fn parse_hex(hex: &str) -> Result<[f32; 3], String> {
+    let digits = hex.strip_prefix('#')
+        .filter(|digits| digits.len() == 6 && digits.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or(format!("invalid color {hex}, expected #rrggbb"))?;
+
+    Ok(std::array::from_fn(|i| u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).unwrap() as f32 / 255.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::keyboard::Key;
+    use super::{lookup_table, presets, Palette, Palettes};
+
+    #[test]
+    fn parses_gradient_stops() {
+        let palette = Palette::parse_gradient("// sky\n0.0 #002bff\n\n0.5 0.5 0.25 1.0\n1.0 #ffffff\n").unwrap();
+        assert_eq!(palette, Palette::Gradient(vec![
+            (0.0, [0.0, 43.0 / 255.0, 1.0]),
+            (0.5, [0.5, 0.25, 1.0]),
+            (1.0, [1.0, 1.0, 1.0])
+        ]));
+
+        assert_eq!(palette.color(-1.0), [0.0, 43.0 / 255.0, 1.0]);
+        assert_eq!(palette.color(0.75), [0.75, 0.625, 1.0]);
+        assert_eq!(palette.color(2.0), [1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn names_the_broken_line() {
+        let error = |text| Palette::parse_gradient(text).unwrap_err();
+        assert_eq!(error("0.0 #002bff\n0.5 #00zz00"), "line 2: invalid color #00zz00, expected #rrggbb");
+        assert_eq!(error("0.5 #000000\n0.2 #ffffff"), "line 2: the stops have to be in increasing order");
+        assert_eq!(error("0.5 1.0 0.5"), "line 1: expected a position and a color, either #rrggbb or three numbers");
+        assert_eq!(error("// empty\n"), "the gradient has no stops");
+    }
+
+    // `classic` is the palette step 04 hard-coded before palettes could be switched
+    #[test]
+    fn classic_matches_the_old_palette() {
+        let (_, classic) = presets().into_iter().find(|(name, _)| name == "classic").unwrap();
+        let table = lookup_table(&classic);
+        assert_eq!(table.len(), 256);
+        for (t, expected) in [(0.0, [1.0, 0.6545085, 0.0]), (1.0, [0.6545085, 1.0, 1.0])] {
+            let color = classic.color(t);
+            assert!(color.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-5), "{color:?} at {t}, expected {expected:?}");
+        }
+        assert_eq!(table[0], classic.color(0.0));
+        assert_eq!(table[255], classic.color(1.0));
+    }
+
+    #[test]
+    fn fades_over_the_shader_time() {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            force_fallback_adapter: true,
+            ..Default::default()
+        })).or_else(|| pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))).unwrap();
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None)).unwrap();
+
+        let table = |name: &str| lookup_table(&presets().into_iter().find(|(preset, _)| preset == name).unwrap().1);
+        let shows = |palettes: &Palettes, name| palettes.shown.iter().zip(table(name)).all(|(a, b)| (0..3).all(|i| (a[i] - b[i]).abs() < 1e-5));
+        let mut palettes = Palettes::new(&device, "classic");
+        palettes.update(&queue, 5.0);
+        palettes.handle_key(&Key::Character("p".into()), false);
+
+        // Paused, the fade holds however long it takes
+        palettes.update(&queue, 5.0);
+        assert!(shows(&palettes, "classic"));
+        palettes.update(&queue, 5.5);
+        assert!(!shows(&palettes, "classic") && !shows(&palettes, "rainbow"));
+        palettes.update(&queue, 6.0);
+        assert!(shows(&palettes, "rainbow"));
+        assert!(palettes.fade.is_none());
+    }
+}
@@ -0,0 +1,430 @@
+use std::{collections::HashMap, ops::Range, path::PathBuf};
+
+use wgpu::naga;
+use winit::keyboard::{Key, NamedKey};
+use crate::{layout::{member_range, struct_range}, preprocessor::Preprocessed, shader::ShaderError};
+
+
+// Values of a `Params` uniform struct declared by the shader. Each field can carry its default
+// and range in a trailing comment, `scale: f32, // 9.0, 1.0..40.0`, vectors list every component.
+// Tab and Shift+Tab select a value, Up and Down change it (ten times faster with Shift)
+// and Backspace resets it to the default. Changed values are saved to `path`.
+pub struct Params {
+    path: Option<PathBuf>,
+    saved: HashMap<String, Vec<f32>>,
+    layout: ParamsLayout,
+    selected: usize,
+    dirty: bool,
+    buffer: wgpu::Buffer,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup
+}
+
+// The reflected `Params` struct, empty when the shader declares none
+#[derive(Default)]
+pub struct ParamsLayout {
+    size: u64,
+    fields: Vec<Field>
+}
+
+struct Field {
+    name: String,
+    offset: u64,
+    kind: ScalarKind,
+    default: Vec<f32>,
+    value: Vec<f32>,
+    range: Option<(f32, f32)>
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ScalarKind {
+    Float,
+    Sint,
+    Uint
+}
+
+impl Params {
+    const MIN_SIZE: u64 = 16;
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::all(),
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None
+                    },
+                    count: None
+                }
+            ]
+        });
+        let (buffer, bind_group) = create_buffer(device, &bind_group_layout, Self::MIN_SIZE);
+
+        Params {
+            path: None,
+            saved: HashMap::new(),
+            layout: ParamsLayout::default(),
+            selected: 0,
+            dirty: true,
+            buffer,
+            bind_group_layout,
+            bind_group
+        }
+    }
+
+    // Loads the values saved in `path`, they take precedence over the defaults from the shader
+    pub fn set_file(&mut self, path: PathBuf) {
+        self.saved = match std::fs::read_to_string(&path) {
+            Ok(contents) => parse_saved(&contents),
+            Err(_) => HashMap::new()
+        };
+        self.path = Some(path);
+
+        for field in &mut self.layout.fields {
+            field.value = saved_value(&self.saved, field);
+        }
+        self.dirty = true;
+    }
+
+    pub fn set_layout(&mut self, device: &wgpu::Device, mut layout: ParamsLayout) {
+        for field in &mut layout.fields {
+            field.value = saved_value(&self.saved, field);
+        }
+
+        let size = layout.size.max(Self::MIN_SIZE);
+        if size != self.layout.size.max(Self::MIN_SIZE) {
+            (self.buffer, self.bind_group) = create_buffer(device, &self.bind_group_layout, size);
+        }
+
+        self.layout = layout;
+        self.selected = self.selected.min(self.len().saturating_sub(1));
+        self.dirty = true;
+    }
+
+    pub fn handle_key(&mut self, key: &Key, fast: bool) -> bool {
+        if self.len() == 0 {
+            return false;
+        }
+
+        match key {
+            Key::Named(NamedKey::Tab) => {
+                self.selected = match fast {
+                    true => (self.selected + self.len() - 1) % self.len(),
+                    false => (self.selected + 1) % self.len()
+                };
+            },
+            Key::Named(NamedKey::ArrowUp) => self.adjust(if fast { 10.0 } else { 1.0 }),
+            Key::Named(NamedKey::ArrowDown) => self.adjust(if fast { -10.0 } else { -1.0 }),
+            Key::Named(NamedKey::Backspace) => {
+                let (field, component) = self.selected_component();
+                let field = &mut self.layout.fields[field];
+                field.value[component] = field.default[component];
+                self.save();
+            },
+            _ => return false
+        }
+
+        self.print_selected();
+        true
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        let mut bytes = vec![0u8; self.layout.size.max(Self::MIN_SIZE) as usize];
+        for field in &self.layout.fields {
+            for (i, value) in field.value.iter().enumerate() {
+                let offset = field.offset as usize + i * 4;
+                let value = match field.kind {
+                    ScalarKind::Float => value.to_le_bytes(),
+                    ScalarKind::Sint => (value.round() as i32).to_le_bytes(),
+                    ScalarKind::Uint => (value.round().max(0.0) as u32).to_le_bytes()
+                };
+                bytes[offset..offset + 4].copy_from_slice(&value);
+            }
+        }
+        queue.write_buffer(&self.buffer, 0, &bytes);
+    }
+
+    fn len(&self) -> usize {
+        self.layout.fields.iter().map(|field| field.value.len()).sum()
+    }
+
+    fn selected_component(&self) -> (usize, usize) {
+        let mut index = self.selected;
+        for (i, field) in self.layout.fields.iter().enumerate() {
+            if index < field.value.len() {
+                return (i, index);
+            }
+            index -= field.value.len();
+        }
+        unreachable!("the selection is kept within the fields")
+    }
+
+    fn adjust(&mut self, steps: f32) {
+        let (field, component) = self.selected_component();
+        let field = &mut self.layout.fields[field];
+
+        let step = match (field.kind, field.range) {
+            (ScalarKind::Sint | ScalarKind::Uint, _) => 1.0,
+            (ScalarKind::Float, Some((min, max))) => (max - min) / 100.0,
+            (ScalarKind::Float, None) => field.default[component].abs().max(1.0) / 100.0
+        };
+        let mut value = field.value[component] + step * steps;
+        if let Some((min, max)) = field.range {
+            value = value.clamp(min, max);
+        }
+        field.value[component] = value;
+
+        self.save();
+    }
+
+    fn print_selected(&self) {
+        let (field, component) = self.selected_component();
+        let field = &self.layout.fields[field];
+        let name = match field.value.len() {
+            1 => field.name.clone(),
+            _ => format!("{}.{}", field.name, ['x', 'y', 'z', 'w'][component])
+        };
+        println!("{name} = {}", field.value[component]);
+    }
+
+    fn save(&mut self) {
+        self.dirty = true;
+        for field in &self.layout.fields {
+            self.saved.insert(field.name.clone(), field.value.clone());
+        }
+
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Err(error) = std::fs::write(path, format_saved(&self.saved)) {
+            eprintln!("Failed to save the params to {}: {error}", path.display());
+        }
+    }
+}
+
+impl ParamsLayout {
+    // Finds the uniform variable of type `Params`, which has to be bound at `@group(group) @binding(0)`
+    pub fn reflect(source: &Preprocessed, module: &naga::Module, group: u32) -> Result<Self, ShaderError> {
+        let Some((handle, variable)) = module.global_variables.iter().find(|(_, variable)| {
+            variable.space == naga::AddressSpace::Uniform && module.types[variable.ty].name.as_deref() == Some("Params")
+        }) else {
+            return Ok(ParamsLayout::default());
+        };
+
+        let error = |span: naga::Span, message: &str| {
+            let labels = span.to_range().map(|range| source.map(range)).map(|(file, range)| (file, range, String::new()));
+            ShaderError::new(&source.files, message, labels.into_iter().collect(), Vec::new())
+        };
+
+        let variable_span = module.global_variables.get_span(handle);
+        if variable.binding != Some(naga::ResourceBinding { group, binding: 0 }) {
+            return Err(error(variable_span, &format!("Params has to be bound at @group({group}) @binding(0)")));
+        }
+        let naga::TypeInner::Struct { members, span: size } = &module.types[variable.ty].inner else {
+            return Err(error(variable_span, "Params has to be a struct"));
+        };
+
+        let struct_range = struct_range(&source.code, module.types.get_span(variable.ty));
+        let mut fields = Vec::new();
+        for member in members {
+            let name = member.name.clone().unwrap_or_default();
+            let annotation = find_annotation(&source.code, struct_range.clone(), &name);
+            let annotation_span = annotation.as_ref().map_or(variable_span, |(_, range)| naga::Span::from(range.clone()));
+
+            let (scalar, components) = match module.types[member.ty].inner {
+                naga::TypeInner::Scalar(scalar) => (scalar, 1),
+                naga::TypeInner::Vector { size, scalar } => (scalar, size as usize),
+                _ => return Err(error(annotation_span, &format!("param {name} has to be a scalar or a vector")))
+            };
+            let kind = match (scalar.kind, scalar.width) {
+                (naga::ScalarKind::Float, 4) => ScalarKind::Float,
+                (naga::ScalarKind::Sint, 4) => ScalarKind::Sint,
+                (naga::ScalarKind::Uint, 4) => ScalarKind::Uint,
+                _ => return Err(error(annotation_span, &format!("param {name} has to be made of f32, i32 or u32")))
+            };
+
+            let (default, range) = match &annotation {
+                Some((text, _)) => parse_annotation(text, components)
+                    .map_err(|message| error(annotation_span, &format!("param {name}: {message}")))?,
+                None => (vec![0.0; components], None)
+            };
+
+            fields.push(Field {
+                name,
+                offset: member.offset as u64,
+                kind,
+                value: default.clone(),
+                default,
+                range
+            });
+        }
+
+        Ok(ParamsLayout {
+            size: *size as u64,
+            fields
+        })
+    }
+}
+
+fn create_buffer(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, size: u64) -> (wgpu::Buffer, wgpu::BindGroup) {
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false
+    });
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding()
+            }
+        ]
+    });
+
+    (buffer, bind_group)
+}
+
+fn saved_value(saved: &HashMap<String, Vec<f32>>, field: &Field) -> Vec<f32> {
+    match saved.get(&field.name) {
+        Some(value) if value.len() == field.default.len() => value.clone(),
+        _ => field.default.clone()
+    }
+}
+
+// One `name = value value ...` per line, sorted by name
+fn format_saved(saved: &HashMap<String, Vec<f32>>) -> String {
+    let mut names: Vec<&String> = saved.keys().collect();
+    names.sort();
+    names.into_iter().map(|name| {
+        let values: Vec<String> = saved[name].iter().map(f32::to_string).collect();
+        format!("{name} = {}\n", values.join(" "))
+    }).collect()
+}
+
+fn parse_saved(contents: &str) -> HashMap<String, Vec<f32>> {
+    contents.lines().filter_map(|line| {
+        let (name, values) = line.split_once('=')?;
+        let values = values.split_whitespace().map(str::parse).collect::<Result<Vec<f32>, _>>().ok()?;
+        Some((name.trim().to_string(), values))
+    }).collect()
+}
+
+// The trailing comment of the member's line and its byte range in `code`
+fn find_annotation(code: &str, struct_range: Range<usize>, name: &str) -> Option<(String, Range<usize>)> {
+    let line = member_range(code, struct_range, name)?;
+    let start = line.start + code[line.clone()].find("//")? + 2;
+    Some((code[start..line.end].trim().to_string(), start..line.end))
+}
+
+// The default value of every component and the optional range
+type Annotation = (Vec<f32>, Option<(f32, f32)>);
+
+// `DEFAULT[, MIN..MAX]`, where a single default value is used for every component
+fn parse_annotation(text: &str, components: usize) -> Result<Annotation, String> {
+    let (default, range) = match text.split_once(',') {
+        Some((default, range)) => (default, Some(range)),
+        None => (text, None)
+    };
+
+    let parse = |value: &str| value.trim().parse::<f32>().map_err(|_| format!("invalid number {}", value.trim()));
+    let mut default = default.split_whitespace().map(parse).collect::<Result<Vec<f32>, _>>()?;
+    if default.len() == 1 {
+        default = vec![default[0]; components];
+    }
+    if default.len() != components {
+        return Err(format!("expected {components} default values, found {}", default.len()));
+    }
+
+    let range = match range {
+        Some(range) => {
+            let (min, max) = range.split_once("..").ok_or(format!("invalid range {}, expected MIN..MAX", range.trim()))?;
+            Some((parse(min)?, parse(max)?))
+        },
+        None => None
+    };
+
+    Ok((default, range))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::PathBuf};
+
+    use crate::{preprocessor::{Preprocessed, SourceFile}, shader::{self, ShaderError}};
+    use super::{format_saved, parse_annotation, parse_saved, saved_value, ParamsLayout, ScalarKind};
+
+    fn reflect(members: &str) -> Result<ParamsLayout, ShaderError> {
+        let code = format!("struct Params {{\n{members}}}\n\n@group(1) @binding(0) var<uniform> params: Params;\n");
+        let source = Preprocessed::concat(vec![SourceFile { path: PathBuf::from("params.wgsl"), source: code }]);
+        let module = shader::validate(&source).unwrap_or_else(|error| panic!("{error}"));
+        ParamsLayout::reflect(&source, &module, 1)
+    }
+
+    #[test]
+    fn reflects_defaults_and_ranges() {
+        let layout = reflect("    scale: f32, // 9.0, 1.0..40.0\n    color: vec3f, // 1.0 0.5 0.25\n    offset: vec2f, // -2\n    steps: i32,\n    seed: u32 // 7\n")
+            .unwrap_or_else(|error| panic!("{error}"));
+
+        let fields: Vec<_> = layout.fields.iter().map(|field| (field.name.as_str(), field.offset, field.default.clone(), field.range)).collect();
+        assert_eq!(fields, [
+            ("scale", 0, vec![9.0], Some((1.0, 40.0))),
+            ("color", 16, vec![1.0, 0.5, 0.25], None),
+            ("offset", 32, vec![-2.0, -2.0], None),
+            ("steps", 40, vec![0.0], None),
+            ("seed", 44, vec![7.0], None)
+        ]);
+        let kinds: Vec<ScalarKind> = layout.fields.iter().map(|field| field.kind).collect();
+        assert!(kinds == [ScalarKind::Float, ScalarKind::Float, ScalarKind::Float, ScalarKind::Sint, ScalarKind::Uint]);
+        assert_eq!(layout.size, 48);
+    }
+
+    #[test]
+    fn rejects_malformed_annotations() {
+        let error = |members: &str| reflect(members).err().map(|error| error.summary);
+
+        assert_eq!(error("    scale: f32, // fast\n"), Some("params.wgsl:2:19: param scale: invalid number fast".to_string()));
+        assert_eq!(error("    scale: f32, // 1.0, 2.0\n"), Some("params.wgsl:2:19: param scale: invalid range 2.0, expected MIN..MAX".to_string()));
+        assert_eq!(error("    color: vec3f, // 1 2\n"), Some("params.wgsl:2:21: param color: expected 3 default values, found 2".to_string()));
+        assert_eq!(parse_annotation("1.0, 0.0..x", 1), Err("invalid number x".to_string()));
+        assert_eq!(parse_annotation(" 0.5 ,-1..1 ", 2), Ok((vec![0.5, 0.5], Some((-1.0, 1.0)))));
+    }
+
+    #[test]
+    fn rejects_members_that_are_not_scalars_or_vectors() {
+        let error = |members: &str| reflect(members).err().map(|error| error.summary);
+
+        assert_eq!(error("    scale: f32,\n    rotation: mat2x2f, // 1\n"), Some("params.wgsl:3:26: param rotation has to be a scalar or a vector".to_string()));
+        assert_eq!(error("    flags: array<vec4u, 2>\n"), Some("params.wgsl:5:23: param flags has to be a scalar or a vector".to_string()));
+    }
+
+    #[test]
+    fn saves_and_loads_the_values() {
+        let saved = HashMap::from([
+            ("scale".to_string(), vec![9.5]),
+            ("color".to_string(), vec![1.0, 0.1, 1.0 / 3.0])
+        ]);
+
+        let contents = format_saved(&saved);
+        assert!(contents.starts_with("color = 1 0.1 0.33333334\nscale = 9.5\n"), "{contents}");
+        assert_eq!(parse_saved(&contents), saved);
+        assert_eq!(parse_saved("scale = 2\nbroken = x\n\ncolor 1 2 3\n"), HashMap::from([("scale".to_string(), vec![2.0])]));
+
+        // Values saved for a field of another size fall back to the default
+        let layout = reflect("    scale: f32,\n    color: vec4f // 0.5\n").unwrap_or_else(|error| panic!("{error}"));
+        assert_eq!(saved_value(&saved, &layout.fields[0]), [9.5]);
+        assert_eq!(saved_value(&saved, &layout.fields[1]), [0.5; 4]);
+    }
+}
@@ -0,0 +1,291 @@
+use std::{collections::HashSet, io, ops::Range, path::{Component, Path, PathBuf}};
+
+use crate::shader::ShaderError;
+
+
+// Supported directives, each on its own line:
+//   #include "file.wgsl" / #import "file.wgsl"  pastes the file, at most once per shader
+//   #define NAME / #undef NAME
+//   #ifdef NAME / #ifndef NAME, #else, #endif
+// Includes are looked up next to the including file first, then in the include directories.
+
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub source: String
+}
+
+pub struct Preprocessed {
+    pub code: String,
+    // The root shader comes first, followed by its includes
+    pub files: Vec<SourceFile>,
+    // Start of every line of `code`, with the file and the offset it was copied from
+    lines: Vec<(usize, usize, usize)>
+}
+
+impl Preprocessed {
+    pub fn path(&self) -> &Path {
+        &self.files[0].path
+    }
+
+    // Joins the files verbatim, without looking at any directives
+    pub fn concat(files: Vec<SourceFile>) -> Self {
+        let mut preprocessed = Preprocessed {
+            code: String::new(),
+            files,
+            lines: Vec::new()
+        };
+
+        for (file, source_file) in preprocessed.files.iter().enumerate() {
+            let mut offset = 0;
+            for line in source_file.source.split_inclusive('\n') {
+                preprocessed.lines.push((preprocessed.code.len(), file, offset));
+                preprocessed.code.push_str(line.trim_end_matches(['\n', '\r']));
+                preprocessed.code.push('\n');
+                offset += line.len();
+            }
+        }
+
+        preprocessed
+    }
+
+    // Maps a byte range of `code` back to the file it was copied from
+    pub fn map(&self, range: Range<usize>) -> (usize, Range<usize>) {
+        let (file, start) = self.map_offset(range.start);
+        if range.is_empty() {
+            return (file, start..start);
+        }
+
+        match self.map_offset(range.end - 1) {
+            (end_file, last) if end_file == file && last >= start => (file, start..last + 1),
+            _ => (file, start..start)
+        }
+    }
+
+    fn map_offset(&self, offset: usize) -> (usize, usize) {
+        let line = self.lines.partition_point(|&(start, ..)| start <= offset).saturating_sub(1);
+        match self.lines.get(line) {
+            Some(&(start, file, origin)) => (file, (origin + offset - start).min(self.files[file].source.len())),
+            None => (0, 0)
+        }
+    }
+}
+
+pub fn preprocess(path: &Path, include_dirs: &[PathBuf], defines: &[String], load: impl FnMut(&Path) -> io::Result<String>) -> Result<Preprocessed, ShaderError> {
+    let mut preprocessor = Preprocessor {
+        include_dirs,
+        defines: defines.iter().cloned().collect(),
+        load,
+        included: HashSet::new(),
+        output: Preprocessed {
+            code: String::new(),
+            files: Vec::new(),
+            lines: Vec::new()
+        }
+    };
+
+    let path = normalize(path);
+    let source = (preprocessor.load)(&path).map_err(|error| ShaderError::unreadable(&path, &error))?;
+    preprocessor.process(path, source)?;
+
+    Ok(preprocessor.output)
+}
+
+// Resolves `.` and `..` without touching the file system, so files embedded
+// into the binary are found by the same paths as the ones on disk
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                },
+                Some(Component::RootDir | Component::Prefix(_)) => {},
+                _ => normalized.push("..")
+            },
+            _ => normalized.push(component)
+        }
+    }
+
+    normalized
+}
+
+struct Preprocessor<'a, F> {
+    include_dirs: &'a [PathBuf],
+    defines: HashSet<String>,
+    load: F,
+    included: HashSet<PathBuf>,
+    output: Preprocessed
+}
+
+struct Condition {
+    active: bool,
+    has_else: bool,
+    directive: Range<usize>
+}
+
+impl<F: FnMut(&Path) -> io::Result<String>> Preprocessor<'_, F> {
+    fn process(&mut self, path: PathBuf, source: String) -> Result<(), ShaderError> {
+        let file = self.output.files.len();
+        self.included.insert(path.clone());
+        self.output.files.push(SourceFile { path, source: source.clone() });
+
+        let mut conditions: Vec<Condition> = Vec::new();
+        let mut offset = 0;
+        for line in source.split_inclusive('\n') {
+            let start = offset;
+            offset += line.len();
+
+            let text = line.trim_end_matches(['\n', '\r']);
+            let active = conditions.iter().all(|condition| condition.active);
+            let Some(directive) = text.trim_start().strip_prefix('#') else {
+                if active {
+                    self.output.lines.push((self.output.code.len(), file, start));
+                    self.output.code.push_str(text);
+                    self.output.code.push('\n');
+                }
+                continue;
+            };
+
+            let span = start..start + text.len();
+            let (name, argument) = directive.split_once(char::is_whitespace)
+                .map_or((directive, ""), |(name, argument)| (name, argument.trim()));
+            if argument.is_empty() && matches!(name, "ifdef" | "ifndef" | "define" | "undef" | "include" | "import") {
+                return Err(self.error(file, span, &format!("#{name} expects an argument")));
+            }
+
+            match name {
+                "ifdef" | "ifndef" => conditions.push(Condition {
+                    active: self.defines.contains(argument) == (name == "ifdef"),
+                    has_else: false,
+                    directive: span
+                }),
+                "else" => match conditions.last_mut() {
+                    Some(condition) if !condition.has_else => {
+                        condition.active = !condition.active;
+                        condition.has_else = true;
+                    },
+                    _ => return Err(self.error(file, span, "#else without a matching #ifdef"))
+                },
+                "endif" => {
+                    if conditions.pop().is_none() {
+                        return Err(self.error(file, span, "#endif without a matching #ifdef"));
+                    }
+                },
+                _ if !active => {},
+                "define" => {
+                    self.defines.insert(argument.to_string());
+                },
+                "undef" => {
+                    self.defines.remove(argument);
+                },
+                "include" | "import" => self.include(file, span, argument.trim_matches('"'))?,
+                _ => return Err(self.error(file, span, &format!("unknown directive #{name}")))
+            }
+        }
+
+        match conditions.pop() {
+            Some(condition) => Err(self.error(file, condition.directive, "#ifdef without a matching #endif")),
+            None => Ok(())
+        }
+    }
+
+    fn include(&mut self, file: usize, span: Range<usize>, name: &str) -> Result<(), ShaderError> {
+        let dir = self.output.files[file].path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let candidates: Vec<PathBuf> = std::iter::once(&dir).chain(self.include_dirs)
+            .map(|dir| normalize(&dir.join(name)))
+            .collect();
+
+        for path in candidates {
+            if self.included.contains(&path) {
+                return Ok(());
+            }
+            match (self.load)(&path) {
+                Ok(source) => return self.process(path, source),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {},
+                Err(error) => return Err(self.error(file, span, &format!("cannot read {}: {error}", path.display())))
+            }
+        }
+
+        Err(self.error(file, span, &format!("cannot find {name}")))
+    }
+
+    fn error(&self, file: usize, span: Range<usize>, message: &str) -> ShaderError {
+        ShaderError::new(&self.output.files, message, vec![(file, span, String::new())], Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, io, path::{Path, PathBuf}};
+
+    use super::{preprocess, Preprocessed};
+
+    fn run(files: &[(&str, &str)], defines: &[&str]) -> Preprocessed {
+        let files: HashMap<PathBuf, String> = files.iter().map(|(path, source)| (PathBuf::from(path), source.to_string())).collect();
+        let defines: Vec<String> = defines.iter().map(|define| define.to_string()).collect();
+        preprocess(Path::new("main.wgsl"), &[PathBuf::from("lib")], &defines, |path| {
+            files.get(path).cloned().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "missing"))
+        }).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    #[test]
+    fn includes_each_file_once() {
+        let source = run(&[
+            ("main.wgsl", "#include \"common.wgsl\"\n#import \"noise.wgsl\"\nmain\n"),
+            ("lib/common.wgsl", "common\n"),
+            ("lib/noise.wgsl", "#include \"common.wgsl\"\n#import \"./noise.wgsl\"\nnoise\n")
+        ], &[]);
+
+        assert_eq!(source.code, "common\nnoise\nmain\n");
+        let paths: Vec<&Path> = source.files.iter().map(|file| file.path.as_path()).collect();
+        assert_eq!(paths, [Path::new("main.wgsl"), Path::new("lib/common.wgsl"), Path::new("lib/noise.wgsl")]);
+    }
+
+    #[test]
+    fn nests_conditions() {
+        let main = "#ifdef A\n#ifndef B\na\n#else\nab\n#endif\n#else\n#ifdef B\nb\n#endif\nnone\n#endif\n";
+
+        assert_eq!(run(&[("main.wgsl", main)], &["A"]).code, "a\n");
+        assert_eq!(run(&[("main.wgsl", main)], &["A", "B"]).code, "ab\n");
+        assert_eq!(run(&[("main.wgsl", main)], &["B"]).code, "b\nnone\n");
+        assert_eq!(run(&[("main.wgsl", main)], &[]).code, "none\n");
+    }
+
+    #[test]
+    fn defines_and_undefines() {
+        let main = "#define A\n#ifdef A\na\n#endif\n#undef A\n#ifdef A\nstill a\n#endif\n#ifdef B\nb\n#endif\n#ifndef C\n#define B\n#endif\n#ifdef B\nb later\n#endif\n";
+
+        assert_eq!(run(&[("main.wgsl", main)], &[]).code, "a\nb later\n");
+        assert_eq!(run(&[("main.wgsl", "#undef A\n#ifndef A\nnot a\n#endif\n")], &["A"]).code, "not a\n");
+    }
+
+    #[test]
+    fn maps_back_into_the_included_file() {
+        let source = run(&[
+            ("main.wgsl", "fn main() {}\n#include \"lib.wgsl\"\nfn last() {}\n"),
+            ("lib/lib.wgsl", "// lib\nfn helper() -> f32 { return 1.0; }\n")
+        ], &[]);
+
+        let start = source.code.find("helper").unwrap();
+        let (file, range) = source.map(start..start + "helper".len());
+        assert_eq!(source.files[file].path, Path::new("lib/lib.wgsl"));
+        assert_eq!(&source.files[file].source[range], "helper");
+
+        let start = source.code.find("last").unwrap();
+        let (file, range) = source.map(start..start + "last".len());
+        assert_eq!((file, &source.files[file].source[range]), (0, "last"));
+    }
+
+    #[test]
+    fn reports_includes_it_cannot_read() {
+        let error = preprocess(Path::new("main.wgsl"), &[PathBuf::from("lib")], &[], |path| match path.to_str() {
+            Some("main.wgsl") => Ok("\n#include \"locked.wgsl\"\n".to_string()),
+            Some("locked.wgsl") => Err(io::Error::new(io::ErrorKind::PermissionDenied, "permission denied")),
+            _ => Err(io::Error::new(io::ErrorKind::NotFound, "missing"))
+        }).err().unwrap();
+
+        assert_eq!(error.summary, "main.wgsl:2:1: cannot read locked.wgsl: permission denied");
+    }
+}
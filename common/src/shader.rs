@@ -0,0 +1,360 @@
+use std::{error::Error, fmt, io, ops::Range, path::{Path, PathBuf}, time::{Duration, Instant, SystemTime}};
+
+use codespan_reporting::{diagnostic::{Diagnostic, Label}, files::{Files, SimpleFiles}, term::{self, termcolor::NoColor}};
+use gpu_layout::HostLayout;
+use wgpu::naga;
+use crate::{layout, params::ParamsLayout, preprocessor::{normalize, preprocess, Preprocessed, SourceFile}};
+
+
+pub struct ShaderFile {
+    pub path: PathBuf,
+    include_dirs: Vec<PathBuf>,
+    defines: Vec<String>,
+    // Sources compiled into the binary, keyed by their path on disk
+    embedded: &'static [(&'static str, &'static str)],
+    watched: Vec<(PathBuf, Option<SystemTime>)>,
+    last_check: Instant
+}
+
+impl ShaderFile {
+    const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+    // `embedded` is the fallback when the files on disk are missing or broken at startup,
+    // it has to contain the shader and everything it includes
+    pub fn new(path: PathBuf, embedded: &'static [(&'static str, &'static str)]) -> Self {
+        let watched = vec![(path.clone(), modified(&path))];
+
+        ShaderFile {
+            path,
+            include_dirs: Vec::new(),
+            defines: Vec::new(),
+            embedded,
+            watched,
+            last_check: Instant::now()
+        }
+    }
+
+    pub fn include_dir(mut self, dir: PathBuf) -> Self {
+        self.include_dirs.push(dir);
+        self
+    }
+
+    pub fn define(mut self, name: &str) -> Self {
+        self.defines.push(name.to_string());
+        self
+    }
+
+    // Reads and preprocesses the shader from disk, every file it touched is watched afterwards
+    pub fn load(&mut self) -> Result<Preprocessed, ShaderError> {
+        let mut visited = Vec::new();
+        let result = preprocess(&self.path, &self.include_dirs, &self.defines, |path| {
+            visited.push(path.to_path_buf());
+            std::fs::read_to_string(path)
+        });
+        self.watched = visited.into_iter().map(|path| {
+            let modified = modified(&path);
+            (path, modified)
+        }).collect();
+
+        result
+    }
+
+    pub fn load_embedded(&self) -> Result<Preprocessed, ShaderError> {
+        preprocess(&self.path, &self.include_dirs, &self.defines, |path| read_embedded(self.embedded, path))
+    }
+
+    pub fn changed(&mut self) -> bool {
+        if self.last_check.elapsed() < Self::POLL_INTERVAL {
+            return false;
+        }
+        self.last_check = Instant::now();
+
+        let mut changed = false;
+        for (path, last_modified) in &mut self.watched {
+            let current = modified(path);
+            if current != *last_modified {
+                *last_modified = current;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+pub struct ShaderError {
+    // One line `file:line:column: message`, short enough for a window title
+    pub summary: String,
+    // Full diagnostic with the offending source snippet
+    pub report: String
+}
+
+impl ShaderError {
+    // Each label points into `files` as (file index, byte range, message)
+    pub fn new(files: &[SourceFile], message: &str, labels: Vec<(usize, Range<usize>, String)>, notes: Vec<String>) -> Self {
+        let mut simple_files = SimpleFiles::new();
+        for file in files {
+            simple_files.add(file.path.display().to_string(), file.source.as_str());
+        }
+
+        let location = labels.first().and_then(|(file, range, _)| {
+            let location = simple_files.location(*file, range.start).ok()?;
+            Some(format!("{}:{}:{}", files[*file].path.display(), location.line_number, location.column_number))
+        });
+        let summary = match location {
+            Some(location) => format!("{location}: {message}"),
+            None => format!("{}: {message}", files[0].path.display())
+        };
+
+        let diagnostic = Diagnostic::error()
+            .with_message(message)
+            .with_labels(labels.into_iter().map(|(file, range, label)| Label::primary(file, range).with_message(label)).collect())
+            .with_notes(notes);
+        let mut writer = NoColor::new(Vec::new());
+        term::emit(&mut writer, &term::Config::default(), &simple_files, &diagnostic).expect("cannot write the shader error");
+
+        ShaderError {
+            summary,
+            report: String::from_utf8(writer.into_inner()).unwrap()
+        }
+    }
+
+    pub fn unreadable(path: &Path, error: &io::Error) -> Self {
+        let summary = format!("{}: {error}", path.display());
+
+        ShaderError {
+            report: format!("error: cannot read {summary}"),
+            summary
+        }
+    }
+
+    // Errors raised by the device have no source location, the last line holds the actual message
+    pub fn device(path: &Path, error: &wgpu::Error) -> Self {
+        let report = error.to_string();
+        let message = report.lines().map(str::trim).rfind(|line| !line.is_empty()).unwrap_or_default();
+
+        ShaderError {
+            summary: format!("{}: {message}", path.display()),
+            report
+        }
+    }
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.report)
+    }
+}
+
+// Parses and validates the WGSL with naga, so errors can be reported with their location
+// in the original files before the source ever reaches the device
+// The source of `path` in `embedded`, which is keyed by the paths on disk like `ShaderFile`'s
+pub fn read_embedded(embedded: &[(&str, &str)], path: &Path) -> io::Result<String> {
+    embedded.iter()
+        .find(|(embedded_path, _)| normalize(Path::new(embedded_path)) == path)
+        .map(|(_, source)| source.to_string())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "not embedded into the binary"))
+}
+
+pub fn validate(source: &Preprocessed) -> Result<naga::Module, ShaderError> {
+    let module = naga::front::wgsl::parse_str(&source.code).map_err(|error| {
+        let labels = map_labels(source, error.labels());
+        ShaderError::new(&source.files, error.message(), labels, Vec::new())
+    })?;
+
+    validate_module(source, &module)?;
+
+    Ok(module)
+}
+
+// Validates a module parsed from `source` by any of naga's frontends
+pub fn validate_module(source: &Preprocessed, module: &naga::Module) -> Result<(), ShaderError> {
+    let mut validator = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all());
+    validator.validate(module).map_err(|error| {
+        let labels = map_labels(source, error.spans().map(|(span, label)| (*span, label.as_str())));
+
+        let mut notes = Vec::new();
+        let mut inner: &dyn Error = error.as_inner();
+        while let Some(next) = inner.source() {
+            notes.push(next.to_string());
+            inner = next;
+        }
+
+        ShaderError::new(&source.files, &error.as_inner().to_string(), labels, notes)
+    })?;
+
+    Ok(())
+}
+
+pub fn map_labels<'a>(source: &Preprocessed, labels: impl Iterator<Item = (naga::Span, &'a str)>) -> Vec<(usize, Range<usize>, String)> {
+    labels
+        .filter_map(|(span, label)| {
+            let (file, range) = source.map(span.to_range()?);
+            Some((file, range, label.to_string()))
+        })
+        .collect()
+}
+
+// Validates `source`, checks its `UniformParameters` struct against `uniform`, reflects its `Params`
+// struct and hands the created module to `create`, any error raised by the device while building
+// the pipelines is returned as well
+pub fn compile<T>(device: &wgpu::Device, source: &Preprocessed, uniform: &HostLayout, params_group: u32, create: impl FnOnce(&wgpu::ShaderModule) -> T) -> Result<(T, ParamsLayout), ShaderError> {
+    let module = validate(source)?;
+    layout::check(source, &module, "UniformParameters", uniform)?;
+    let params = ParamsLayout::reflect(source, &module, params_group)?;
+
+    let value = capture_errors(device, || {
+        let shader_module = create_shader_module(device, &source.code);
+        create(&shader_module)
+    }).map_err(|error| ShaderError::device(source.path(), &error))?;
+
+    Ok((value, params))
+}
+
+pub fn create_shader_module(device: &wgpu::Device, source: &str) -> wgpu::ShaderModule {
+    device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: None,
+        source: wgpu::ShaderSource::Wgsl(source.into())
+    })
+}
+
+// Runs `create` inside a validation error scope, so a broken shader is reported
+// as an error instead of going to the device's uncaptured error handler
+pub fn capture_errors<T>(device: &wgpu::Device, create: impl FnOnce() -> T) -> Result<T, wgpu::Error> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let value = create();
+
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(error) => Err(error),
+        None => Ok(value)
+    }
+}
+
+// Tints the frame with red stripes while the shader on disk is broken
+pub struct ErrorOverlay {
+    pipeline: wgpu::RenderPipeline
+}
+
+impl ErrorOverlay {
+    const SHADER: &'static str = "
+        @vertex
+        fn vs_main(@builtin(vertex_index) id: u32) -> @builtin(position) vec4f {
+            let x = i32(id) & 2;
+            let y = i32(id) & 1;
+            return vec4f(f32(x) * 4.0 - 1.0, 1.0 - f32(y) * 4.0, 0.0, 1.0);
+        }
+
+        @fragment
+        fn fs_main(@builtin(position) pos: vec4f) -> @location(0) vec4f {
+            let stripe = step(0.5, fract((pos.x + pos.y) / 48.0));
+            return vec4f(0.9, 0.05, 0.05, 0.25 + stripe * 0.2);
+        }
+    ";
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader_module = create_shader_module(device, Self::SHADER);
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[]
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "fs_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::all()
+                    })
+                ]
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None
+        });
+
+        ErrorOverlay {
+            pipeline
+        }
+    }
+
+    pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[
+                Some(
+                    wgpu::RenderPassColorAttachment {
+                        view,
+                        resolve_target: None,
+                        ops: wgpu::Operations{
+                            load: wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store
+                        }
+                    }
+                )
+            ],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, path::Path};
+
+    use crate::preprocessor::{preprocess, Preprocessed};
+    use super::{read_embedded, validate};
+
+    const MAIN: &str = "#include \"helpers.wgsl\"
+
+@fragment
+fn fs_main() -> @location(0) vec4f {
+    return vec4f(helper());
+}
+";
+
+    fn source(helpers: &str) -> Preprocessed {
+        preprocess(Path::new("shaders/main.wgsl"), &[], &[], |path| match path.to_str() {
+            Some("shaders/main.wgsl") => Ok(MAIN.to_string()),
+            Some("shaders/helpers.wgsl") => Ok(helpers.to_string()),
+            _ => Err(io::Error::new(io::ErrorKind::NotFound, "missing"))
+        }).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    #[test]
+    fn locates_errors_in_included_files() {
+        let error = validate(&source("// Helpers\nfn helper() -> f32 {\n    return missing;\n}\n")).err().unwrap();
+        assert_eq!(error.summary, "shaders/helpers.wgsl:3:12: no definition in scope for identifier: 'missing'");
+        assert!(error.report.contains("┌─ shaders/helpers.wgsl:3:12"), "{error}");
+        assert!(error.report.contains("^^^^^^^ unknown identifier"), "{error}");
+
+        // Caught by the validator rather than the parser, the first label is the function
+        let error = validate(&source("// Helpers\nfn helper() -> f32 {\n    return 1u;\n}\n")).err().unwrap();
+        assert_eq!(error.summary, "shaders/helpers.wgsl:2:1: Function [1] 'helper' is invalid");
+        assert!(error.report.contains("3 │ │     return 1u;"), "{error}");
+        assert!(error.report.contains("does not match the function return value"), "{error}");
+    }
+
+    #[test]
+    fn finds_embedded_sources_by_their_normalized_path() {
+        let embedded = [("/steps/02_noises/../shaders/noise.wgsl", "// noise")];
+        assert_eq!(read_embedded(&embedded, Path::new("/steps/shaders/noise.wgsl")).unwrap(), "// noise");
+        assert_eq!(read_embedded(&embedded, Path::new("/steps/shaders/palette.wgsl")).unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
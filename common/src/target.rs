@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use winit::window::Window;
+
+
+pub enum RenderTarget {
+    Window {
+        window: Arc<Window>,
+        title: String,
+        surface: wgpu::Surface<'static>,
+        config: wgpu::SurfaceConfiguration
+    },
+    Offscreen(OffscreenTarget)
+}
+
+impl RenderTarget {
+    pub fn format(&self) -> wgpu::TextureFormat {
+        match self {
+            RenderTarget::Window { config, .. } => config.format,
+            RenderTarget::Offscreen(target) => target.texture.format()
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        match self {
+            RenderTarget::Window { surface, config, .. } => {
+                config.width = width;
+                config.height = height;
+                surface.configure(device, config);
+            },
+            RenderTarget::Offscreen(target) => {
+                *target = OffscreenTarget::new(device, width, height);
+            }
+        }
+    }
+
+    pub fn show_error(&self, error: Option<&str>) {
+        if let RenderTarget::Window { window, title, .. } = self {
+            match error {
+                Some(error) => window.set_title(&format!("{title} | {error}")),
+                None => window.set_title(title)
+            }
+        }
+    }
+
+    pub fn acquire(&self) -> Result<Frame, wgpu::SurfaceError> {
+        match self {
+            RenderTarget::Window { surface, .. } => {
+                let texture = surface.get_current_texture()?;
+                let view = texture.texture.create_view(&wgpu::TextureViewDescriptor::default());
+                Ok(Frame { surface_texture: Some(texture), view })
+            },
+            RenderTarget::Offscreen(target) => {
+                let view = target.texture.create_view(&wgpu::TextureViewDescriptor::default());
+                Ok(Frame { surface_texture: None, view })
+            }
+        }
+    }
+}
+
+pub struct Frame {
+    surface_texture: Option<wgpu::SurfaceTexture>,
+    pub view: wgpu::TextureView
+}
+
+impl Frame {
+    pub fn present(self) {
+        if let Some(texture) = self.surface_texture {
+            texture.present();
+        }
+    }
+}
+
+pub struct OffscreenTarget {
+    pub texture: wgpu::Texture
+}
+
+impl OffscreenTarget {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            view_formats: &[],
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC
+        });
+
+        OffscreenTarget {
+            texture
+        }
+    }
+
+    pub fn read_image(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> image::RgbaImage {
+        let width = self.texture.width();
+        let height = self.texture.height();
+
+        // Rows of a texture-to-buffer copy have to be aligned to 256 bytes
+        let unpadded_bytes_per_row = width * 4;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (padded_bytes_per_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                origin: wgpu::Origin3d::ZERO,
+                mip_level: 0,
+                aspect: wgpu::TextureAspect::All
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height)
+                }
+            },
+            self.texture.size()
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+            }
+        }
+        buffer.unmap();
+
+        image::RgbaImage::from_raw(width, height, pixels).unwrap()
+    }
+}
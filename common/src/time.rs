@@ -0,0 +1,182 @@
+use winit::keyboard::{Key, NamedKey};
+
+
+// The shader time. Space pauses it, `.` advances a single frame, Left and Right scrub it by half
+// a second and Home rewinds it to zero. With a fixed step every frame advances by the same
+// amount, so a run can be replayed frame by frame.
+pub enum TimeMode {
+    RealTime,
+    FixedStep(f32)
+}
+
+pub struct Time {
+    mode: TimeMode,
+    prev_frame: std::time::Instant,
+    elapsed: f32,
+    elapsed_frame: f32,
+    frame: u32,
+    paused: bool,
+    // Single steps and scrubbing requested since the last update
+    pending: f32
+}
+
+impl Time {
+    const SCRUB_STEP: f32 = 0.5;
+
+    pub fn new(mode: TimeMode) -> Self {
+        Time {
+            mode,
+            prev_frame: std::time::Instant::now(),
+            elapsed: 0.0,
+            elapsed_frame: 0.0,
+            frame: 0,
+            paused: false,
+            pending: 0.0
+        }
+    }
+
+    pub fn set_elapsed(&mut self, elapsed: f32) {
+        self.elapsed = elapsed;
+        self.pending = 0.0;
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn step(&mut self) {
+        self.pending += self.step_size();
+    }
+
+    pub fn scrub(&mut self, offset: f32) {
+        self.pending += offset;
+    }
+
+    pub fn update(&mut self) {
+        let now = std::time::Instant::now();
+        let delta = match self.mode {
+            TimeMode::RealTime => (now - self.prev_frame).as_secs_f32(),
+            TimeMode::FixedStep(step) => step
+        };
+        self.prev_frame = now;
+
+        // The first frame is rendered exactly at the start time
+        let delta = if self.paused || self.frame == 0 { 0.0 } else { delta };
+        let elapsed = (self.elapsed + delta + self.pending).max(0.0);
+
+        self.elapsed_frame = elapsed - self.elapsed;
+        self.elapsed = elapsed;
+        self.pending = 0.0;
+        self.frame += 1;
+    }
+
+    pub fn handle_key(&mut self, key: &Key) -> bool {
+        match key {
+            Key::Named(NamedKey::Space) => self.paused = !self.paused,
+            Key::Named(NamedKey::ArrowRight) => self.scrub(Self::SCRUB_STEP),
+            Key::Named(NamedKey::ArrowLeft) => self.scrub(-Self::SCRUB_STEP),
+            Key::Named(NamedKey::Home) => self.set_elapsed(0.0),
+            Key::Character(c) if c == "." => self.step(),
+            _ => return false
+        }
+
+        true
+    }
+
+    pub fn elapsed(&self) -> f32 {
+        self.elapsed
+    }
+
+    // Index of the frame being rendered, counting from 0
+    pub fn frame(&self) -> u32 {
+        self.frame.saturating_sub(1)
+    }
+
+    pub fn elapsed_frame(&self) -> f32 {
+        self.elapsed_frame
+    }
+
+    fn step_size(&self) -> f32 {
+        match self.mode {
+            TimeMode::RealTime => 1.0 / 60.0,
+            TimeMode::FixedStep(step) => step
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::keyboard::{Key, NamedKey};
+    use super::{Time, TimeMode};
+
+    fn press(time: &mut Time, key: Key) {
+        assert!(time.handle_key(&key));
+    }
+
+    #[test]
+    fn pauses_steps_and_scrubs() {
+        let mut time = Time::new(TimeMode::FixedStep(0.25));
+        time.set_elapsed(1.0);
+        time.update();
+        assert_eq!((time.elapsed(), time.frame()), (1.0, 0));
+        time.update();
+        assert_eq!((time.elapsed(), time.frame()), (1.25, 1));
+
+        press(&mut time, Key::Named(NamedKey::Space));
+        time.update();
+        assert_eq!(time.elapsed(), 1.25);
+        press(&mut time, Key::Character(".".into()));
+        time.update();
+        assert_eq!(time.elapsed(), 1.5);
+        time.update();
+        assert_eq!(time.elapsed(), 1.5);
+
+        press(&mut time, Key::Named(NamedKey::ArrowRight));
+        time.update();
+        assert_eq!(time.elapsed(), 2.0);
+        for _ in 0..5 {
+            press(&mut time, Key::Named(NamedKey::ArrowLeft));
+        }
+        time.update();
+        assert_eq!(time.elapsed(), 0.0);
+        assert_eq!(time.elapsed_frame(), -2.0);
+
+        press(&mut time, Key::Named(NamedKey::Space));
+        time.update();
+        assert_eq!(time.elapsed(), 0.25);
+        assert!(!time.handle_key(&Key::Named(NamedKey::Enter)));
+    }
+
+    #[test]
+    fn home_rewinds_to_zero() {
+        let mut time = Time::new(TimeMode::FixedStep(0.5));
+        time.set_elapsed(3.0);
+        time.update();
+        time.scrub(1.0);
+
+        // Drops the scrubbing that was still pending
+        press(&mut time, Key::Named(NamedKey::Home));
+        assert_eq!(time.elapsed(), 0.0);
+        time.update();
+        assert_eq!((time.elapsed(), time.frame()), (0.5, 1));
+    }
+
+    #[test]
+    fn fixed_steps_ignore_the_wall_clock() {
+        let run = |sleep: u64| {
+            let mut time = Time::new(TimeMode::FixedStep(1.0 / 60.0));
+            (0..90).map(|frame| {
+                std::thread::sleep(std::time::Duration::from_millis(sleep));
+                if frame == 30 {
+                    time.step();
+                }
+                time.update();
+                time.elapsed().to_bits()
+            }).collect::<Vec<u32>>()
+        };
+
+        let frames = run(0);
+        assert_eq!(frames, run(2));
+        assert!((f32::from_bits(frames[89]) - 90.0 / 60.0).abs() < 1e-5);
+    }
+}
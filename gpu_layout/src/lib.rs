@@ -0,0 +1,111 @@
+// The layout rules the derives in `gpu_layout_derive` build on. The generated code refers to
+// this crate by name, it re-exports the derives so depending on it is enough.
+pub use gpu_layout_derive::{UniformLayout, VertexLayout};
+
+// Lets the tests below use the derives, which name this crate `gpu_layout`
+extern crate self as gpu_layout;
+
+
+// The layout of a Rust struct that is uploaded into a WGSL struct
+pub struct HostLayout {
+    pub name: &'static str,
+    pub size: usize,
+    pub fields: Vec<HostField>
+}
+
+pub struct HostField {
+    pub name: String,
+    pub offset: usize,
+    pub size: usize
+}
+
+// Values placed into uniform buffers with the alignment and size WGSL gives them. Implemented for
+// scalars, vectors, `mat4x4<f32>` as `[f32; 16]` and, with `#[derive(UniformLayout)]`, for structs.
+pub trait UniformLayout {
+    const ALIGN: usize;
+    const SIZE: usize;
+    // Bytes from the start of a struct member of this type to the next member. A member after
+    // a nested struct starts at least roundUp(16, SizeOf(S)) bytes later in a uniform buffer.
+    const MEMBER_STRIDE: usize = Self::SIZE;
+
+    // Writes the value to the start of `bytes`
+    fn write(&self, bytes: &mut [u8]);
+
+    // The members of a struct, in declaration order
+    fn fields() -> Vec<HostField> {
+        Vec::new()
+    }
+
+    fn layout() -> HostLayout where Self: Sized {
+        HostLayout {
+            name: std::any::type_name::<Self>().rsplit("::").next().unwrap_or_default(),
+            size: Self::SIZE,
+            fields: Self::fields()
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> where Self: Sized {
+        let mut bytes = vec![0; Self::SIZE];
+        self.write(&mut bytes);
+        bytes
+    }
+}
+
+macro_rules! impl_uniform_layout {
+    ($($ty:ty => $align:literal),*) => {
+        $(
+            impl UniformLayout for $ty {
+                const ALIGN: usize = $align;
+                const SIZE: usize = std::mem::size_of::<$ty>();
+
+                fn write(&self, bytes: &mut [u8]) {
+                    bytes[..Self::SIZE].copy_from_slice(bytemuck::bytes_of(self));
+                }
+            }
+        )*
+    };
+}
+
+impl_uniform_layout!(
+    f32 => 4, i32 => 4, u32 => 4,
+    [f32; 2] => 8, [i32; 2] => 8, [u32; 2] => 8,
+    [f32; 3] => 16, [i32; 3] => 16, [u32; 3] => 16,
+    [f32; 4] => 16, [i32; 4] => 16, [u32; 4] => 16,
+    [f32; 16] => 16
+);
+
+pub const fn align_to(offset: usize, align: usize) -> usize {
+    offset.div_ceil(align) * align
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UniformLayout;
+
+    #[derive(UniformLayout)]
+    struct Light {
+        intensity: f32
+    }
+
+    #[derive(UniformLayout)]
+    struct Scene {
+        light: Light,
+        exposure: f32,
+        #[uniform(flatten)]
+        sun: Light
+    }
+
+    #[test]
+    fn spaces_the_member_after_a_nested_struct() {
+        assert_eq!((Light::ALIGN, Light::SIZE, Light::MEMBER_STRIDE), (16, 4, 16));
+
+        let offsets: Vec<(String, usize)> = Scene::fields().into_iter().map(|field| (field.name, field.offset)).collect();
+        assert_eq!(offsets, [("light".to_string(), 0), ("exposure".to_string(), 16), ("sun.intensity".to_string(), 32)]);
+        assert_eq!(Scene::SIZE, 48);
+
+        let scene = Scene { light: Light { intensity: 2.0 }, exposure: 3.0, sun: Light { intensity: 4.0 } };
+        let bytes = scene.to_bytes();
+        let floats: Vec<f32> = bytes.chunks(4).map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap())).collect();
+        assert_eq!(floats, [2.0, 0.0, 0.0, 0.0, 3.0, 0.0, 0.0, 0.0, 4.0, 0.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,189 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, punctuated::Punctuated, spanned::Spanned, Data, DeriveInput, Expr, ExprLit, Field, Fields, Lit, Token, Type};
+
+
+// Derives for the structs the steps upload to the GPU. The generated code refers to `wgpu`
+// and to the `gpu_layout` crate, which re-exports them with the traits they implement.
+
+// `desc()` returning the `wgpu::VertexBufferLayout` of a `#[repr(C)]` vertex struct. The fields
+// are given consecutive shader locations in declaration order and have to be `f32`, `i32`, `u32`
+// or arrays of two to four of them.
+#[proc_macro_derive(VertexLayout)]
+pub fn derive_vertex_layout(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    vertex_layout(&input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+// Implements `gpu_layout::UniformLayout`, which writes the fields at the offsets WGSL gives
+// them in a uniform buffer. `#[uniform(flatten)]` on a field of a struct that derives this too
+// lists its members in place of the field when the layout is checked against a shader.
+#[proc_macro_derive(UniformLayout, attributes(uniform))]
+pub fn derive_uniform_layout(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    uniform_layout(&input).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+fn vertex_layout(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let fields = named_fields(input)?;
+
+    let attributes = fields.iter().enumerate().map(|(location, field)| {
+        let ident = &field.ident;
+        let format = vertex_format(&field.ty)?;
+        let location = location as u32;
+        Ok(quote! {
+            wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::#format,
+                offset: std::mem::offset_of!(#name, #ident) as u64,
+                shader_location: #location
+            }
+        })
+    }).collect::<syn::Result<Vec<_>>>()?;
+    let count = attributes.len();
+
+    Ok(quote! {
+        impl #name {
+            const ATTRIBUTES: [wgpu::VertexAttribute; #count] = [#(#attributes),*];
+
+            pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+                wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<#name>() as u64,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &Self::ATTRIBUTES
+                }
+            }
+        }
+    })
+}
+
+fn vertex_format(ty: &Type) -> syn::Result<syn::Ident> {
+    let (scalar, count) = match ty {
+        Type::Path(path) => (path.path.get_ident(), 1),
+        Type::Array(array) => match (&*array.elem, &array.len) {
+            (Type::Path(path), Expr::Lit(ExprLit { lit: Lit::Int(len), .. })) => (path.path.get_ident(), len.base10_parse()?),
+            _ => (None, 0)
+        },
+        _ => (None, 0)
+    };
+
+    let prefix = match scalar.map(ToString::to_string).as_deref() {
+        Some("f32") => "Float32",
+        Some("i32") => "Sint32",
+        Some("u32") => "Uint32",
+        _ => ""
+    };
+    match (prefix, count) {
+        ("", _) | (_, 0 | 5..) => Err(syn::Error::new(ty.span(), "vertex attributes have to be f32, i32, u32 or an array of 2 to 4 of them")),
+        (prefix, 1) => Ok(format_ident!("{prefix}")),
+        (prefix, count) => Ok(format_ident!("{prefix}x{count}"))
+    }
+}
+
+fn uniform_layout(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let fields = named_fields(input)?;
+    if fields.is_empty() {
+        return Err(syn::Error::new(name.span(), "uniform structs need at least one field"));
+    }
+
+    let count = fields.len();
+    let indices: Vec<usize> = (0..count).collect();
+    let idents: Vec<_> = fields.iter().map(|field| &field.ident).collect();
+    let types: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+    let last = &types[count - 1];
+
+    let host_fields = fields.iter().enumerate().map(|(index, field)| {
+        let ident = &field.ident;
+        let ty = &field.ty;
+        Ok(match is_flattened(field)? {
+            true => quote! {
+                fields.extend(<#ty as ::gpu_layout::UniformLayout>::fields().into_iter().map(|field| ::gpu_layout::HostField {
+                    name: format!("{}.{}", stringify!(#ident), field.name),
+                    offset: Self::OFFSETS[#index] + field.offset,
+                    size: field.size
+                }));
+            },
+            false => quote! {
+                fields.push(::gpu_layout::HostField {
+                    name: stringify!(#ident).to_string(),
+                    offset: Self::OFFSETS[#index],
+                    size: <#ty as ::gpu_layout::UniformLayout>::SIZE
+                });
+            }
+        })
+    }).collect::<syn::Result<Vec<_>>>()?;
+
+    Ok(quote! {
+        impl #name {
+            const OFFSETS: [usize; #count] = {
+                let mut offsets = [0; #count];
+                let mut end = 0;
+                #(
+                    offsets[#indices] = ::gpu_layout::align_to(end, <#types as ::gpu_layout::UniformLayout>::ALIGN);
+                    end = offsets[#indices] + <#types as ::gpu_layout::UniformLayout>::MEMBER_STRIDE;
+                )*
+                offsets
+            };
+
+            // The largest alignment of the fields, the size is rounded up to it
+            const MEMBER_ALIGN: usize = {
+                let mut align = 1;
+                #(
+                    if <#types as ::gpu_layout::UniformLayout>::ALIGN > align {
+                        align = <#types as ::gpu_layout::UniformLayout>::ALIGN;
+                    }
+                )*
+                align
+            };
+        }
+
+        impl ::gpu_layout::UniformLayout for #name {
+            // Structs nested in a uniform buffer start at a multiple of 16 bytes
+            const ALIGN: usize = ::gpu_layout::align_to(Self::MEMBER_ALIGN, 16);
+            const SIZE: usize = ::gpu_layout::align_to(
+                Self::OFFSETS[#count - 1] + <#last as ::gpu_layout::UniformLayout>::SIZE,
+                Self::MEMBER_ALIGN
+            );
+            // The member after a nested struct starts at roundUp(16, SizeOf(S))
+            const MEMBER_STRIDE: usize = ::gpu_layout::align_to(Self::SIZE, 16);
+
+            fn write(&self, bytes: &mut [u8]) {
+                #(
+                    ::gpu_layout::UniformLayout::write(&self.#idents, &mut bytes[Self::OFFSETS[#indices]..]);
+                )*
+            }
+
+            fn fields() -> Vec<::gpu_layout::HostField> {
+                let mut fields = Vec::new();
+                #(#host_fields)*
+                fields
+            }
+        }
+    })
+}
+
+fn is_flattened(field: &Field) -> syn::Result<bool> {
+    let mut flatten = false;
+    for attribute in field.attrs.iter().filter(|attribute| attribute.path().is_ident("uniform")) {
+        for option in attribute.parse_args_with(Punctuated::<syn::Path, Token![,]>::parse_terminated)? {
+            match option.is_ident("flatten") {
+                true => flatten = true,
+                false => return Err(syn::Error::new(option.span(), "unknown uniform option, expected flatten"))
+            }
+        }
+    }
+
+    Ok(flatten)
+}
+
+fn named_fields(input: &DeriveInput) -> syn::Result<Vec<&Field>> {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(fields.named.iter().collect()),
+            _ => Err(syn::Error::new(input.ident.span(), "only structs with named fields are supported"))
+        },
+        _ => Err(syn::Error::new(input.ident.span(), "only structs are supported"))
+    }
+}
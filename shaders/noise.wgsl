@@ -0,0 +1,581 @@
+// Noise library shared by the steps, included with `#include "noise.wgsl"`
+//
+// Defines:
+//   ANIMATED_NOISE  rotates the gradients over time, the including shader has to declare
+//                   `uniforms` with a `time: f32` member
+//   QUINTIC_FADE    interpolates perlin noise with smootherstep instead of smoothstep
+
+fn domainWarp(pos2: vec2f, octaves: i32) -> f32 {
+    return domainWarpWith(pos2, octaves, 1.0, 0.0);
+}
+
+// `strength` scales the warp and `seed` shifts the two fbm lookups that make it
+fn domainWarpWith(pos2: vec2f, octaves: i32, strength: f32, seed: f32) -> f32 {
+    let r = length(pos2);
+    let t = length(atan(pos2));
+    let pos = vec2f(r, t);
+    let offset = vec2f(
+        fbm(pos + vec2f(15.424, 42.14) + seed, octaves),
+        fbm(pos + vec2f(74.824, 378.54) + seed, octaves)
+    );
+
+    return fbm(pos + offset * strength, octaves);
+}
+
+fn worleyNoise(pos: vec2f) -> f32 {
+    let base = floor(pos);
+    let dist = fract(pos);
+
+    var accum = 0.0;
+    var minDist = 1.0;
+    let k = -5.0;
+
+    for(var x = -1.0; x <= 1.0; x += 1.0) {
+        for(var y = -1.0; y <= 1.0; y += 1.0) {
+            let offset = vec2f(x, y);
+            let cellP = abs(vec2f(
+                perlinNoise(base + offset + vec2f(12.515, 166.424)),
+                perlinNoise(base + offset + vec2f(82.115, 76.624))
+            ));
+            let currDist = cellP + offset - dist;
+            accum += exp(length(currDist) * k);
+            //minDist = min(minDist, length(currDist));
+        }
+    }
+
+    return log(accum) / k;
+}
+
+fn fbm(pos: vec2f, octaves: i32) -> f32 {
+    var accum = 0.0;
+    var result = 0.0;
+    var freq = 1.0;
+    var amp = 1.0;
+
+    for(var i = 0; i < octaves; i += 1) {
+        let noise = perlinNoise(pos * freq) * 0.5 + 0.5;
+        result += noise * amp;
+        accum += amp;
+        freq *= 2.0;
+        amp *= 0.5;
+    }
+
+    return result / accum;
+}
+
+// Returns values in range -1 to 1
+fn perlinNoise(pos: vec2f) -> f32 {
+    let base = floor(pos);
+    let dist = fract(pos);
+
+    let d1 = dot(randomGradient(base + vec2f(0.0, 0.0)), dist - vec2f(0.0, 0.0));
+    let d2 = dot(randomGradient(base + vec2f(1.0, 0.0)), dist - vec2f(1.0, 0.0));
+    let d3 = dot(randomGradient(base + vec2f(0.0, 1.0)), dist - vec2f(0.0, 1.0));
+    let d4 = dot(randomGradient(base + vec2f(1.0, 1.0)), dist - vec2f(1.0, 1.0));
+
+#ifdef QUINTIC_FADE
+    let k = dist * dist * dist * (dist * (dist * 6.0 - 15.0) + 10.0);
+#else
+    let k = smoothstep(vec2f(0.0), vec2f(1.0), dist);
+#endif
+
+    let lerp1 = mix(d1, d2, k.x);
+    let lerp2 = mix(d3, d4, k.x);
+
+    return mix(lerp1, lerp2, k.y);
+}
+
+// Pseudorandom direction vector, no precomputed gradients mean this works for any number of grid coordinates
+fn randomGradient(posf: vec2f) -> vec2f {
+    let a = hashCell(vec2i(posf));
+#ifdef ANIMATED_NOISE
+    let random = 0.5 * uniforms.time * f32(a) * (3.14159265 / f32(~(~u32(0) >> 1)));
+#else
+    let random = f32(a) * (3.14159265 / f32(~(~u32(0) >> 1))); // in [0, 2*Pi]
+#endif
+
+    return vec2f(cos(random), sin(random));
+}
+
+// Integer hash of a grid cell, mirrored bit for bit by `noise::hash_cell` in step 02
+fn hashCell(posi: vec2i) -> u32 {
+    let w = u32(32);
+    let s = w / 2; // rotation width
+    var a = u32(posi.x);
+    var b = u32(posi.y);
+    a *= u32(3284157443); b ^= a << s | a >> w-s;
+    b *= u32(1911520717); a ^= b << s | b >> w-s;
+    a *= u32(2048419325);
+
+    return a;
+}
+
+// Seedable noise
+//
+// Unlike the functions above these take a seed, ignore ANIMATED_NOISE and interpolate with the
+// quintic fade. Step 02's noise.rs has a Rust version of each, named in snake_case.
+
+const BASIS_PERLIN: i32 = 0;
+const BASIS_VALUE: i32 = 1;
+const BASIS_SIMPLEX: i32 = 2;
+
+// Settings of the fractal noises, every octave multiplies the frequency by `lacunarity`
+// and the amplitude by `gain` and uses the next seed
+struct Fractal {
+    basis: i32,
+    octaves: i32,
+    lacunarity: f32,
+    gain: f32,
+    seed: u32
+}
+
+fn defaultFractal(seed: u32) -> Fractal {
+    return Fractal(BASIS_PERLIN, 5, 2.0, 0.5, seed);
+}
+
+// Returns values in range -1 to 1
+fn valueNoise2(pos: vec2f, seed: u32) -> f32 {
+    let cell = vec2i(floor(pos));
+    let k = quinticFade2(fract(pos));
+
+    let a = hashToSigned(hash2(cell, seed));
+    let b = hashToSigned(hash2(cell + vec2i(1, 0), seed));
+    let c = hashToSigned(hash2(cell + vec2i(0, 1), seed));
+    let d = hashToSigned(hash2(cell + vec2i(1, 1), seed));
+
+    return mix(mix(a, b, k.x), mix(c, d, k.x), k.y);
+}
+
+// Returns values in range -1 to 1
+fn valueNoise3(pos: vec3f, seed: u32) -> f32 {
+    let cell = vec3i(floor(pos));
+    let k = quinticFade3(fract(pos));
+
+    var values: array<f32, 8>;
+    for(var i = 0; i < 8; i += 1) {
+        values[i] = hashToSigned(hash3(cell + cubeCorner(i), seed));
+    }
+
+    return interpolate3(values, k);
+}
+
+// Gradient noise with hashed gradients, roughly in range -1 to 1
+fn perlinNoise2(pos: vec2f, seed: u32) -> f32 {
+    let cell = vec2i(floor(pos));
+    let dist = fract(pos);
+    let k = quinticFade2(dist);
+
+    let a = gradient2(hash2(cell, seed), dist);
+    let b = gradient2(hash2(cell + vec2i(1, 0), seed), dist - vec2f(1.0, 0.0));
+    let c = gradient2(hash2(cell + vec2i(0, 1), seed), dist - vec2f(0.0, 1.0));
+    let d = gradient2(hash2(cell + vec2i(1, 1), seed), dist - vec2f(1.0, 1.0));
+
+    return 0.5 * mix(mix(a, b, k.x), mix(c, d, k.x), k.y);
+}
+
+fn perlinNoise3(pos: vec3f, seed: u32) -> f32 {
+    let cell = vec3i(floor(pos));
+    let dist = fract(pos);
+
+    var values: array<f32, 8>;
+    for(var i = 0; i < 8; i += 1) {
+        let corner = cubeCorner(i);
+        values[i] = gradient3(hash3(cell + corner, seed), dist - vec3f(corner));
+    }
+
+    return interpolate3(values, quinticFade3(dist));
+}
+
+fn perlinNoise4(pos: vec4f, seed: u32) -> f32 {
+    let cell = vec4i(floor(pos));
+    let dist = fract(pos);
+    let k = quinticFade4(dist);
+
+    var values: array<f32, 16>;
+    for(var i = 0; i < 16; i += 1) {
+        let corner = vec4i(i & 1, (i >> 1u) & 1, (i >> 2u) & 1, (i >> 3u) & 1);
+        values[i] = gradient4(hash4(cell + corner, seed), dist - vec4f(corner));
+    }
+    for(var i = 0; i < 8; i += 1) {
+        values[i] = mix(values[i * 2], values[i * 2 + 1], k.x);
+    }
+    for(var i = 0; i < 4; i += 1) {
+        values[i] = mix(values[i * 2], values[i * 2 + 1], k.y);
+    }
+    for(var i = 0; i < 2; i += 1) {
+        values[i] = mix(values[i * 2], values[i * 2 + 1], k.z);
+    }
+
+    return mix(values[0], values[1], k.w);
+}
+
+// Simplex noise after Stefan Gustavson's implementations, roughly in range -1 to 1
+fn simplexNoise2(pos: vec2f, seed: u32) -> f32 {
+    let skew = (sqrt(3.0) - 1.0) / 2.0;
+    let unskew = (3.0 - sqrt(3.0)) / 6.0;
+
+    let cell = floor(pos + (pos.x + pos.y) * skew);
+    let d0 = pos - (cell - (cell.x + cell.y) * unskew);
+    let corner = select(vec2f(0.0, 1.0), vec2f(1.0, 0.0), d0.x > d0.y);
+    let d1 = d0 - corner + unskew;
+    let d2 = d0 - 1.0 + 2.0 * unskew;
+
+    let c = vec2i(cell);
+    let n0 = simplexCorner(0.5 - dot(d0, d0), gradient2(hash2(c, seed), d0));
+    let n1 = simplexCorner(0.5 - dot(d1, d1), gradient2(hash2(c + vec2i(corner), seed), d1));
+    let n2 = simplexCorner(0.5 - dot(d2, d2), gradient2(hash2(c + vec2i(1, 1), seed), d2));
+
+    return 40.0 * (n0 + n1 + n2);
+}
+
+fn simplexNoise3(pos: vec3f, seed: u32) -> f32 {
+    let skew = 1.0 / 3.0;
+    let unskew = 1.0 / 6.0;
+
+    let cell = floor(pos + (pos.x + pos.y + pos.z) * skew);
+    let d0 = pos - (cell - (cell.x + cell.y + cell.z) * unskew);
+
+    // The simplex is picked by the order of the components
+    let g = step(d0.yzx, d0.xyz);
+    let l = 1.0 - g;
+    let corner1 = min(g, l.zxy);
+    let corner2 = max(g, l.zxy);
+    let d1 = d0 - corner1 + unskew;
+    let d2 = d0 - corner2 + 2.0 * unskew;
+    let d3 = d0 - 1.0 + 3.0 * unskew;
+
+    let c = vec3i(cell);
+    let n0 = simplexCorner(0.6 - dot(d0, d0), gradient3(hash3(c, seed), d0));
+    let n1 = simplexCorner(0.6 - dot(d1, d1), gradient3(hash3(c + vec3i(corner1), seed), d1));
+    let n2 = simplexCorner(0.6 - dot(d2, d2), gradient3(hash3(c + vec3i(corner2), seed), d2));
+    let n3 = simplexCorner(0.6 - dot(d3, d3), gradient3(hash3(c + vec3i(1, 1, 1), seed), d3));
+
+    return 32.0 * (n0 + n1 + n2 + n3);
+}
+
+fn simplexNoise4(pos: vec4f, seed: u32) -> f32 {
+    let skew = (sqrt(5.0) - 1.0) / 4.0;
+    let unskew = (5.0 - sqrt(5.0)) / 20.0;
+
+    let cell = floor(pos + (pos.x + pos.y + pos.z + pos.w) * skew);
+    let d0 = pos - (cell - (cell.x + cell.y + cell.z + cell.w) * unskew);
+
+    // Rank of every component, the number of other components it is larger than
+    let isX = step(d0.yzw, d0.xxx);
+    let isYZ = step(d0.zww, d0.yyz);
+    var rank = vec4f(isX.x + isX.y + isX.z, 1.0 - isX);
+    rank.y += isYZ.x + isYZ.y;
+    rank.z += 1.0 - isYZ.x;
+    rank.w += 1.0 - isYZ.y;
+    rank.z += isYZ.z;
+    rank.w += 1.0 - isYZ.z;
+
+    let corner3 = clamp(rank, vec4f(0.0), vec4f(1.0));
+    let corner2 = clamp(rank - 1.0, vec4f(0.0), vec4f(1.0));
+    let corner1 = clamp(rank - 2.0, vec4f(0.0), vec4f(1.0));
+    let d1 = d0 - corner1 + unskew;
+    let d2 = d0 - corner2 + 2.0 * unskew;
+    let d3 = d0 - corner3 + 3.0 * unskew;
+    let d4 = d0 - 1.0 + 4.0 * unskew;
+
+    let c = vec4i(cell);
+    let n0 = simplexCorner(0.6 - dot(d0, d0), gradient4(hash4(c, seed), d0));
+    let n1 = simplexCorner(0.6 - dot(d1, d1), gradient4(hash4(c + vec4i(corner1), seed), d1));
+    let n2 = simplexCorner(0.6 - dot(d2, d2), gradient4(hash4(c + vec4i(corner2), seed), d2));
+    let n3 = simplexCorner(0.6 - dot(d3, d3), gradient4(hash4(c + vec4i(corner3), seed), d3));
+    let n4 = simplexCorner(0.6 - dot(d4, d4), gradient4(hash4(c + vec4i(1, 1, 1, 1), seed), d4));
+
+    return 27.0 * (n0 + n1 + n2 + n3 + n4);
+}
+
+// Sum of octaves, in range -1 to 1
+fn fractalFbm2(pos: vec2f, fractal: Fractal) -> f32 {
+    var result = 0.0;
+    var accum = 0.0;
+    var freq = 1.0;
+    var amp = 1.0;
+
+    for(var i = 0; i < fractal.octaves; i += 1) {
+        result += basisNoise2(fractal.basis, pos * freq, fractal.seed + u32(i)) * amp;
+        accum += amp;
+        freq *= fractal.lacunarity;
+        amp *= fractal.gain;
+    }
+
+    return result / accum;
+}
+
+fn fractalFbm3(pos: vec3f, fractal: Fractal) -> f32 {
+    var result = 0.0;
+    var accum = 0.0;
+    var freq = 1.0;
+    var amp = 1.0;
+
+    for(var i = 0; i < fractal.octaves; i += 1) {
+        result += basisNoise3(fractal.basis, pos * freq, fractal.seed + u32(i)) * amp;
+        accum += amp;
+        freq *= fractal.lacunarity;
+        amp *= fractal.gain;
+    }
+
+    return result / accum;
+}
+
+// Sharp crests where the noise crosses zero, in range 0 to 1
+fn fractalRidged2(pos: vec2f, fractal: Fractal) -> f32 {
+    var result = 0.0;
+    var accum = 0.0;
+    var freq = 1.0;
+    var amp = 1.0;
+
+    for(var i = 0; i < fractal.octaves; i += 1) {
+        let ridge = 1.0 - abs(basisNoise2(fractal.basis, pos * freq, fractal.seed + u32(i)));
+        result += ridge * ridge * amp;
+        accum += amp;
+        freq *= fractal.lacunarity;
+        amp *= fractal.gain;
+    }
+
+    return result / accum;
+}
+
+fn fractalRidged3(pos: vec3f, fractal: Fractal) -> f32 {
+    var result = 0.0;
+    var accum = 0.0;
+    var freq = 1.0;
+    var amp = 1.0;
+
+    for(var i = 0; i < fractal.octaves; i += 1) {
+        let ridge = 1.0 - abs(basisNoise3(fractal.basis, pos * freq, fractal.seed + u32(i)));
+        result += ridge * ridge * amp;
+        accum += amp;
+        freq *= fractal.lacunarity;
+        amp *= fractal.gain;
+    }
+
+    return result / accum;
+}
+
+// Rounded bumps with creases where the noise crosses zero, in range -1 to 1
+fn fractalBillow2(pos: vec2f, fractal: Fractal) -> f32 {
+    var result = 0.0;
+    var accum = 0.0;
+    var freq = 1.0;
+    var amp = 1.0;
+
+    for(var i = 0; i < fractal.octaves; i += 1) {
+        result += (abs(basisNoise2(fractal.basis, pos * freq, fractal.seed + u32(i))) * 2.0 - 1.0) * amp;
+        accum += amp;
+        freq *= fractal.lacunarity;
+        amp *= fractal.gain;
+    }
+
+    return result / accum;
+}
+
+fn fractalBillow3(pos: vec3f, fractal: Fractal) -> f32 {
+    var result = 0.0;
+    var accum = 0.0;
+    var freq = 1.0;
+    var amp = 1.0;
+
+    for(var i = 0; i < fractal.octaves; i += 1) {
+        result += (abs(basisNoise3(fractal.basis, pos * freq, fractal.seed + u32(i))) * 2.0 - 1.0) * amp;
+        accum += amp;
+        freq *= fractal.lacunarity;
+        amp *= fractal.gain;
+    }
+
+    return result / accum;
+}
+
+// Cellular noise with one feature point per cell. Returns the distance to the closest point (F1),
+// to the second closest point (F2) and to the border of the closest point's cell
+fn voronoi2(pos: vec2f, seed: u32) -> vec3f {
+    let cell = vec2i(floor(pos));
+    let dist = fract(pos);
+
+    var f1 = 8.0;
+    var f2 = 8.0;
+    var closest = vec2f(0.0);
+    var closestCell = vec2i(0);
+    for(var y = -1; y <= 1; y += 1) {
+        for(var x = -1; x <= 1; x += 1) {
+            let offset = vec2i(x, y);
+            let toPoint = featurePoint2(hash2(cell + offset, seed)) + vec2f(offset) - dist;
+            let d = dot(toPoint, toPoint);
+            if d < f1 {
+                f2 = f1;
+                f1 = d;
+                closest = toPoint;
+                closestCell = offset;
+            } else if d < f2 {
+                f2 = d;
+            }
+        }
+    }
+
+    // The border is halfway to the neighbours of the closest point
+    var edge = 8.0;
+    for(var y = -2; y <= 2; y += 1) {
+        for(var x = -2; x <= 2; x += 1) {
+            let offset = closestCell + vec2i(x, y);
+            let toPoint = featurePoint2(hash2(cell + offset, seed)) + vec2f(offset) - dist;
+            let between = toPoint - closest;
+            if dot(between, between) > 0.00001 {
+                edge = min(edge, dot(0.5 * (closest + toPoint), normalize(between)));
+            }
+        }
+    }
+
+    return vec3f(sqrt(f1), sqrt(f2), edge);
+}
+
+// Divergence free flow, the rotated gradient of simplex noise
+fn curlNoise2(pos: vec2f, seed: u32) -> vec2f {
+    let e = 0.01;
+    let dx = simplexNoise2(pos + vec2f(e, 0.0), seed) - simplexNoise2(pos - vec2f(e, 0.0), seed);
+    let dy = simplexNoise2(pos + vec2f(0.0, e), seed) - simplexNoise2(pos - vec2f(0.0, e), seed);
+
+    return vec2f(dy, -dx) / (2.0 * e);
+}
+
+// Divergence free flow, the curl of three simplex noise fields with consecutive seeds
+fn curlNoise3(pos: vec3f, seed: u32) -> vec3f {
+    let e = 0.01;
+    let dx = curlPotential3(pos + vec3f(e, 0.0, 0.0), seed) - curlPotential3(pos - vec3f(e, 0.0, 0.0), seed);
+    let dy = curlPotential3(pos + vec3f(0.0, e, 0.0), seed) - curlPotential3(pos - vec3f(0.0, e, 0.0), seed);
+    let dz = curlPotential3(pos + vec3f(0.0, 0.0, e), seed) - curlPotential3(pos - vec3f(0.0, 0.0, e), seed);
+
+    return vec3f(dy.z - dz.y, dz.x - dx.z, dx.y - dy.x) / (2.0 * e);
+}
+
+fn curlPotential3(pos: vec3f, seed: u32) -> vec3f {
+    return vec3f(simplexNoise3(pos, seed), simplexNoise3(pos, seed + 1u), simplexNoise3(pos, seed + 2u));
+}
+
+fn basisNoise2(basis: i32, pos: vec2f, seed: u32) -> f32 {
+    switch basis {
+        case BASIS_VALUE: {
+            return valueNoise2(pos, seed);
+        }
+        case BASIS_SIMPLEX: {
+            return simplexNoise2(pos, seed);
+        }
+        default: {
+            return perlinNoise2(pos, seed);
+        }
+    }
+}
+
+fn basisNoise3(basis: i32, pos: vec3f, seed: u32) -> f32 {
+    switch basis {
+        case BASIS_VALUE: {
+            return valueNoise3(pos, seed);
+        }
+        case BASIS_SIMPLEX: {
+            return simplexNoise3(pos, seed);
+        }
+        default: {
+            return perlinNoise3(pos, seed);
+        }
+    }
+}
+
+// Dot product of `dist` with one of 8 gradients picked by the hash
+fn gradient2(hash: u32, dist: vec2f) -> f32 {
+    let h = hash & 7u;
+    let u = select(dist.y, dist.x, h < 4u);
+    let v = select(dist.x, dist.y, h < 4u);
+
+    return select(-u, u, (h & 1u) == 0u) + select(-2.0 * v, 2.0 * v, (h & 2u) == 0u);
+}
+
+// Dot product of `dist` with one of the 12 cube edge directions picked by the hash
+fn gradient3(hash: u32, dist: vec3f) -> f32 {
+    let h = hash & 15u;
+    let u = select(dist.y, dist.x, h < 8u);
+    let v = select(select(dist.z, dist.x, h == 12u || h == 14u), dist.y, h < 4u);
+
+    return select(-u, u, (h & 1u) == 0u) + select(-v, v, (h & 2u) == 0u);
+}
+
+// Dot product of `dist` with one of 32 gradients picked by the hash
+fn gradient4(hash: u32, dist: vec4f) -> f32 {
+    let h = hash & 31u;
+    let u = select(dist.y, dist.x, h < 24u);
+    let v = select(dist.z, dist.y, h < 16u);
+    let w = select(dist.w, dist.z, h < 8u);
+
+    return select(-u, u, (h & 1u) == 0u) + select(-v, v, (h & 2u) == 0u) + select(-w, w, (h & 4u) == 0u);
+}
+
+fn simplexCorner(falloff: f32, gradient: f32) -> f32 {
+    let t = max(falloff, 0.0);
+    let t2 = t * t;
+
+    return t2 * t2 * gradient;
+}
+
+// Position of a cell's feature point, in range 0 to 1
+fn featurePoint2(hash: u32) -> vec2f {
+    return vec2f(f32(hash & 0xffffu), f32(hash >> 16u)) / 65536.0;
+}
+
+fn cubeCorner(i: i32) -> vec3i {
+    return vec3i(i & 1, (i >> 1u) & 1, (i >> 2u) & 1);
+}
+
+// Trilinear interpolation of the 8 corner values, indexed like `cubeCorner`
+fn interpolate3(corners: array<f32, 8>, k: vec3f) -> f32 {
+    var values = corners;
+    for(var i = 0; i < 4; i += 1) {
+        values[i] = mix(values[i * 2], values[i * 2 + 1], k.x);
+    }
+    for(var i = 0; i < 2; i += 1) {
+        values[i] = mix(values[i * 2], values[i * 2 + 1], k.y);
+    }
+
+    return mix(values[0], values[1], k.z);
+}
+
+fn quinticFade2(t: vec2f) -> vec2f {
+    return t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+}
+
+fn quinticFade3(t: vec3f) -> vec3f {
+    return t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+}
+
+fn quinticFade4(t: vec4f) -> vec4f {
+    return t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+}
+
+// Uniform in range -1 to 1
+fn hashToSigned(hash: u32) -> f32 {
+    return f32(hash >> 8u) * (2.0 / 16777216.0) - 1.0;
+}
+
+fn hash2(cell: vec2i, seed: u32) -> u32 {
+    return hashU32(bitcast<u32>(cell.x) ^ hashU32(bitcast<u32>(cell.y) ^ hashU32(seed)));
+}
+
+fn hash3(cell: vec3i, seed: u32) -> u32 {
+    return hashU32(bitcast<u32>(cell.x) ^ hash2(cell.yz, seed));
+}
+
+fn hash4(cell: vec4i, seed: u32) -> u32 {
+    return hashU32(bitcast<u32>(cell.x) ^ hash3(cell.yzw, seed));
+}
+
+// lowbias32 by Chris Wellons
+fn hashU32(value: u32) -> u32 {
+    var x = value;
+    x ^= x >> 16u;
+    x *= 0x7feb352du;
+    x ^= x >> 15u;
+    x *= 0x846ca68bu;
+    x ^= x >> 16u;
+
+    return x;
+}
@@ -0,0 +1,18 @@
+// Palettes shared by the steps, included with `#include "palette.wgsl"`
+//
+// `palette` reads the lookup table of the palette selected at runtime, the including shader
+// declares it as `palette_lut: texture_2d<f32>` and `palette_sampler: sampler`
+
+const PALETTE_LUT_SIZE: f32 = 256.0;
+
+// Values outside of 0 to 1 get the color of the closest end
+fn palette(t: f32) -> vec3f {
+    // The ends of the range fall on the centers of the first and the last texel
+    let u = (saturate(t) * (PALETTE_LUT_SIZE - 1.0) + 0.5) / PALETTE_LUT_SIZE;
+    return textureSampleLevel(palette_lut, palette_sampler, vec2f(u, 0.5), 0.0).rgb;
+}
+
+// The formula of the cosine presets, see https://iquilezles.org/articles/palettes/
+fn cosinePalette(t: f32, offset: vec3f, amp: vec3f, freq: vec3f, phase: vec3f) -> vec3f {
+    return offset + amp * cos(6.2831855 * (freq * t + phase));
+}